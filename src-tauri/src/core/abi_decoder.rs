@@ -0,0 +1,146 @@
+use ethers::abi::{Abi, Function, ParamType, RawLog, Token};
+use ethers::types::{H256, I256};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use crate::types::{DecodedCall, DecodedEvent, DecodedParam};
+
+/// Canonical `name(type1,type2)` signature of a function
+pub fn function_signature(function: &Function) -> String {
+    let inputs: Vec<String> = function.inputs.iter().map(|p| p.kind.to_string()).collect();
+    format!("{}({})", function.name, inputs.join(","))
+}
+
+/// Find the function in the ABI whose selector matches the first 4 bytes of input
+pub fn find_function<'a>(abi: &'a Abi, input: &[u8]) -> Option<&'a Function> {
+    if input.len() < 4 {
+        return None;
+    }
+    abi.functions().find(|f| f.short_signature() == input[..4])
+}
+
+/// Decode calldata against an ABI
+pub fn decode_call(abi: &Abi, input: &[u8]) -> Option<DecodedCall> {
+    decode_function_call(find_function(abi, input)?, input)
+}
+
+/// Decode calldata (selector included) against a known function
+pub fn decode_function_call(function: &Function, input: &[u8]) -> Option<DecodedCall> {
+    if input.len() < 4 || input[..4] != function.short_signature() {
+        return None;
+    }
+    let tokens = function.decode_input(&input[4..]).ok()?;
+
+    Some(DecodedCall {
+        name: function.name.clone(),
+        signature: function_signature(function),
+        selector: format!("0x{}", hex::encode(function.short_signature())),
+        args: decode_params(function, &tokens),
+        virtual_calls: Vec::new(),
+    })
+}
+
+/// Pair decoded input tokens with the function's parameter names and types
+pub fn decode_params(function: &Function, tokens: &[Token]) -> Vec<DecodedParam> {
+    function.inputs.iter().zip(tokens.iter()).enumerate().map(|(idx, (param, token))| {
+        DecodedParam {
+            name: if param.name.is_empty() { format!("arg{}", idx) } else { param.name.clone() },
+            type_: param.kind.to_string(),
+            value: token_to_json(token),
+        }
+    }).collect()
+}
+
+/// Decode the return data of a call against the function matched by its input
+pub fn decode_output(abi: &Abi, input: &[u8], output: &[u8]) -> Option<Value> {
+    let function = find_function(abi, input)?;
+    if function.outputs.is_empty() {
+        return None;
+    }
+    let tokens = function.decode_output(output).ok()?;
+
+    let values: Vec<Value> = function.outputs.iter().zip(tokens.iter()).enumerate().map(|(idx, (param, token))| {
+        json!({
+            "name": if param.name.is_empty() { format!("ret{}", idx) } else { param.name.clone() },
+            "type": param.kind.to_string(),
+            "value": token_to_json(token),
+        })
+    }).collect();
+
+    Some(Value::Array(values))
+}
+
+/// Decode an event log against an ABI
+pub fn decode_log(abi: &Abi, topics: &[H256], data: &[u8]) -> Option<DecodedEvent> {
+    let topic0 = topics.first()?;
+    let event = abi.events().find(|e| !e.anonymous && e.signature() == *topic0)?;
+    let parsed = event.parse_log(RawLog { topics: topics.to_vec(), data: data.to_vec() }).ok()?;
+
+    let args: HashMap<String, Value> = parsed.params.iter()
+        .map(|p| (p.name.clone(), token_to_json(&p.value)))
+        .collect();
+
+    let inputs: Vec<String> = event.inputs.iter().map(|p| p.kind.to_string()).collect();
+    Some(DecodedEvent {
+        name: format!("{}({})", event.name, inputs.join(",")),
+        args,
+    })
+}
+
+/// Selector of `Error(string)`, used by `require` and `revert("...")`
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of `Panic(uint256)`, used by failed asserts and checked arithmetic
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Decode `Error(string)` and `Panic(uint256)` revert data
+pub fn decode_builtin_revert(output: &[u8]) -> Option<String> {
+    let (selector, data) = (output.get(..4)?, &output[4..]);
+    if selector == ERROR_SELECTOR {
+        let tokens = ethers::abi::decode(&[ParamType::String], data).ok()?;
+        return tokens.into_iter().next()?.into_string();
+    }
+    if selector == PANIC_SELECTOR {
+        let code = ethers::abi::decode(&[ParamType::Uint(256)], data).ok()?.into_iter().next()?.into_uint()?;
+        let description = match code.low_u64() {
+            0x00 => "generic compiler panic",
+            0x01 => "assertion failed",
+            0x11 => "arithmetic overflow or underflow",
+            0x12 => "division or modulo by zero",
+            0x21 => "invalid enum value",
+            0x22 => "invalid storage byte array encoding",
+            0x31 => "pop on empty array",
+            0x32 => "array index out of bounds",
+            0x41 => "out of memory",
+            0x51 => "call to zero-initialized function",
+            _ => "unknown panic code",
+        };
+        return Some(format!("Panic(0x{:02x}): {}", code, description));
+    }
+    None
+}
+
+/// Decode revert data against the custom errors of an ABI, e.g. `InsufficientBalance(100, 40)`
+pub fn decode_custom_error(abi: &Abi, output: &[u8]) -> Option<String> {
+    let selector = output.get(..4)?;
+    let error = abi.errors().find(|e| e.signature()[..4] == *selector)?;
+    let tokens = error.decode(&output[4..]).ok()?;
+    let args: Vec<String> = tokens.iter().map(|token| match token_to_json(token) {
+        Value::String(value) => value,
+        value => value.to_string(),
+    }).collect();
+    Some(format!("{}({})", error.name, args.join(", ")))
+}
+
+/// Convert an ABI token into a JSON value suitable for the UI
+pub fn token_to_json(token: &Token) -> Value {
+    match token {
+        Token::Address(addr) => json!(format!("{:?}", addr)),
+        Token::FixedBytes(bytes) | Token::Bytes(bytes) => json!(format!("0x{}", hex::encode(bytes))),
+        Token::Int(value) => json!(I256::from_raw(*value).to_string()),
+        Token::Uint(value) => json!(value.to_string()),
+        Token::Bool(value) => json!(value),
+        Token::String(value) => json!(value),
+        Token::FixedArray(tokens) | Token::Array(tokens) | Token::Tuple(tokens) => {
+            Value::Array(tokens.iter().map(token_to_json).collect())
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use ethers::providers::{Http, Provider};
+use ethers::types::transaction::eip2930::AccessList;
+use ethers::types::{Transaction, U256};
+use serde_json::{json, Value};
+use super::call_tree::trace_call_request;
+
+/// Suggested access list and the gas the transaction uses with and without it
+#[derive(Debug, Clone)]
+pub struct AccessListEstimate {
+    pub access_list: AccessList,
+    pub gas_without: u64,
+    pub gas_with: u64,
+}
+
+impl AccessListEstimate {
+    /// Re-simulate the transaction without an access list and with the suggested one
+    pub async fn simulate(provider: &Provider<Http>, tx: &Transaction, access_list: AccessList) -> anyhow::Result<Self> {
+        let mut without = tx.clone();
+        without.access_list = None;
+        let mut with = tx.clone();
+        with.access_list = Some(access_list.clone());
+
+        Ok(Self {
+            gas_without: simulate_gas(provider, &without).await?,
+            gas_with: simulate_gas(provider, &with).await?,
+            access_list,
+        })
+    }
+
+    /// Gas saved by the access list; negative when it costs more than it saves
+    pub fn savings(&self) -> i64 {
+        self.gas_without as i64 - self.gas_with as i64
+    }
+
+    pub fn to_json(&self, current: Option<&AccessList>) -> Value {
+        json!({
+            "accessList": self.access_list,
+            // Ready to paste into a transaction request
+            "json": serde_json::to_string_pretty(&self.access_list).unwrap_or_default(),
+            "addresses": self.access_list.0.len(),
+            "storageKeys": self.access_list.0.iter().map(|item| item.storage_keys.len()).sum::<usize>(),
+            "gasWithout": self.gas_without,
+            "gasWith": self.gas_with,
+            "savings": self.savings(),
+            "worthwhile": self.savings() > 0,
+            "currentAccessList": current.filter(|list| !list.0.is_empty()),
+        })
+    }
+}
+
+/// Gas used by the transaction on the fork, intrinsic cost (including the access list) included
+async fn simulate_gas(provider: &Provider<Http>, tx: &Transaction) -> anyhow::Result<u64> {
+    let options = json!({
+        "tracer": "callTracer",
+        "tracerConfig": { "onlyTopCall": true },
+    });
+    let raw: Value = provider
+        .request("debug_traceCall", (trace_call_request(tx), "latest", options))
+        .await
+        .map_err(|e| anyhow::anyhow!("debug_traceCall failed: {}", e))?;
+
+    let gas_used: U256 = serde_json::from_value(raw["gasUsed"].clone())
+        .map_err(|e| anyhow::anyhow!("Unexpected callTracer output: {}", e))?;
+    Ok(gas_used.low_u64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::transaction::eip2930::AccessListItem;
+    use ethers::types::{Address, H256};
+
+    fn estimate(gas_without: u64, gas_with: u64) -> AccessListEstimate {
+        AccessListEstimate {
+            access_list: AccessList(vec![
+                AccessListItem { address: Address::repeat_byte(1), storage_keys: vec![H256::zero(), H256::repeat_byte(1)] },
+                AccessListItem { address: Address::repeat_byte(2), storage_keys: Vec::new() },
+            ]),
+            gas_without,
+            gas_with,
+        }
+    }
+
+    #[test]
+    fn reports_savings_and_list_size() {
+        let json = estimate(60_000, 57_000).to_json(None);
+        assert_eq!(json["savings"], 3000);
+        assert_eq!(json["worthwhile"], true);
+        assert_eq!((json["addresses"].as_u64(), json["storageKeys"].as_u64()), (Some(2), Some(2)));
+        assert!(json["currentAccessList"].is_null());
+    }
+
+    #[test]
+    fn reports_lists_costing_more_than_they_save() {
+        let estimate = estimate(50_000, 50_200);
+        assert_eq!(estimate.savings(), -200);
+        let json = estimate.to_json(Some(&AccessList(Vec::new())));
+        assert_eq!(json["worthwhile"], false);
+        // An empty current list is treated as none
+        assert!(json["currentAccessList"].is_null());
+    }
+}
@@ -0,0 +1,439 @@
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::types::Address;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use crate::types::{AddressLabel, LabelSource};
+use super::contract_registry::ContractRegistry;
+use super::csv;
+
+/// Labels shipped with the app
+const KNOWN_LABELS: &str = include_str!("known_labels.json");
+
+/// Upper bound on ENS reverse lookups per trace
+const MAX_ENS_LOOKUPS: usize = 25;
+
+/// Column order of exported CSV files
+const CSV_HEADER: [&str; 5] = ["chainId", "address", "label", "tags", "color"];
+
+/// Import/export formats of the address book
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressBookFormat {
+    Json,
+    Csv,
+}
+
+impl AddressBookFormat {
+    pub fn parse(format: &str) -> anyhow::Result<Self> {
+        match format.to_lowercase().as_str() {
+            "json" => Ok(AddressBookFormat::Json),
+            "csv" => Ok(AddressBookFormat::Csv),
+            other => Err(anyhow::anyhow!("Unsupported address book format: {}", other)),
+        }
+    }
+}
+
+/// User-defined address labels, persisted as JSON
+#[derive(Debug, Clone, Default)]
+pub struct AddressBook {
+    path: Option<PathBuf>,
+    labels: Vec<AddressLabel>,
+    /// Why the file on disk could not be loaded; the book is then never saved over it
+    load_error: Option<String>,
+}
+
+impl AddressBook {
+    /// Load the address book from disk; a missing file gives an empty book
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let labels = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("Invalid address book {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(anyhow::anyhow!("Failed to read address book {}: {}", path.display(), e)),
+        };
+        Ok(Self { path: Some(path), labels, load_error: None })
+    }
+
+    /// Empty book standing in for one that failed to load, refusing every change so the
+    /// file on disk is not overwritten
+    pub fn unavailable(path: PathBuf, error: String) -> Self {
+        Self { path: Some(path), labels: Vec::new(), load_error: Some(error) }
+    }
+
+    fn check_loaded(&self) -> anyhow::Result<()> {
+        match &self.load_error {
+            Some(error) => Err(anyhow::anyhow!("The address book could not be loaded, fix or remove the file first: {}", error)),
+            None => Ok(()),
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        self.check_loaded()?;
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, serde_json::to_string_pretty(&self.labels)?)?;
+        }
+        Ok(())
+    }
+
+    pub fn labels(&self) -> &[AddressLabel] {
+        &self.labels
+    }
+
+    /// Add or replace the label of an address on a chain
+    pub fn set(&mut self, label: AddressLabel) -> anyhow::Result<()> {
+        self.check_loaded()?;
+        let label = normalize_label(label)?;
+        self.labels.retain(|l| !(l.address == label.address && l.chain_id == label.chain_id));
+        self.labels.push(label);
+        self.save()
+    }
+
+    pub fn remove(&mut self, address: &str, chain_id: Option<u64>) -> anyhow::Result<()> {
+        self.check_loaded()?;
+        let address: Address = address.parse()?;
+        let address = format!("{:?}", address);
+        self.labels.retain(|l| !(l.address == address && l.chain_id == chain_id));
+        self.save()
+    }
+
+    /// Import labels, replacing existing entries for the same address and chain
+    /// Nothing is imported unless every row is valid; returns the number of labels imported
+    pub fn import(&mut self, content: &str, format: AddressBookFormat) -> anyhow::Result<usize> {
+        self.check_loaded()?;
+        let labels = match format {
+            AddressBookFormat::Json => serde_json::from_str::<Vec<AddressLabel>>(content)?,
+            AddressBookFormat::Csv => parse_csv(content)?,
+        };
+        let labels = labels.into_iter().map(normalize_label).collect::<anyhow::Result<Vec<_>>>()?;
+        let count = labels.len();
+        for label in labels {
+            self.labels.retain(|l| !(l.address == label.address && l.chain_id == label.chain_id));
+            self.labels.push(label);
+        }
+        self.save()?;
+        Ok(count)
+    }
+
+    pub fn export(&self, format: AddressBookFormat) -> anyhow::Result<String> {
+        match format {
+            AddressBookFormat::Json => Ok(serde_json::to_string_pretty(&self.labels)?),
+            AddressBookFormat::Csv => {
+                let mut out = csv::format_row(&CSV_HEADER);
+                out.push('\n');
+                for label in &self.labels {
+                    out.push_str(&csv::format_row(&[
+                        label.chain_id.map(|c| c.to_string()).unwrap_or_default(),
+                        label.address.clone(),
+                        label.label.clone(),
+                        label.tags.join(";"),
+                        label.color.clone().unwrap_or_default(),
+                    ]));
+                    out.push('\n');
+                }
+                Ok(out)
+            }
+        }
+    }
+
+    /// Labels applying to a chain: user labels override the bundled ones
+    pub fn resolve(&self, chain_id: u64) -> AddressLabels {
+        let mut resolved = AddressLabels::default();
+        let applies = |label: &AddressLabel| label.chain_id.map(|c| c == chain_id).unwrap_or(true);
+
+        for label in bundled_labels().into_iter().chain(self.labels.iter().cloned()) {
+            if applies(&label) {
+                resolved.insert(label);
+            }
+        }
+        resolved
+    }
+}
+
+/// Check the address and store it in the lowercase form used throughout the trace
+fn normalize_label(mut label: AddressLabel) -> anyhow::Result<AddressLabel> {
+    let address: Address = label.address.trim().parse()
+        .map_err(|_| anyhow::anyhow!("Invalid address: {}", label.address))?;
+    label.address = format!("{:?}", address);
+    label.label = label.label.trim().to_string();
+    if label.label.is_empty() {
+        return Err(anyhow::anyhow!("Empty label for {}", label.address));
+    }
+    label.source = LabelSource::User;
+    Ok(label)
+}
+
+fn parse_csv(content: &str) -> anyhow::Result<Vec<AddressLabel>> {
+    let rows = csv::parse(content);
+    let Some((header, records)) = rows.split_first() else {
+        return Ok(Vec::new());
+    };
+    let column = |name: &str| header.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    let address_col = column("address").ok_or_else(|| anyhow::anyhow!("CSV is missing an address column"))?;
+    let label_col = column("label").ok_or_else(|| anyhow::anyhow!("CSV is missing a label column"))?;
+    let chain_col = column("chainId");
+    let tags_col = column("tags");
+    let color_col = column("color");
+
+    let cell = |record: &[String], col: Option<usize>| {
+        col.and_then(|c| record.get(c)).map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+    };
+
+    records.iter().map(|record| {
+        Ok(AddressLabel {
+            address: cell(record, Some(address_col)).unwrap_or_default(),
+            label: cell(record, Some(label_col)).unwrap_or_default(),
+            chain_id: match cell(record, chain_col) {
+                Some(chain) => Some(chain.parse().map_err(|_| anyhow::anyhow!("Invalid chain ID: {}", chain))?),
+                None => None,
+            },
+            tags: cell(record, tags_col)
+                .map(|tags| tags.split(';').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect())
+                .unwrap_or_default(),
+            color: cell(record, color_col),
+            source: LabelSource::User,
+        })
+    }).collect()
+}
+
+fn bundled_labels() -> Vec<AddressLabel> {
+    let mut labels: Vec<AddressLabel> = serde_json::from_str(KNOWN_LABELS).unwrap_or_default();
+    for label in labels.iter_mut() {
+        if let Ok(address) = label.address.parse::<Address>() {
+            label.address = format!("{:?}", address);
+        }
+        label.source = LabelSource::Bundled;
+    }
+    labels
+}
+
+/// Labels resolved for one trace
+#[derive(Debug, Clone, Default)]
+pub struct AddressLabels {
+    labels: HashMap<Address, AddressLabel>,
+}
+
+impl AddressLabels {
+    /// Insert a label unless one with higher precedence is already known
+    pub fn insert(&mut self, label: AddressLabel) {
+        let Ok(address) = label.address.parse::<Address>() else { return };
+        match self.labels.get(&address) {
+            Some(existing) if existing.source < label.source => {}
+            _ => {
+                self.labels.insert(address, label);
+            }
+        }
+    }
+
+    pub fn get(&self, address: &Address) -> Option<&AddressLabel> {
+        self.labels.get(address)
+    }
+
+    /// Label text for an address
+    pub fn label(&self, address: &Address) -> Option<&str> {
+        self.labels.get(address).map(|l| l.label.as_str())
+    }
+
+    /// Use registered contract names for addresses without a better label
+    pub fn add_contract_names(&mut self, registry: &ContractRegistry) {
+        for info in registry.contracts() {
+            if let Some(name) = &info.name {
+                self.insert(AddressLabel {
+                    address: info.address.clone(),
+                    label: name.clone(),
+                    chain_id: None,
+                    tags: vec!["contract".to_string()],
+                    color: None,
+                    source: LabelSource::Contract,
+                });
+            }
+        }
+    }
+
+    /// Reverse-resolve ENS names at the forked block for addresses without a label
+    pub async fn add_ens_names(&mut self, provider: &Provider<Http>, addresses: &BTreeSet<Address>) {
+        let unlabeled: Vec<Address> = addresses.iter()
+            .filter(|a| !self.labels.contains_key(a))
+            .take(MAX_ENS_LOOKUPS)
+            .copied()
+            .collect();
+
+        for address in unlabeled {
+            let lookup = tokio::time::timeout(Duration::from_secs(5), provider.lookup_address(address)).await;
+            if let Ok(Ok(name)) = lookup {
+                self.insert(AddressLabel {
+                    address: format!("{:?}", address),
+                    label: name,
+                    chain_id: None,
+                    tags: vec!["ens".to_string()],
+                    color: None,
+                    source: LabelSource::Ens,
+                });
+            }
+        }
+    }
+
+    /// Attach labels to the trace: every string field holding a labeled address gets a
+    /// sibling `<field>Label`, every array holding labeled addresses a sibling `<field>Labels`
+    /// of the same shape (null where there is no label), and all labels are listed under
+    /// `addressLabels`
+    pub fn apply(&self, trace: &mut Value) {
+        let mut used = BTreeSet::new();
+        self.label_fields(trace, &mut used);
+
+        if let Some(obj) = trace.as_object_mut() {
+            let all: Map<String, Value> = used.iter()
+                .filter_map(|a| self.labels.get(a))
+                .map(|l| (l.address.clone(), json!(l)))
+                .collect();
+            obj.insert("addressLabels".to_string(), Value::Object(all));
+        }
+    }
+
+    fn label_fields(&self, value: &mut Value, used: &mut BTreeSet<Address>) {
+        match value {
+            Value::Object(obj) => {
+                let mut labels = Vec::new();
+                for (key, field) in obj.iter_mut() {
+                    let address = field.as_str().and_then(parse_address);
+                    match address.and_then(|a| self.label(&a).map(|l| (a, l))) {
+                        Some((address, label)) => {
+                            used.insert(address);
+                            labels.push((format!("{}Label", key), Value::String(label.to_string())));
+                        }
+                        None => {
+                            if let Some(array_labels) = self.array_labels(field, used) {
+                                labels.push((format!("{}Labels", key), array_labels));
+                            }
+                            self.label_fields(field, used);
+                        }
+                    }
+                }
+                for (key, label) in labels {
+                    obj.entry(key).or_insert(label);
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.label_fields(item, used)),
+            _ => {}
+        }
+    }
+
+    /// Labels of the addresses in a (possibly nested) array, mirroring its shape,
+    /// or `None` when no element has a label
+    fn array_labels(&self, value: &Value, used: &mut BTreeSet<Address>) -> Option<Value> {
+        let items = value.as_array()?;
+        let mut found = false;
+        let labels = items.iter().map(|item| match item {
+            Value::String(text) => match parse_address(text).and_then(|a| self.label(&a).map(|l| (a, l))) {
+                Some((address, label)) => {
+                    used.insert(address);
+                    found = true;
+                    Value::String(label.to_string())
+                }
+                None => Value::Null,
+            },
+            Value::Array(_) => self.array_labels(item, used).inspect(|_| found = true).unwrap_or(Value::Null),
+            _ => Value::Null,
+        }).collect();
+        found.then_some(Value::Array(labels))
+    }
+}
+
+/// Parse a `0x`-prefixed 20-byte hex string
+fn parse_address(value: &str) -> Option<Address> {
+    if value.len() != 42 || !value.starts_with("0x") {
+        return None;
+    }
+    value.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "0x000000000000000000000000000000000000000a";
+    const B: &str = "0x000000000000000000000000000000000000000b";
+
+    fn label(address: &str, text: &str, chain_id: Option<u64>) -> AddressLabel {
+        AddressLabel {
+            address: address.to_string(),
+            label: text.to_string(),
+            chain_id,
+            tags: Vec::new(),
+            color: None,
+            source: LabelSource::User,
+        }
+    }
+
+    fn book() -> AddressBook {
+        let mut book = AddressBook::default();
+        let mut treasury = label(A, "Treasury, \"main\"", Some(1));
+        treasury.tags = vec!["team".to_string(), "multisig".to_string()];
+        treasury.color = Some("#ff0000".to_string());
+        book.set(treasury).unwrap();
+        book.set(label(B, "=HYPERLINK(\"x\")", None)).unwrap();
+        book
+    }
+
+    fn summary(book: &AddressBook) -> Value {
+        json!(book.labels())
+    }
+
+    #[test]
+    fn round_trips_imports_and_exports() {
+        let original = book();
+        for format in [AddressBookFormat::Json, AddressBookFormat::Csv] {
+            let exported = original.export(format).unwrap();
+            let mut imported = AddressBook::default();
+            assert_eq!(imported.import(&exported, format).unwrap(), 2);
+            assert_eq!(summary(&imported), summary(&original), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn imports_all_rows_or_none() {
+        let mut book = book();
+        let csv = format!("address,label,chainId\n{},Replaced,1\nnot-an-address,Broken,\n", A);
+        assert!(book.import(&csv, AddressBookFormat::Csv).is_err());
+        assert_eq!(summary(&book), summary(&self::book()));
+
+        let csv = format!("Label,Address,chainId\nReplaced,{},1\n", A);
+        assert_eq!(book.import(&csv, AddressBookFormat::Csv).unwrap(), 1);
+        assert_eq!(book.labels().iter().map(|l| l.label.as_str()).collect::<Vec<_>>(), vec!["=HYPERLINK(\"x\")", "Replaced"]);
+    }
+
+    #[test]
+    fn refuses_changes_to_unavailable_book() {
+        let mut book = AddressBook::unavailable(PathBuf::from("labels.json"), "bad JSON".to_string());
+        assert!(book.set(label(A, "Treasury", None)).is_err());
+        assert!(book.import("[]", AddressBookFormat::Json).is_err());
+        assert!(book.labels().is_empty());
+    }
+
+    #[test]
+    fn labels_addresses_in_fields_and_arrays() {
+        let mut labels = AddressLabels::default();
+        labels.insert(label(A, "Treasury", None));
+        let mut contract = label(A, "Vault", None);
+        contract.source = LabelSource::Contract;
+        labels.insert(contract);
+
+        let mut trace = json!({
+            "from": A,
+            "path": [B, A],
+            "routes": [[A], [B]],
+            "amounts": ["1", "2"],
+        });
+        labels.apply(&mut trace);
+
+        assert_eq!(trace["fromLabel"], "Treasury");
+        assert_eq!(trace["pathLabels"], json!([null, "Treasury"]));
+        assert_eq!(trace["routesLabels"], json!([["Treasury"], null]));
+        assert!(trace.get("amountsLabels").is_none());
+        assert_eq!(trace["addressLabels"].as_object().unwrap().len(), 1);
+    }
+}
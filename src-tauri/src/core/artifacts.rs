@@ -0,0 +1,627 @@
+use ethers::types::{Address, Bytes};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::types::{AbiEntry, ContractInfo};
+use super::contract_registry::ContractRegistry;
+use super::source_map::{ContractSourceMap, SourceFile, SourceTable};
+use super::storage_layout::StorageLayout;
+
+/// Byte range (offset, length) within a bytecode
+pub type ByteRange = (usize, usize);
+
+/// Build system that produced a set of artifacts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtifactFormat {
+    Foundry,
+    Hardhat,
+}
+
+/// A compiled contract loaded from a Foundry or Hardhat artifact
+#[derive(Debug, Clone)]
+pub struct CompiledContract {
+    pub name: String,
+    pub source_path: String,
+    pub artifact_path: PathBuf,
+    pub abi: Option<Vec<AbiEntry>>,
+    pub bytecode: Vec<u8>,
+    pub deployed_bytecode: Vec<u8>,
+    pub source_map: Option<String>,
+    pub deployed_source_map: Option<String>,
+    pub storage_layout: Option<Value>,
+    /// Byte ranges of immutables and library links in the deployed bytecode
+    pub deployed_placeholders: Vec<ByteRange>,
+    /// Whether `deployed_placeholders` lists the immutables reported by the compiler
+    pub has_immutable_references: bool,
+    /// Sources of the compilation the contract belongs to, from build-info
+    pub sources: Option<Arc<SourceTable>>,
+}
+
+impl CompiledContract {
+    pub fn to_contract_info(&self, address: Address) -> ContractInfo {
+        ContractInfo {
+            address: format!("{:?}", address),
+            abi: self.abi.clone(),
+            name: Some(self.name.clone()),
+            verified: None,
+            source: None,
+        }
+    }
+
+    /// Source map of the deployed bytecode, if both the map and the sources are available
+    pub fn runtime_source_map(&self) -> Option<ContractSourceMap> {
+        let map = self.deployed_source_map.as_deref()?;
+        let sources = self.sources.clone()?;
+        Some(ContractSourceMap::new(&self.deployed_bytecode, map, sources))
+    }
+
+    /// Check whether on-chain runtime code was produced by this contract,
+    /// ignoring the metadata hash, immutables and linked library addresses
+    pub fn matches_runtime_code(&self, code: &[u8]) -> bool {
+        let expected = strip_metadata(&self.deployed_bytecode);
+        let actual = strip_metadata(code);
+        if expected.is_empty() || expected.len() != actual.len() {
+            return false;
+        }
+
+        let mut ignored = vec![false; expected.len()];
+        let mut placeholders = self.deployed_placeholders.clone();
+        if !self.has_immutable_references {
+            placeholders.extend(zero_push32_ranges(expected));
+        }
+        // Libraries embed their own address at deploy time (PUSH20 0x00..00 at offset 0)
+        if expected.len() > 21 && expected[0] == 0x73 && expected[1..21].iter().all(|b| *b == 0) {
+            placeholders.push((1, 20));
+        }
+        for (start, length) in placeholders {
+            for flag in ignored.iter_mut().skip(start).take(length) {
+                *flag = true;
+            }
+        }
+
+        expected.iter().zip(actual.iter()).zip(ignored.iter())
+            .all(|((a, b), skip)| *skip || a == b)
+    }
+}
+
+/// Artifacts of a linked Foundry or Hardhat project
+#[derive(Debug, Clone)]
+pub struct ProjectArtifacts {
+    /// Directory holding the artifacts
+    pub root: PathBuf,
+    /// Project directory that source paths are relative to
+    pub project_root: PathBuf,
+    pub format: ArtifactFormat,
+    pub contracts: Vec<CompiledContract>,
+    /// Contract indices grouped by deployed bytecode length (metadata stripped)
+    by_code_length: HashMap<usize, Vec<usize>>,
+}
+
+/// Short description of a linked project returned to the UI
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectSummary {
+    pub root: String,
+    pub format: ArtifactFormat,
+    pub contract_count: usize,
+    pub contracts: Vec<String>,
+}
+
+impl ProjectArtifacts {
+    /// Scan a project root, a Foundry `out/` directory or a Hardhat `artifacts/` directory
+    pub fn scan(path: &Path) -> anyhow::Result<Self> {
+        let (dir, format) = resolve_artifacts_dir(path)?;
+        println!("[ARTIFACTS] Scanning {:?} artifacts in {:?}", format, dir);
+
+        let mut files = Vec::new();
+        collect_json_files(&dir, &mut files)?;
+
+        let mut contracts = Vec::new();
+        for file in files {
+            let parsed = fs::read_to_string(&file)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| serde_json::from_str::<Value>(&content).map_err(anyhow::Error::from));
+            let json = match parsed {
+                Ok(json) => json,
+                Err(e) => {
+                    println!("[ARTIFACTS] Skipping {:?}: {}", file, e);
+                    continue;
+                }
+            };
+            let contract = match format {
+                ArtifactFormat::Foundry => parse_foundry_artifact(&file, &json),
+                ArtifactFormat::Hardhat => parse_hardhat_artifact(&file, &json),
+            };
+            if let Some(contract) = contract {
+                contracts.push(contract);
+            }
+        }
+
+        println!("[ARTIFACTS] Loaded {} deployable contracts", contracts.len());
+
+        let project_root = dir.parent().map(|p| p.to_path_buf()).unwrap_or_else(|| dir.clone());
+        // Source indices are only meaningful within one compilation, which only build-info records
+        if !attach_build_info(&dir, &project_root, &mut contracts) {
+            println!("[ARTIFACTS] No build info in {:?}, source locations are unavailable (run `forge build --build-info`)", dir);
+        }
+
+        let mut by_code_length: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (idx, contract) in contracts.iter().enumerate() {
+            by_code_length.entry(strip_metadata(&contract.deployed_bytecode).len()).or_default().push(idx);
+        }
+
+        Ok(Self { root: dir, project_root, format, contracts, by_code_length })
+    }
+
+    pub fn summary(&self) -> ProjectSummary {
+        let mut names: Vec<String> = self.contracts.iter().map(|c| c.name.clone()).collect();
+        names.sort();
+        names.dedup();
+        ProjectSummary {
+            root: self.root.display().to_string(),
+            format: self.format,
+            contract_count: self.contracts.len(),
+            contracts: names,
+        }
+    }
+
+    /// Find the compiled contract whose runtime bytecode matches on-chain code
+    pub fn match_runtime_code(&self, code: &[u8]) -> Option<&CompiledContract> {
+        let candidates = self.by_code_length.get(&strip_metadata(code).len())?;
+        candidates.iter()
+            .map(|idx| &self.contracts[*idx])
+            .find(|contract| contract.matches_runtime_code(code))
+    }
+
+    /// Register every address whose code matches an artifact
+    /// Returns the number of contracts registered
+    pub fn register_matches(&self, codes: &HashMap<Address, Bytes>, registry: &mut ContractRegistry) -> usize {
+        let mut matched = 0;
+        for (address, code) in codes {
+            if let Some(contract) = self.match_runtime_code(code) {
+                println!("[ARTIFACTS] {:?} matched {} ({})", address, contract.name, contract.source_path);
+                registry.register(contract.to_contract_info(*address));
+                if let Some(source_map) = contract.runtime_source_map() {
+                    registry.register_source_map(*address, Arc::new(source_map));
+                }
+                if let Some(layout) = contract.storage_layout.as_ref().and_then(StorageLayout::from_value) {
+                    registry.register_storage_layout(*address, Arc::new(layout));
+                }
+                matched += 1;
+            }
+        }
+        matched
+    }
+}
+
+/// Work out which directory holds the artifacts and which tool produced them
+fn resolve_artifacts_dir(path: &Path) -> anyhow::Result<(PathBuf, ArtifactFormat)> {
+    if !path.is_dir() {
+        return Err(anyhow::anyhow!("Project path {:?} is not a directory", path));
+    }
+
+    if path.join("out").is_dir() {
+        return Ok((path.join("out"), ArtifactFormat::Foundry));
+    }
+    if path.join("artifacts").is_dir() {
+        return Ok((path.join("artifacts"), ArtifactFormat::Hardhat));
+    }
+    if path.join("foundry.toml").exists() {
+        return Err(anyhow::anyhow!("Foundry project at {:?} has no out/ directory. Run `forge build` first.", path));
+    }
+    if path.file_name().map(|n| n == "artifacts").unwrap_or(false)
+        || (path.join("build-info").is_dir() && path.join("contracts").is_dir())
+    {
+        return Ok((path.to_path_buf(), ArtifactFormat::Hardhat));
+    }
+    Ok((path.to_path_buf(), ArtifactFormat::Foundry))
+}
+
+/// Recursively collect artifact JSON files, skipping build-info and debug files
+fn collect_json_files(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if path.file_name().map(|n| n == "build-info").unwrap_or(false) {
+                continue;
+            }
+            collect_json_files(&path, files)?;
+        } else if path.extension().map(|e| e == "json").unwrap_or(false)
+            && !path.to_string_lossy().ends_with(".dbg.json")
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn parse_foundry_artifact(file: &Path, json: &Value) -> Option<CompiledContract> {
+    let deployed = json.get("deployedBytecode")?;
+    let (deployed_bytecode, mut deployed_placeholders) = decode_bytecode(deployed.get("object")?.as_str()?)?;
+    if deployed_bytecode.is_empty() {
+        return None;
+    }
+    let (bytecode, _) = json.get("bytecode")
+        .and_then(|b| b.get("object"))
+        .and_then(|o| o.as_str())
+        .and_then(decode_bytecode)
+        .unwrap_or_default();
+
+    let immutables = deployed.get("immutableReferences").and_then(|r| r.as_object());
+    if let Some(references) = immutables {
+        deployed_placeholders.extend(offset_ranges(references.values()));
+    }
+    if let Some(links) = deployed.get("linkReferences").and_then(|r| r.as_object()) {
+        deployed_placeholders.extend(link_ranges(links));
+    }
+
+    // compilationTarget is the most reliable source of the contract and file names
+    let target = json.pointer("/metadata/settings/compilationTarget")
+        .and_then(|t| t.as_object())
+        .and_then(|t| t.iter().next())
+        .map(|(path, name)| (path.clone(), name.as_str().unwrap_or_default().to_string()));
+    let (source_path, name) = match target {
+        Some(target) => target,
+        None => {
+            let name = file.file_stem()?.to_string_lossy().split('.').next()?.to_string();
+            let source = json.pointer("/ast/absolutePath")
+                .and_then(|p| p.as_str())
+                .map(|p| p.to_string())
+                .or_else(|| file.parent().and_then(|p| p.file_name()).map(|n| n.to_string_lossy().to_string()))
+                .unwrap_or_default();
+            (source, name)
+        }
+    };
+
+    Some(CompiledContract {
+        name,
+        source_path,
+        artifact_path: file.to_path_buf(),
+        abi: parse_abi_entries(json.get("abi")),
+        bytecode,
+        deployed_bytecode,
+        source_map: json.pointer("/bytecode/sourceMap").and_then(|s| s.as_str()).map(|s| s.to_string()),
+        deployed_source_map: deployed.get("sourceMap").and_then(|s| s.as_str()).map(|s| s.to_string()),
+        storage_layout: json.get("storageLayout").cloned(),
+        deployed_placeholders,
+        has_immutable_references: immutables.is_some(),
+        sources: None,
+    })
+}
+
+fn parse_hardhat_artifact(file: &Path, json: &Value) -> Option<CompiledContract> {
+    if !json.get("_format")?.as_str()?.starts_with("hh-sol-artifact") {
+        return None;
+    }
+    let (deployed_bytecode, mut deployed_placeholders) = decode_bytecode(json.get("deployedBytecode")?.as_str()?)?;
+    if deployed_bytecode.is_empty() {
+        return None;
+    }
+    let (bytecode, _) = json.get("bytecode")
+        .and_then(|b| b.as_str())
+        .and_then(decode_bytecode)
+        .unwrap_or_default();
+    if let Some(links) = json.get("deployedLinkReferences").and_then(|r| r.as_object()) {
+        deployed_placeholders.extend(link_ranges(links));
+    }
+
+    Some(CompiledContract {
+        name: json.get("contractName")?.as_str()?.to_string(),
+        source_path: json.get("sourceName").and_then(|s| s.as_str()).unwrap_or_default().to_string(),
+        artifact_path: file.to_path_buf(),
+        abi: parse_abi_entries(json.get("abi")),
+        bytecode,
+        deployed_bytecode,
+        source_map: None,
+        deployed_source_map: None,
+        storage_layout: None,
+        deployed_placeholders,
+        has_immutable_references: false,
+        sources: None,
+    })
+}
+
+/// Compiler output for one contract found in a build-info file
+struct BuildInfoContract {
+    deployed_len: usize,
+    deployed_source_map: Option<String>,
+    immutable_ranges: Option<Vec<ByteRange>>,
+    storage_layout: Option<Value>,
+}
+
+/// Load `build-info/*.json` files (written by Hardhat, and by Foundry with `--build-info`)
+/// and attach their sources, source maps, immutables and storage layouts to the contracts
+/// Returns whether any build-info file was loaded
+fn attach_build_info(dir: &Path, project_root: &Path, contracts: &mut [CompiledContract]) -> bool {
+    let build_info_dir = dir.join("build-info");
+    let entries = match fs::read_dir(&build_info_dir) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+
+    let mut loaded = false;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().map(|e| e != "json").unwrap_or(true) {
+            continue;
+        }
+        let json = match fs::read_to_string(&path).ok().and_then(|c| serde_json::from_str::<Value>(&c).ok()) {
+            Some(json) => json,
+            None => {
+                println!("[ARTIFACTS] Skipping unreadable build info {:?}", path);
+                continue;
+            }
+        };
+
+        loaded = true;
+        let sources = Arc::new(build_info_sources(&json, project_root));
+        let compiled = build_info_contracts(&json);
+        println!("[ARTIFACTS] Build info {:?}: {} sources, {} contracts", path.file_name().unwrap_or_default(), sources.files.len(), compiled.len());
+
+        for contract in contracts.iter_mut() {
+            let Some(info) = compiled.get(&(contract.source_path.clone(), contract.name.clone())) else {
+                continue;
+            };
+            // The same contract may be compiled by several solc versions; only
+            // take data from the compilation that produced this bytecode
+            if info.deployed_len != contract.deployed_bytecode.len() || contract.sources.is_some() {
+                continue;
+            }
+            contract.sources = Some(sources.clone());
+            if contract.deployed_source_map.is_none() {
+                contract.deployed_source_map = info.deployed_source_map.clone();
+            }
+            if contract.storage_layout.is_none() {
+                contract.storage_layout = info.storage_layout.clone();
+            }
+            if !contract.has_immutable_references {
+                if let Some(ranges) = &info.immutable_ranges {
+                    contract.deployed_placeholders.extend(ranges.iter().copied());
+                    contract.has_immutable_references = true;
+                }
+            }
+        }
+    }
+    loaded
+}
+
+/// Source files of a build-info keyed by solc source index
+fn build_info_sources(json: &Value, project_root: &Path) -> SourceTable {
+    let mut ids: HashMap<i64, String> = HashMap::new();
+    if let Some(map) = json.get("source_id_to_path").and_then(|m| m.as_object()) {
+        for (id, path) in map {
+            if let (Ok(id), Some(path)) = (id.parse(), path.as_str()) {
+                ids.insert(id, path.to_string());
+            }
+        }
+    }
+    if let Some(sources) = json.pointer("/output/sources").and_then(|s| s.as_object()) {
+        for (path, source) in sources {
+            if let Some(id) = source.get("id").and_then(|id| id.as_i64()) {
+                ids.entry(id).or_insert_with(|| path.clone());
+            }
+        }
+    }
+
+    let mut table = SourceTable::default();
+    for (id, path) in ids {
+        let content = json.pointer("/input/sources")
+            .and_then(|s| s.get(&path))
+            .and_then(|s| s.get("content"))
+            .and_then(|c| c.as_str())
+            .map(|c| c.to_string())
+            .or_else(|| fs::read_to_string(project_root.join(&path)).ok());
+        table.files.insert(id, SourceFile::new(path, content));
+    }
+    table
+}
+
+/// Per-contract compiler output of a build-info keyed by (source path, contract name)
+fn build_info_contracts(json: &Value) -> HashMap<(String, String), BuildInfoContract> {
+    let mut contracts = HashMap::new();
+    let Some(files) = json.pointer("/output/contracts").and_then(|c| c.as_object()) else {
+        return contracts;
+    };
+
+    for (path, file_contracts) in files {
+        let Some(file_contracts) = file_contracts.as_object() else { continue };
+        for (name, output) in file_contracts {
+            let Some(deployed) = output.pointer("/evm/deployedBytecode") else { continue };
+            let deployed_len = deployed.get("object")
+                .and_then(|o| o.as_str())
+                .and_then(decode_bytecode)
+                .map(|(code, _)| code.len())
+                .unwrap_or_default();
+            contracts.insert((path.clone(), name.clone()), BuildInfoContract {
+                deployed_len,
+                deployed_source_map: deployed.get("sourceMap").and_then(|s| s.as_str()).map(|s| s.to_string()),
+                immutable_ranges: deployed.get("immutableReferences")
+                    .and_then(|r| r.as_object())
+                    .map(|r| offset_ranges(r.values())),
+                storage_layout: output.get("storageLayout").cloned(),
+            });
+        }
+    }
+    contracts
+}
+
+fn parse_abi_entries(abi: Option<&Value>) -> Option<Vec<AbiEntry>> {
+    serde_json::from_value(abi?.clone()).ok()
+}
+
+/// Decode a hex bytecode string, zero-filling unlinked library placeholders (`__$...$__`)
+/// Returns the bytes together with the byte ranges of the placeholders
+fn decode_bytecode(object: &str) -> Option<(Vec<u8>, Vec<ByteRange>)> {
+    let hex_str = object.strip_prefix("0x").unwrap_or(object);
+    let mut cleaned = String::with_capacity(hex_str.len());
+    let mut placeholders = Vec::new();
+
+    let mut rest = hex_str;
+    while let Some(pos) = rest.find("__") {
+        cleaned.push_str(&rest[..pos]);
+        // Placeholders are always 40 hex characters (one address) long
+        let end = (pos + 40).min(rest.len());
+        placeholders.push((cleaned.len() / 2, 20));
+        cleaned.push_str(&"0".repeat(end - pos));
+        rest = &rest[end..];
+    }
+    cleaned.push_str(rest);
+
+    hex::decode(&cleaned).ok().map(|bytes| (bytes, placeholders))
+}
+
+/// Convert solc `{ "start": n, "length": m }` references into byte ranges
+fn offset_ranges<'a>(references: impl Iterator<Item = &'a Value>) -> Vec<ByteRange> {
+    references
+        .filter_map(|r| r.as_array())
+        .flatten()
+        .filter_map(|r| Some((r.get("start")?.as_u64()? as usize, r.get("length")?.as_u64()? as usize)))
+        .collect()
+}
+
+/// Byte ranges of library link references (`file -> library -> [offsets]`)
+fn link_ranges(links: &serde_json::Map<String, Value>) -> Vec<ByteRange> {
+    links.values()
+        .filter_map(|libraries| libraries.as_object())
+        .flat_map(|libraries| offset_ranges(libraries.values()))
+        .collect()
+}
+
+/// Strip the CBOR-encoded metadata appended by solc to the runtime bytecode
+pub fn strip_metadata(code: &[u8]) -> &[u8] {
+    if code.len() < 2 {
+        return code;
+    }
+    let metadata_len = u16::from_be_bytes([code[code.len() - 2], code[code.len() - 1]]) as usize;
+    if metadata_len + 2 > code.len() {
+        return code;
+    }
+    let start = code.len() - 2 - metadata_len;
+    // The metadata is a CBOR map with one to three entries
+    match code.get(start) {
+        Some(0xa1..=0xa3) => &code[..start],
+        _ => code,
+    }
+}
+
+/// Ranges of zero-valued PUSH32 immediates, which is how solc emits immutable placeholders
+fn zero_push32_ranges(code: &[u8]) -> Vec<ByteRange> {
+    let mut ranges = Vec::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        let op = code[pc];
+        if (0x60..=0x7f).contains(&op) {
+            let size = (op - 0x5f) as usize;
+            if op == 0x7f && pc + 33 <= code.len() && code[pc + 1..pc + 33].iter().all(|b| *b == 0) {
+                ranges.push((pc + 1, 32));
+            }
+            pc += size;
+        }
+        pc += 1;
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `a1 65 "bzzr0" 58 20 <32 bytes>`, the shape of a solc metadata map
+    fn with_metadata(runtime: &[u8], hash_byte: u8) -> Vec<u8> {
+        let mut metadata = vec![0xa1, 0x65];
+        metadata.extend_from_slice(b"bzzr0");
+        metadata.extend_from_slice(&[0x58, 0x20]);
+        metadata.extend_from_slice(&[hash_byte; 32]);
+        let mut code = runtime.to_vec();
+        code.extend_from_slice(&metadata);
+        code.extend_from_slice(&(metadata.len() as u16).to_be_bytes());
+        code
+    }
+
+    fn contract(deployed_bytecode: Vec<u8>, deployed_placeholders: Vec<ByteRange>, has_immutable_references: bool) -> CompiledContract {
+        CompiledContract {
+            name: "Test".to_string(),
+            source_path: "src/Test.sol".to_string(),
+            artifact_path: PathBuf::new(),
+            abi: None,
+            bytecode: Vec::new(),
+            deployed_bytecode,
+            source_map: None,
+            deployed_source_map: None,
+            storage_layout: None,
+            deployed_placeholders,
+            has_immutable_references,
+            sources: None,
+        }
+    }
+
+    #[test]
+    fn strips_cbor_metadata() {
+        let runtime = [0x60, 0x80, 0x60, 0x40, 0x52, 0x00];
+        assert_eq!(strip_metadata(&with_metadata(&runtime, 0x11)), runtime);
+    }
+
+    #[test]
+    fn keeps_code_without_metadata() {
+        // Last two bytes read as a length pointing at a non-map byte
+        let code = [0x60, 0x01, 0x60, 0x02, 0x00, 0x03];
+        assert_eq!(strip_metadata(&code), code);
+        // Length larger than the code
+        assert_eq!(strip_metadata(&[0x00, 0xff, 0xff]), [0x00, 0xff, 0xff]);
+        assert_eq!(strip_metadata(&[0x00]), [0x00]);
+    }
+
+    #[test]
+    fn matches_ignoring_metadata_hash() {
+        let runtime = [0x60, 0x80, 0x60, 0x40, 0x52, 0x00];
+        let compiled = contract(with_metadata(&runtime, 0x11), Vec::new(), true);
+        assert!(compiled.matches_runtime_code(&with_metadata(&runtime, 0x22)));
+        assert!(!compiled.matches_runtime_code(&with_metadata(&[0x60, 0x80, 0x60, 0x41, 0x52, 0x00], 0x11)));
+        assert!(!compiled.matches_runtime_code(&with_metadata(&runtime[..5], 0x11)));
+    }
+
+    #[test]
+    fn matches_ignoring_reported_immutables() {
+        // PUSH1 0x00 at offset 0..2 holds an immutable, then STOP
+        let compiled = contract(with_metadata(&[0x60, 0x00, 0x00], 0x11), vec![(1, 1)], true);
+        assert!(compiled.matches_runtime_code(&with_metadata(&[0x60, 0x2a, 0x00], 0x11)));
+        assert!(!compiled.matches_runtime_code(&with_metadata(&[0x61, 0x2a, 0x00], 0x11)));
+    }
+
+    #[test]
+    fn matches_zero_push32_without_immutable_references() {
+        let mut expected = vec![0x7f];
+        expected.extend_from_slice(&[0u8; 32]);
+        expected.push(0x00);
+        let mut actual = vec![0x7f];
+        actual.extend_from_slice(&[0xab; 32]);
+        actual.push(0x00);
+
+        assert!(contract(with_metadata(&expected, 0x11), Vec::new(), false).matches_runtime_code(&with_metadata(&actual, 0x11)));
+        // Reported immutables replace the PUSH32 heuristic
+        assert!(!contract(with_metadata(&expected, 0x11), Vec::new(), true).matches_runtime_code(&with_metadata(&actual, 0x11)));
+    }
+
+    #[test]
+    fn matches_library_address_prefix() {
+        let mut expected = vec![0x73];
+        expected.extend_from_slice(&[0u8; 20]);
+        expected.extend_from_slice(&[0x30, 0x14, 0x00]);
+        let mut actual = expected.clone();
+        actual[1..21].copy_from_slice(&[0x5a; 20]);
+        assert!(contract(with_metadata(&expected, 0x11), Vec::new(), true).matches_runtime_code(&with_metadata(&actual, 0x11)));
+    }
+
+    #[test]
+    fn decodes_link_placeholders() {
+        let object = format!("0x6073{}6000", "__$0123456789abcdef0123456789abcdef01$__");
+        let (code, placeholders) = decode_bytecode(&object).unwrap();
+        assert_eq!(code.len(), 2 + 20 + 2);
+        assert_eq!(placeholders, vec![(2, 20)]);
+        assert!(code[2..22].iter().all(|b| *b == 0));
+    }
+}
@@ -0,0 +1,229 @@
+use ethers::types::{Address, Transaction, TransactionReceipt, I256, U256};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use super::call_tree::CallNode;
+use super::tokens::{TokenRegistry, TokenStandard, TokenTransfer};
+use super::units::UnitFormatter;
+
+/// Asset whose balance changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Asset {
+    Native,
+    /// ERC-20 balances are tracked per token, NFTs per token ID
+    Token { address: Address, token_id: Option<U256> },
+}
+
+/// Net balance change of every address touched by a transaction
+#[derive(Debug, Clone, Default)]
+pub struct BalanceChanges {
+    changes: BTreeMap<Address, BTreeMap<Asset, I256>>,
+    standards: HashMap<Address, TokenStandard>,
+    gas_fees: HashMap<Address, U256>,
+}
+
+impl BalanceChanges {
+    /// Net ether moved by committed call frames, token transfers and the gas paid by the sender
+    /// Without a call tree only the top-level value of a successful transaction is counted
+    pub fn collect(
+        tx: &Transaction,
+        receipt: &TransactionReceipt,
+        call_tree: Option<&CallNode>,
+        transfers: &[TokenTransfer],
+    ) -> Self {
+        let mut changes = Self::default();
+
+        match call_tree {
+            Some(tree) => tree.walk_committed(&mut |_, node| {
+                if let (true, Some(to), Some(value)) = (node.transfers_value(), node.to, node.value) {
+                    changes.add(node.from, to, Asset::Native, value);
+                }
+            }),
+            None => {
+                if let (Some(1), Some(to)) = (receipt.status.map(|s| s.as_u64()), tx.to) {
+                    changes.add(tx.from, to, Asset::Native, tx.value);
+                }
+            }
+        }
+
+        // Mints and burns are shown on the holder side only
+        for transfer in transfers {
+            let asset = Asset::Token {
+                address: transfer.token,
+                token_id: (transfer.standard != TokenStandard::Erc20).then_some(transfer.token_id).flatten(),
+            };
+            changes.standards.insert(transfer.token, transfer.standard);
+            if !transfer.from.is_zero() {
+                changes.change(transfer.from, asset, transfer.amount, false);
+            }
+            if !transfer.to.is_zero() {
+                changes.change(transfer.to, asset, transfer.amount, true);
+            }
+        }
+
+        if let (Some(gas_used), Some(price)) = (receipt.gas_used, receipt.effective_gas_price) {
+            let fee = gas_used.saturating_mul(price);
+            if !fee.is_zero() {
+                changes.change(tx.from, Asset::Native, fee, false);
+                changes.gas_fees.insert(tx.from, fee);
+            }
+        }
+
+        changes
+    }
+
+    fn add(&mut self, from: Address, to: Address, asset: Asset, amount: U256) {
+        if from == to {
+            return;
+        }
+        self.change(from, asset, amount, false);
+        self.change(to, asset, amount, true);
+    }
+
+    fn change(&mut self, address: Address, asset: Asset, amount: U256, credit: bool) {
+        let amount = I256::try_from(amount).unwrap_or(I256::MAX);
+        let balance = self.changes.entry(address).or_default().entry(asset).or_insert_with(I256::zero);
+        *balance = if credit { balance.saturating_add(amount) } else { balance.saturating_sub(amount) };
+    }
+
+    /// One entry per address with a non-zero net change, sorted by address
+    pub fn to_json(&self, tokens: &TokenRegistry, units: &UnitFormatter) -> Value {
+        let entries: Vec<Value> = self.changes.iter().filter_map(|(address, assets)| {
+            let changes: Vec<Value> = assets.iter()
+                .filter(|(_, amount)| !amount.is_zero())
+                .map(|(asset, amount)| self.change_to_json(asset, *amount, tokens, units))
+                .collect();
+            let gas_fee = self.gas_fees.get(address);
+            if changes.is_empty() && gas_fee.is_none() {
+                return None;
+            }
+            Some(json!({
+                "address": format!("{:?}", address),
+                "gasFee": gas_fee.map(|fee| fee.to_string()),
+                "formattedGasFee": gas_fee.map(|fee| units.native(*fee)),
+                "changes": changes,
+            }))
+        }).collect();
+        Value::Array(entries)
+    }
+
+    fn change_to_json(&self, asset: &Asset, amount: I256, tokens: &TokenRegistry, units: &UnitFormatter) -> Value {
+        let sign = if amount.is_negative() { "-" } else { "+" };
+        let magnitude = amount.unsigned_abs();
+        match asset {
+            Asset::Native => json!({
+                "asset": "native",
+                "symbol": units.symbol(),
+                "amount": amount.to_string(),
+                "formattedAmount": format!("{}{}", sign, units.native(magnitude)),
+            }),
+            Asset::Token { address, token_id } => {
+                let standard = self.standards.get(address).copied().unwrap_or(TokenStandard::Erc20);
+                let formatted = match standard {
+                    TokenStandard::Erc20 => tokens.format_amount(address, magnitude, units),
+                    _ => magnitude.to_string(),
+                };
+                let info = tokens.get(address);
+                json!({
+                    "asset": standard,
+                    "token": format!("{:?}", address),
+                    "symbol": info.and_then(|i| i.symbol.clone()),
+                    "tokenId": token_id.map(|id| id.to_string()),
+                    "amount": amount.to_string(),
+                    "formattedAmount": format!("{}{}", sign, formatted),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SENDER: Address = Address::repeat_byte(0x05);
+    const A: Address = Address::repeat_byte(0x0a);
+    const B: Address = Address::repeat_byte(0x0b);
+    const TOKEN: Address = Address::repeat_byte(0x70);
+
+    fn call(from: Address, to: Address, value: u64, error: Option<&str>, calls: Vec<CallNode>) -> CallNode {
+        CallNode {
+            call_type: "CALL".to_string(),
+            from,
+            to: Some(to),
+            value: Some(value.into()),
+            error: error.map(str::to_string),
+            calls,
+            ..Default::default()
+        }
+    }
+
+    fn receipt(status: u64, gas_used: u64, gas_price: u64) -> TransactionReceipt {
+        TransactionReceipt {
+            status: Some(status.into()),
+            gas_used: Some(gas_used.into()),
+            effective_gas_price: Some(gas_price.into()),
+            ..Default::default()
+        }
+    }
+
+    fn transfer(standard: TokenStandard, from: Address, to: Address, token_id: Option<u64>, amount: u64) -> TokenTransfer {
+        TokenTransfer {
+            log_index: 0,
+            standard,
+            token: TOKEN,
+            from,
+            to,
+            operator: None,
+            token_id: token_id.map(U256::from),
+            amount: amount.into(),
+        }
+    }
+
+    fn balance(changes: &BalanceChanges, address: Address, asset: Asset) -> Option<i64> {
+        changes.changes.get(&address)?.get(&asset).map(|amount| amount.as_i64())
+    }
+
+    #[test]
+    fn counts_committed_frames_and_gas_fee() {
+        let tx = Transaction { from: SENDER, to: Some(A), value: 10.into(), ..Default::default() };
+        let tree = call(SENDER, A, 10, None, vec![
+            call(A, B, 4, None, Vec::new()),
+            call(A, B, 100, Some("execution reverted"), vec![call(B, SENDER, 50, None, Vec::new())]),
+        ]);
+        let changes = BalanceChanges::collect(&tx, &receipt(1, 21_000, 2), Some(&tree), &[]);
+
+        assert_eq!(balance(&changes, SENDER, Asset::Native), Some(-10 - 42_000));
+        assert_eq!(balance(&changes, A, Asset::Native), Some(6));
+        assert_eq!(balance(&changes, B, Asset::Native), Some(4));
+        assert_eq!(changes.gas_fees.get(&SENDER), Some(&U256::from(42_000)));
+    }
+
+    #[test]
+    fn counts_top_level_value_without_call_tree_only_on_success() {
+        let tx = Transaction { from: SENDER, to: Some(A), value: 10.into(), ..Default::default() };
+        let changes = BalanceChanges::collect(&tx, &receipt(1, 0, 0), None, &[]);
+        assert_eq!(balance(&changes, A, Asset::Native), Some(10));
+
+        let changes = BalanceChanges::collect(&tx, &receipt(0, 0, 0), None, &[]);
+        assert_eq!(balance(&changes, A, Asset::Native), None);
+    }
+
+    #[test]
+    fn tracks_tokens_per_id_and_skips_zero_address() {
+        let tx = Transaction { from: SENDER, ..Default::default() };
+        let changes = BalanceChanges::collect(&tx, &receipt(1, 0, 0), None, &[
+            transfer(TokenStandard::Erc20, Address::zero(), A, None, 100),
+            transfer(TokenStandard::Erc20, A, B, None, 30),
+            transfer(TokenStandard::Erc721, A, B, Some(7), 1),
+            transfer(TokenStandard::Erc721, B, Address::zero(), Some(8), 1),
+        ]);
+        let erc20 = Asset::Token { address: TOKEN, token_id: None };
+        let nft = |id: u64| Asset::Token { address: TOKEN, token_id: Some(id.into()) };
+
+        assert_eq!(balance(&changes, A, erc20), Some(70));
+        assert_eq!(balance(&changes, B, erc20), Some(30));
+        assert_eq!(balance(&changes, B, nft(7)), Some(1));
+        assert_eq!(balance(&changes, B, nft(8)), Some(-1));
+        assert!(!changes.changes.contains_key(&Address::zero()));
+    }
+}
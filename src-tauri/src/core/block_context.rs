@@ -0,0 +1,153 @@
+use chrono::{DateTime, Utc};
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::types::{Address, Transaction, TransactionReceipt, H256, U256, U64};
+use serde_json::{json, Value};
+use super::units::UnitFormatter;
+
+/// Header fields of the block that included the traced transaction
+#[derive(Debug, Clone)]
+pub struct BlockContext {
+    pub number: U64,
+    pub hash: Option<H256>,
+    pub timestamp: U256,
+    /// Miner, or fee recipient after the merge
+    pub miner: Option<Address>,
+    /// Absent before London (EIP-1559)
+    pub base_fee: Option<U256>,
+    pub gas_limit: U256,
+    pub gas_used: U256,
+    pub transaction_count: usize,
+}
+
+impl BlockContext {
+    pub async fn fetch(provider: &Provider<Http>, number: U64) -> anyhow::Result<Self> {
+        let block = provider
+            .get_block(number)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Block {} not found", number))?;
+        Ok(Self {
+            number,
+            hash: block.hash,
+            timestamp: block.timestamp,
+            miner: block.author,
+            base_fee: block.base_fee_per_gas,
+            gas_limit: block.gas_limit,
+            gas_used: block.gas_used,
+            transaction_count: block.transactions.len(),
+        })
+    }
+
+    pub fn date_time(&self) -> Option<DateTime<Utc>> {
+        let seconds = i64::try_from(self.timestamp).ok()?;
+        DateTime::from_timestamp(seconds, 0)
+    }
+
+    /// Share of the block gas limit used, in percent
+    pub fn utilization(&self) -> Option<f64> {
+        if self.gas_limit.is_zero() {
+            return None;
+        }
+        // Basis points fit comfortably in a u64, so only the final division is done in f64
+        let basis_points = self.gas_used.saturating_mul(U256::from(10_000)) / self.gas_limit;
+        Some(basis_points.low_u64() as f64 / 100.0)
+    }
+
+    /// Priority fee per gas actually paid to the miner: effective gas price minus base fee
+    pub fn priority_fee(&self, receipt: &TransactionReceipt) -> Option<U256> {
+        Some(receipt.effective_gas_price?.saturating_sub(self.base_fee?))
+    }
+
+    pub fn to_json(&self, tx: &Transaction, receipt: &TransactionReceipt, units: &UnitFormatter) -> Value {
+        let date_time = self.date_time();
+        let priority_fee = self.priority_fee(receipt);
+        json!({
+            "number": self.number.as_u64(),
+            "hash": self.hash.map(|h| format!("{:?}", h)),
+            "timestamp": self.timestamp.low_u64(),
+            "dateTime": date_time.map(|t| t.to_rfc3339()),
+            "age": date_time.map(|t| format_age(Utc::now().signed_duration_since(t))),
+            "miner": self.miner.map(|m| format!("{:?}", m)),
+            "baseFee": self.base_fee.map(|fee| units.gwei(fee)),
+            "baseFeeWei": self.base_fee.map(|fee| fee.to_string()),
+            "gasLimit": self.gas_limit.to_string(),
+            "gasUsed": self.gas_used.to_string(),
+            "utilization": self.utilization(),
+            "transactionIndex": receipt.transaction_index.as_u64(),
+            "transactionCount": self.transaction_count,
+            "priorityFee": priority_fee.map(|fee| units.gwei(fee)),
+            "priorityFeeWei": priority_fee.map(|fee| fee.to_string()),
+            "maxPriorityFee": tx.max_priority_fee_per_gas.map(|fee| units.gwei(fee)),
+            "maxFee": tx.max_fee_per_gas.map(|fee| units.gwei(fee)),
+        })
+    }
+}
+
+/// Two most significant units of a duration, e.g. "3 days 4 hrs ago"
+fn format_age(duration: chrono::Duration) -> String {
+    let seconds = duration.num_seconds();
+    if seconds < 0 {
+        return "in the future".to_string();
+    }
+    let units = [("day", 86_400), ("hr", 3_600), ("min", 60), ("sec", 1)];
+    let parts: Vec<String> = units.iter()
+        .scan(seconds, |remaining, (name, size)| {
+            let count = *remaining / size;
+            *remaining %= size;
+            Some((count, name))
+        })
+        .skip_while(|(count, _)| *count == 0)
+        .take(2)
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, name)| format!("{} {}{}", count, name, if count == 1 { "" } else { "s" }))
+        .collect();
+    if parts.is_empty() {
+        "just now".to_string()
+    } else {
+        format!("{} ago", parts.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn context(base_fee: Option<u64>, gas_limit: u64, gas_used: u64) -> BlockContext {
+        BlockContext {
+            number: 100.into(),
+            hash: None,
+            timestamp: 1_700_000_000.into(),
+            miner: None,
+            base_fee: base_fee.map(U256::from),
+            gas_limit: gas_limit.into(),
+            gas_used: gas_used.into(),
+            transaction_count: 1,
+        }
+    }
+
+    #[test]
+    fn formats_two_most_significant_units() {
+        assert_eq!(format_age(Duration::seconds(0)), "just now");
+        assert_eq!(format_age(Duration::seconds(1)), "1 sec ago");
+        assert_eq!(format_age(Duration::seconds(3 * 86_400 + 4 * 3_600 + 59)), "3 days 4 hrs ago");
+        // A zero middle unit is dropped rather than shown
+        assert_eq!(format_age(Duration::seconds(86_400 + 30)), "1 day ago");
+        assert_eq!(format_age(Duration::seconds(-5)), "in the future");
+    }
+
+    #[test]
+    fn computes_utilization_in_percent() {
+        assert_eq!(context(None, 30_000_000, 15_000_000).utilization(), Some(50.0));
+        assert_eq!(context(None, 30_000_000, 10_000_000).utilization(), Some(33.33));
+        assert_eq!(context(None, 0, 0).utilization(), None);
+    }
+
+    #[test]
+    fn derives_priority_fee_from_base_fee() {
+        let receipt = TransactionReceipt { effective_gas_price: Some(12.into()), ..Default::default() };
+        assert_eq!(context(Some(10), 1, 0).priority_fee(&receipt), Some(U256::from(2)));
+        // Pre-London blocks have no base fee
+        assert_eq!(context(None, 1, 0).priority_fee(&receipt), None);
+        assert_eq!(context(Some(10), 1, 0).date_time().map(|t| t.to_rfc3339()), Some("2023-11-14T22:13:20+00:00".to_string()));
+    }
+}
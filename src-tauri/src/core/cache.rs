@@ -0,0 +1,43 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long a "not verified" answer is reused before asking the source again
+pub const NOT_VERIFIED_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// JSON file cache on disk, one file per key grouped in namespaces
+/// e.g. `<root>/etherscan/1/0xabc....json`
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    root: PathBuf,
+}
+
+impl DiskCache {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, namespace: &str, key: &str) -> PathBuf {
+        let file: String = key.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+            .collect();
+        self.root.join(namespace).join(format!("{}.json", file))
+    }
+
+    /// Read a cached entry; unreadable or outdated entries are treated as missing
+    pub fn get<T: DeserializeOwned>(&self, namespace: &str, key: &str) -> Option<T> {
+        let content = fs::read_to_string(self.path(namespace, key)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn put<T: Serialize>(&self, namespace: &str, key: &str, value: &T) -> anyhow::Result<()> {
+        let path = self.path(namespace, key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(value)?)?;
+        Ok(())
+    }
+}
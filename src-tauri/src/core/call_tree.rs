@@ -0,0 +1,392 @@
+use ethers::providers::{Http, Provider};
+use ethers::types::{Address, Bytes, Transaction, H256, U256, U64};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use super::contract_registry::ContractRegistry;
+use super::precompiles;
+use super::revert_cause::revert_reason;
+use super::trace_formatter::format_event;
+use super::units::UnitFormatter;
+
+/// A call frame produced by geth's `callTracer`
+/// Kept separate from the ethers type so that log positions are preserved
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallNode {
+    #[serde(rename = "type")]
+    pub call_type: String,
+    pub from: Address,
+    #[serde(default)]
+    pub to: Option<Address>,
+    #[serde(default)]
+    pub value: Option<U256>,
+    #[serde(default)]
+    pub gas: U256,
+    #[serde(default)]
+    pub gas_used: U256,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub input: Bytes,
+    #[serde(default)]
+    pub output: Option<Bytes>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub revert_reason: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub calls: Vec<CallNode>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub logs: Vec<CallLog>,
+}
+
+/// A log emitted directly by a call frame (`callTracer` with `withLog`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CallLog {
+    pub address: Address,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub topics: Vec<H256>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub data: Bytes,
+    /// Number of sub-calls made by the frame before this log was emitted
+    #[serde(default)]
+    pub position: Option<U64>,
+}
+
+impl CallLog {
+    /// Sub-calls made before the log, or every sub-call when the tracer did not report it
+    fn position_in(&self, node: &CallNode) -> usize {
+        self.position.map(|p| p.as_usize()).unwrap_or(node.calls.len())
+    }
+}
+
+/// A log located in the call tree
+#[derive(Debug, Clone)]
+pub struct FrameLog<'a> {
+    /// Path of the emitting frame
+    pub path: Vec<usize>,
+    /// Number of sub-calls the frame made before emitting the log
+    pub position: usize,
+    pub log: &'a CallLog,
+    /// Index among the receipt logs, `None` when the frame or one of its callers reverted
+    /// and the log was discarded
+    pub receipt_index: Option<usize>,
+}
+
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+impl CallNode {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Visit every frame depth-first together with its path from the root
+    /// The root has an empty path, its second child `[1]`, that child's first `[1, 0]` and so on
+    pub fn walk<'a, F: FnMut(&[usize], &'a CallNode)>(&'a self, f: &mut F) {
+        let mut path = Vec::new();
+        self.walk_inner(&mut path, f);
+    }
+
+    fn walk_inner<'a, F: FnMut(&[usize], &'a CallNode)>(&'a self, path: &mut Vec<usize>, f: &mut F) {
+        f(path, self);
+        for (idx, child) in self.calls.iter().enumerate() {
+            path.push(idx);
+            child.walk_inner(path, f);
+            path.pop();
+        }
+    }
+
+    /// Like `walk`, but skips failed frames and everything below them, whose effects were reverted
+    pub fn walk_committed<'a, F: FnMut(&[usize], &'a CallNode)>(&'a self, f: &mut F) {
+        let mut path = Vec::new();
+        self.walk_committed_inner(&mut path, f);
+    }
+
+    fn walk_committed_inner<'a, F: FnMut(&[usize], &'a CallNode)>(&'a self, path: &mut Vec<usize>, f: &mut F) {
+        if !self.is_success() {
+            return;
+        }
+        f(path, self);
+        for (idx, child) in self.calls.iter().enumerate() {
+            path.push(idx);
+            child.walk_committed_inner(path, f);
+            path.pop();
+        }
+    }
+
+    /// Every log of the tree in emission order, reverted ones included
+    pub fn logs_in_order(&self) -> Vec<FrameLog<'_>> {
+        let mut logs = Vec::new();
+        self.collect_logs(&mut Vec::new(), true, &mut 0, &mut logs);
+        logs
+    }
+
+    fn collect_logs<'a>(&'a self, path: &mut Vec<usize>, committed: bool, receipt_index: &mut usize, out: &mut Vec<FrameLog<'a>>) {
+        let committed = committed && self.is_success();
+        let mut logs: Vec<&CallLog> = self.logs.iter().collect();
+        logs.sort_by_key(|log| log.position_in(self));
+        let mut logs = logs.into_iter().peekable();
+
+        // A log at position n was emitted after the frame's first n sub-calls
+        for idx in 0..=self.calls.len() {
+            while let Some(log) = logs.next_if(|log| log.position_in(self) <= idx) {
+                out.push(FrameLog {
+                    path: path.clone(),
+                    position: log.position_in(self),
+                    log,
+                    receipt_index: committed.then(|| {
+                        *receipt_index += 1;
+                        *receipt_index - 1
+                    }),
+                });
+            }
+            if let Some(child) = self.calls.get(idx) {
+                path.push(idx);
+                child.collect_logs(path, committed, receipt_index, out);
+                path.pop();
+            }
+        }
+    }
+
+    /// Whether the frame moves its `value` from `from` to `to`
+    /// DELEGATECALL and CALLCODE run in the caller's context, so no ether changes hands
+    pub fn transfers_value(&self) -> bool {
+        matches!(self.call_type.as_str(), "CALL" | "CREATE" | "CREATE2" | "SELFDESTRUCT")
+            && self.to.is_some()
+            && self.value.map(|v| !v.is_zero()).unwrap_or(false)
+    }
+
+    /// Frame at a given path below this one
+    pub fn node_at(&self, path: &[usize]) -> Option<&CallNode> {
+        path.iter().try_fold(self, |node, idx| node.calls.get(*idx))
+    }
+
+    /// Every address appearing as caller or callee in the tree
+    pub fn touched_addresses(&self) -> BTreeSet<Address> {
+        let mut addresses = BTreeSet::new();
+        self.walk(&mut |_, node| {
+            addresses.insert(node.from);
+            if let Some(to) = node.to {
+                addresses.insert(to);
+            }
+        });
+        addresses
+    }
+}
+
+/// Call object for `debug_traceCall` re-executing a transaction
+/// The access list is kept, as pre-warmed accounts and slots change the gas of every step
+pub fn trace_call_request(tx: &Transaction) -> Value {
+    let mut call = json!({
+        "from": tx.from,
+        "to": tx.to,
+        "gas": tx.gas,
+        "value": tx.value,
+        "data": tx.input,
+    });
+    if let Some(access_list) = &tx.access_list {
+        call["accessList"] = json!(access_list);
+    }
+    call
+}
+
+/// Re-execute the transaction on the fork with geth's `callTracer`
+pub async fn fetch_call_tree(provider: &Provider<Http>, tx: &Transaction) -> anyhow::Result<CallNode> {
+    let call = trace_call_request(tx);
+    let options = json!({
+        "tracer": "callTracer",
+        "tracerConfig": { "withLog": true },
+    });
+
+    let raw: Value = provider
+        .request("debug_traceCall", (call, "latest", options))
+        .await
+        .map_err(|e| anyhow::anyhow!("debug_traceCall failed: {}", e))?;
+
+    serde_json::from_value(raw).map_err(|e| anyhow::anyhow!("Unexpected callTracer output: {}", e))
+}
+
+/// Link each entry of the receipt's events list to its emitting frame and return the logs of
+/// reverted frames, which never reach the receipt
+/// Nothing is linked when the tree's logs do not line up with the receipt's
+pub fn attribute_events(root: &CallNode, events: &mut Value, registry: &ContractRegistry) -> Vec<Value> {
+    let logs = root.logs_in_order();
+    let (committed, reverted): (Vec<&FrameLog>, Vec<&FrameLog>) = logs.iter().partition(|l| l.receipt_index.is_some());
+
+    if let Some(events) = events.as_array_mut() {
+        let aligned = events.len() == committed.len()
+            && events.iter().zip(&committed).all(|(event, l)| event["address"] == json!(format!("{:?}", l.log.address)));
+        if aligned {
+            for (event, frame_log) in events.iter_mut().zip(&committed) {
+                event["frame"] = json!(frame_log.path);
+                event["position"] = json!(frame_log.position);
+            }
+        } else {
+            println!("[TRACE] Call tree logs do not match the receipt, events are not linked to frames");
+        }
+    }
+
+    reverted.into_iter().map(|frame_log| {
+        let log = frame_log.log;
+        let mut event = format_event(&log.address, &log.topics, &log.data, registry);
+        event["frame"] = json!(frame_log.path);
+        event["position"] = json!(frame_log.position);
+        event
+    }).collect()
+}
+
+/// Formatted frame at a given path, used to attach extra data after formatting
+pub fn formatted_node_mut<'a>(tree: &'a mut Value, path: &[usize]) -> Option<&'a mut serde_json::Map<String, Value>> {
+    let node = path.iter().try_fold(tree, |node, idx| node.get_mut("calls")?.get_mut(*idx))?;
+    node.as_object_mut()
+}
+
+/// Format a call tree with function names and arguments decoded from the registry
+/// Each frame lists the logs it emitted, with their position among its sub-calls
+pub fn format_call_tree(root: &CallNode, registry: &ContractRegistry, units: &UnitFormatter) -> Value {
+    let mut tree = format_node(root, &mut Vec::new(), registry, units);
+    for frame_log in root.logs_in_order() {
+        let log = frame_log.log;
+        let mut event = format_event(&log.address, &log.topics, &log.data, registry);
+        event["index"] = json!(frame_log.receipt_index);
+        event["position"] = json!(frame_log.position);
+        event["reverted"] = json!(frame_log.receipt_index.is_none());
+        if let Some(logs) = formatted_node_mut(&mut tree, &frame_log.path).and_then(|node| node.get_mut("logs")?.as_array_mut()) {
+            logs.push(event);
+        }
+    }
+    tree
+}
+
+fn format_node(node: &CallNode, path: &mut Vec<usize>, registry: &ContractRegistry, units: &UnitFormatter) -> Value {
+    let decoded = node.to.and_then(|to| registry.decode_call(&to, &node.input));
+    let decoded_output = match (node.to, &node.output) {
+        (Some(to), Some(output)) if node.is_success() => registry.decode_output(&to, &node.input, output),
+        _ => None,
+    };
+
+    let precompile = node.to.and_then(|to| precompiles::precompile(&to));
+    let function = match (&decoded, precompile) {
+        (Some(call), _) => call.signature.clone(),
+        (None, Some(precompile)) => precompile.signature.to_string(),
+        (None, None) if node.input.len() >= 4 => format!("0x{}", hex::encode(&node.input[..4])),
+        (None, None) => "fallback()".to_string(),
+    };
+
+    let mut calls = Vec::with_capacity(node.calls.len());
+    for (idx, child) in node.calls.iter().enumerate() {
+        path.push(idx);
+        calls.push(format_node(child, path, registry, units));
+        path.pop();
+    }
+
+    // Calls into a proxy keep the proxy hop but name the implementation that runs
+    let proxy = node.to.and_then(|to| registry.proxy(&to));
+    let implementation = node.to.and_then(|to| registry.implementation_for(&to, &node.input));
+
+    json!({
+        "path": path.clone(),
+        "depth": path.len(),
+        "type": node.call_type,
+        "from": format!("{:?}", node.from),
+        "to": node.to.map(|addr| format!("{:?}", addr)),
+        "contract": node.to.and_then(|to| registry.name(&to)).or(precompile.map(|p| p.name)),
+        "proxy": proxy.map(|p| p.kind),
+        "implementation": implementation.map(|addr| format!("{:?}", addr)),
+        "implementationContract": implementation.and_then(|addr| registry.name(&addr)),
+        "function": function,
+        "args": decoded.as_ref().map(|call| &call.args),
+        "virtualCalls": decoded.as_ref().map(|call| &call.virtual_calls).filter(|calls| !calls.is_empty()),
+        "value": node.value.unwrap_or_default().to_string(),
+        "formattedValue": units.native(node.value.unwrap_or_default()),
+        "gas": node.gas.to_string(),
+        "gasUsed": node.gas_used.to_string(),
+        "input": format!("0x{}", hex::encode(&node.input)),
+        "output": node.output.as_ref().map(|o| format!("0x{}", hex::encode(o))),
+        "decodedOutput": decoded_output,
+        "precompile": node.to.and_then(|to| precompiles::decode(&to, &node.input, node.output.as_deref().filter(|_| node.is_success()))),
+        "success": node.is_success(),
+        "error": node.error,
+        "revertReason": revert_reason(node, registry),
+        "logs": [],
+        "calls": calls,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: Address = Address::repeat_byte(0x0a);
+    const POOL: Address = Address::repeat_byte(0x0b);
+
+    fn log(address: Address, position: u64) -> CallLog {
+        CallLog { address, position: Some(position.into()), ..Default::default() }
+    }
+
+    fn call(to: Address, error: Option<&str>, logs: Vec<CallLog>, calls: Vec<CallNode>) -> CallNode {
+        CallNode {
+            call_type: "CALL".to_string(),
+            to: Some(to),
+            error: error.map(str::to_string),
+            logs,
+            calls,
+            ..Default::default()
+        }
+    }
+
+    /// The root logs before and after its calls; the second call reverts with its log
+    fn tree() -> CallNode {
+        call(POOL, None, vec![log(POOL, 2), log(POOL, 0)], vec![
+            call(TOKEN, None, vec![log(TOKEN, 0)], Vec::new()),
+            call(TOKEN, Some("execution reverted"), vec![log(TOKEN, 0)], Vec::new()),
+        ])
+    }
+
+    fn receipt_events(addresses: &[Address]) -> Value {
+        addresses.iter().map(|a| json!({ "address": format!("{:?}", a) })).collect()
+    }
+
+    #[test]
+    fn orders_logs_by_position_and_skips_reverted_ones() {
+        let tree = tree();
+        let logs: Vec<(Vec<usize>, usize, Option<usize>)> = tree.logs_in_order().into_iter()
+            .map(|l| (l.path, l.position, l.receipt_index))
+            .collect();
+        assert_eq!(logs, vec![
+            (vec![], 0, Some(0)),
+            (vec![0], 0, Some(1)),
+            (vec![1], 0, None),
+            (vec![], 2, Some(2)),
+        ]);
+    }
+
+    #[test]
+    fn links_events_to_frames() {
+        let mut events = receipt_events(&[POOL, TOKEN, POOL]);
+        let reverted = attribute_events(&tree(), &mut events, &ContractRegistry::new());
+
+        assert_eq!(events[1]["frame"], json!([0]));
+        assert_eq!(events[2]["frame"], json!([]));
+        assert_eq!(events[2]["position"], 2);
+        assert_eq!(reverted.len(), 1);
+        assert_eq!(reverted[0]["frame"], json!([1]));
+    }
+
+    #[test]
+    fn leaves_events_unlinked_when_logs_do_not_line_up() {
+        let registry = ContractRegistry::new();
+        for addresses in [vec![POOL, TOKEN], vec![POOL, POOL, TOKEN]] {
+            let mut events = receipt_events(&addresses);
+            let reverted = attribute_events(&tree(), &mut events, &registry);
+            assert!(events.as_array().unwrap().iter().all(|e| e.get("frame").is_none()));
+            // Reverted logs come from the tree alone and are still listed
+            assert_eq!(reverted.len(), 1);
+        }
+    }
+}
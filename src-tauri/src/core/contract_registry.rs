@@ -0,0 +1,238 @@
+use ethers::abi::Abi;
+use ethers::types::{Address, H256};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::types::{ContractInfo, DecodedCall, DecodedEvent};
+use super::abi_decoder;
+use super::nested_calls;
+use super::proxy::ProxyInfo;
+use super::source_map::ContractSourceMap;
+use super::storage_layout::StorageLayout;
+
+/// A registered contract with its ABI parsed once for decoding
+#[derive(Debug, Clone)]
+struct RegisteredContract {
+    info: ContractInfo,
+    abi: Option<Abi>,
+}
+
+/// Registry of known contracts used to decode traces
+/// Populated from linked build artifacts and other contract sources
+#[derive(Debug, Clone, Default)]
+pub struct ContractRegistry {
+    contracts: HashMap<Address, RegisteredContract>,
+    source_maps: HashMap<Address, Arc<ContractSourceMap>>,
+    storage_layouts: HashMap<Address, Arc<StorageLayout>>,
+    proxies: HashMap<Address, ProxyInfo>,
+}
+
+impl ContractRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a contract. Fields already known for the address take precedence,
+    /// so sources should be registered from most to least trusted
+    pub fn register(&mut self, info: ContractInfo) {
+        let address: Address = match info.address.parse() {
+            Ok(address) => address,
+            Err(e) => {
+                println!("[REGISTRY] Ignoring contract with invalid address {}: {}", info.address, e);
+                return;
+            }
+        };
+
+        let entry = self.contracts.entry(address).or_insert_with(|| RegisteredContract {
+            info: ContractInfo {
+                address: format!("{:?}", address),
+                abi: None,
+                name: None,
+                verified: None,
+                source: None,
+            },
+            abi: None,
+        });
+
+        if entry.info.abi.is_none() && info.abi.is_some() {
+            entry.abi = info.abi.as_ref().and_then(|entries| parse_abi(entries));
+            entry.info.abi = info.abi;
+        }
+        if entry.info.name.is_none() {
+            entry.info.name = info.name;
+        }
+        if entry.info.verified.is_none() {
+            entry.info.verified = info.verified;
+        }
+        if entry.info.source.is_none() {
+            entry.info.source = info.source;
+        }
+    }
+
+    /// Register the runtime source map of a contract for source-level stepping
+    pub fn register_source_map(&mut self, address: Address, source_map: Arc<ContractSourceMap>) {
+        self.source_maps.entry(address).or_insert(source_map);
+    }
+
+    pub fn source_map(&self, address: &Address) -> Option<&ContractSourceMap> {
+        self.source_maps.get(address).map(|m| m.as_ref())
+    }
+
+    pub fn has_source_maps(&self) -> bool {
+        !self.source_maps.is_empty()
+    }
+
+    /// Register the solc storage layout of a contract for decoding storage changes
+    pub fn register_storage_layout(&mut self, address: Address, layout: Arc<StorageLayout>) {
+        self.storage_layouts.entry(address).or_insert(layout);
+    }
+
+    /// Layout of the storage at an address: a proxy's storage follows its implementation's layout
+    pub fn storage_layout(&self, address: &Address) -> Option<&StorageLayout> {
+        let layout = self.storage_layouts.get(address).or_else(|| {
+            let implementation = self.proxies.get(address)?.implementation?;
+            self.storage_layouts.get(&implementation)
+        });
+        layout.map(|l| l.as_ref())
+    }
+
+    pub fn has_storage_layouts(&self) -> bool {
+        !self.storage_layouts.is_empty()
+    }
+
+    /// Register a detected proxy so its calls and events decode with the implementation's ABI
+    pub fn register_proxy(&mut self, address: Address, proxy: ProxyInfo) {
+        self.register(ContractInfo {
+            address: format!("{:?}", address),
+            abi: None,
+            name: None,
+            verified: None,
+            source: None,
+        });
+        self.proxies.insert(address, proxy);
+    }
+
+    pub fn proxy(&self, address: &Address) -> Option<&ProxyInfo> {
+        self.proxies.get(address)
+    }
+
+    /// Implementation executing a call to a proxy
+    pub fn implementation_for(&self, address: &Address, input: &[u8]) -> Option<Address> {
+        self.proxies.get(address)?.implementation_for(input)
+    }
+
+    pub fn get(&self, address: &Address) -> Option<&ContractInfo> {
+        self.contracts.get(address).map(|c| &c.info)
+    }
+
+    pub fn contains(&self, address: &Address) -> bool {
+        self.contracts.contains_key(address)
+    }
+
+    /// Parsed ABI for an address, if one was registered
+    pub fn abi(&self, address: &Address) -> Option<&Abi> {
+        self.contracts.get(address).and_then(|c| c.abi.as_ref())
+    }
+
+    /// Contract name for an address, if one was registered
+    pub fn name(&self, address: &Address) -> Option<&str> {
+        self.contracts.get(address).and_then(|c| c.info.name.as_deref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.contracts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contracts.is_empty()
+    }
+
+    pub fn contracts(&self) -> impl Iterator<Item = &ContractInfo> {
+        self.contracts.values().map(|c| &c.info)
+    }
+
+    /// ABI used to decode a call: the contract's own if it defines the function,
+    /// otherwise the ABI of the implementation behind a proxy
+    fn call_abi(&self, address: &Address, input: &[u8]) -> Option<&Abi> {
+        if let Some(abi) = self.abi(address) {
+            if abi_decoder::find_function(abi, input).is_some() {
+                return Some(abi);
+            }
+        }
+        self.abi(&self.implementation_for(address, input)?)
+    }
+
+    /// Decode calldata sent to an address, including calls packed in known batching functions
+    pub fn decode_call(&self, address: &Address, input: &[u8]) -> Option<DecodedCall> {
+        self.decode_nested_call(Some(address), input, 0)
+    }
+
+    /// Decode calldata found `depth` batches deep; the target is unknown for some batch formats
+    pub fn decode_nested_call(&self, address: Option<&Address>, input: &[u8], depth: usize) -> Option<DecodedCall> {
+        let decoded = address
+            .and_then(|address| self.call_abi(address, input))
+            .and_then(|abi| abi_decoder::decode_call(abi, input));
+        nested_calls::unpack(self, address.copied(), input, decoded, depth)
+    }
+
+    /// Signature of the function called by `input`, without decoding its arguments
+    pub fn function_signature(&self, address: &Address, input: &[u8]) -> Option<String> {
+        let function = abi_decoder::find_function(self.call_abi(address, input)?, input)?;
+        Some(abi_decoder::function_signature(function))
+    }
+
+    /// Decode return data of a call to an address
+    pub fn decode_output(&self, address: &Address, input: &[u8], output: &[u8]) -> Option<Value> {
+        abi_decoder::decode_output(self.call_abi(address, input)?, input, output)
+    }
+
+    /// Human readable revert reason of a failed call
+    /// Custom errors may come from a callee, so every registered ABI is tried after the target's
+    pub fn decode_revert(&self, address: Option<&Address>, input: &[u8], output: &[u8]) -> Option<String> {
+        if let Some(reason) = abi_decoder::decode_builtin_revert(output) {
+            return Some(reason);
+        }
+        address
+            .and_then(|address| self.call_abi(address, input))
+            .into_iter()
+            .chain(self.contracts.values().filter_map(|c| c.abi.as_ref()))
+            .find_map(|abi| abi_decoder::decode_custom_error(abi, output))
+    }
+
+    /// Decode a log emitted by an address
+    /// Proxies emit their implementation's events, so those ABIs are tried as well
+    pub fn decode_log(&self, address: &Address, topics: &[H256], data: &[u8]) -> Option<DecodedEvent> {
+        let implementations = self.proxies.get(address).map(|p| p.implementations()).unwrap_or_default();
+        std::iter::once(*address)
+            .chain(implementations)
+            .filter_map(|a| self.abi(&a))
+            .find_map(|abi| abi_decoder::decode_log(abi, topics, data))
+    }
+
+    /// Summary of registered contracts for the trace output (ABIs omitted)
+    pub fn to_json(&self) -> Value {
+        let mut contracts: Vec<&ContractInfo> = self.contracts().collect();
+        contracts.sort_by(|a, b| a.address.cmp(&b.address));
+        Value::Array(contracts.into_iter().map(|info| {
+            serde_json::json!({
+                "address": info.address,
+                "name": info.name,
+                "verified": info.verified,
+                "hasAbi": info.abi.is_some(),
+                "proxy": info.address.parse::<Address>().ok().and_then(|a| self.proxies.get(&a)),
+            })
+        }).collect())
+    }
+}
+
+/// Convert ABI entries into an ethers `Abi` for decoding
+fn parse_abi(entries: &[crate::types::AbiEntry]) -> Option<Abi> {
+    let value = serde_json::to_value(entries).ok()?;
+    match serde_json::from_value::<Abi>(value) {
+        Ok(abi) => Some(abi),
+        Err(e) => {
+            println!("[REGISTRY] Failed to parse ABI: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Minimal RFC 4180 CSV reading and writing
+
+/// Characters that make spreadsheet applications evaluate a cell as a formula
+const FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// Quote a field when it contains a separator, quote or line break
+/// Fields that a spreadsheet would run as a formula are prefixed with `'`; plain numbers are kept
+pub fn escape_field(field: &str) -> String {
+    let field = if is_guarded(field) {
+        format!("'{}", field)
+    } else {
+        field.to_string()
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+/// Whether a field gets the formula guard: a formula, possibly already behind `'` prefixes,
+/// which are then guarded too so the field reads back unchanged
+fn is_guarded(field: &str) -> bool {
+    let field = field.trim_start_matches('\'');
+    field.starts_with(FORMULA_PREFIXES) && field.parse::<f64>().is_err()
+}
+
+/// Undo the formula guard of `escape_field`
+fn unescape_field(field: String) -> String {
+    match field.strip_prefix('\'') {
+        Some(rest) if is_guarded(rest) => rest.to_string(),
+        _ => field,
+    }
+}
+
+/// Format one CSV record (without the trailing newline)
+pub fn format_row<S: AsRef<str>>(fields: &[S]) -> String {
+    fields.iter().map(|f| escape_field(f.as_ref())).collect::<Vec<_>>().join(",")
+}
+
+/// Parse CSV text into records, honouring quoted fields spanning lines
+pub fn parse(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_quotes = true,
+            ',' => row.push(unescape_field(std::mem::take(&mut field))),
+            '\r' => {}
+            '\n' => {
+                row.push(unescape_field(std::mem::take(&mut field)));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(unescape_field(field));
+        rows.push(row);
+    }
+
+    rows.retain(|r| !(r.len() == 1 && r[0].trim().is_empty()));
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(fields: &[&str]) -> Vec<Vec<String>> {
+        parse(&format!("{}\n", format_row(fields)))
+    }
+
+    #[test]
+    fn guards_formulas_and_reads_them_back() {
+        let fields = ["=cmd|' /C calc'!A0", "-1+1", "@SUM(A1)", "\tvalue", "+x"];
+        assert_eq!(format_row(&fields[1..3]), "'-1+1,'@SUM(A1)");
+        assert_eq!(escape_field("\tvalue"), "'\tvalue");
+        assert_eq!(round_trip(&fields), vec![fields.map(str::to_string).to_vec()]);
+    }
+
+    #[test]
+    fn keeps_plain_numbers() {
+        assert_eq!(format_row(&["-1.5", "+3", "1e18", "0"]), "-1.5,+3,1e18,0");
+        assert_eq!(round_trip(&["-1.5", "+3"]), vec![vec!["-1.5".to_string(), "+3".to_string()]]);
+    }
+
+    #[test]
+    fn keeps_leading_quotes_of_plain_text() {
+        assert_eq!(escape_field("'quoted"), "'quoted");
+        assert_eq!(escape_field("'=x"), "''=x");
+        let fields = ["'quoted", "'=x", "''-y"];
+        assert_eq!(round_trip(&fields), vec![fields.map(str::to_string).to_vec()]);
+    }
+
+    #[test]
+    fn quotes_separators_quotes_and_line_breaks() {
+        assert_eq!(escape_field("a,b"), "\"a,b\"");
+        assert_eq!(escape_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        let fields = ["a,b", "say \"hi\"", "two\nlines", "crlf\r\nline", ""];
+        assert_eq!(round_trip(&fields), vec![fields.map(str::to_string).to_vec()]);
+    }
+
+    #[test]
+    fn parses_records_and_skips_blank_lines() {
+        let text = "address,label\r\n0xabc,\"Vault, v2\"\n\n0xdef,plain";
+        assert_eq!(parse(text), vec![
+            vec!["address".to_string(), "label".to_string()],
+            vec!["0xabc".to_string(), "Vault, v2".to_string()],
+            vec!["0xdef".to_string(), "plain".to_string()],
+        ]);
+    }
+}
@@ -0,0 +1,213 @@
+use ethers::types::{Address, Transaction, H256, U256};
+use ethers::utils::{get_contract_address, get_create2_address_from_hash, keccak256};
+use serde_json::{json, Value};
+use super::call_tree::CallNode;
+use super::step_trace::StepTrace;
+
+/// A contract deployed by a CREATE or CREATE2 frame, or by the transaction itself
+#[derive(Debug, Clone)]
+pub struct Deployment {
+    pub path: Vec<usize>,
+    pub call_type: String,
+    pub deployer: Address,
+    /// Address reported by the tracer, missing for some failed deployments
+    pub address: Option<Address>,
+    /// CREATE2 salt, read from the stack when the step trace is available
+    pub salt: Option<H256>,
+    pub init_code_hash: H256,
+    pub init_code_size: usize,
+    pub runtime_code_size: usize,
+    pub value: U256,
+    pub success: bool,
+    pub error: Option<String>,
+    /// A caller reverted, so the contract does not exist after the transaction
+    pub reverted: bool,
+    /// Address derived from the deployer and salt (CREATE2) or nonce (deployment transaction)
+    pub expected_address: Option<Address>,
+}
+
+impl Deployment {
+    /// Every CREATE/CREATE2 frame of the tree, failed ones included
+    pub fn collect(root: &CallNode, tx: &Transaction, step_trace: Option<&StepTrace>) -> Vec<Self> {
+        let call_steps = step_trace.map(|trace| trace.call_steps()).unwrap_or_default();
+        let mut deployments = Vec::new();
+        let mut failed: Vec<bool> = Vec::new();
+        root.walk(&mut |path, node| {
+            failed.truncate(path.len());
+            let reverted = failed.contains(&true);
+            failed.push(!node.is_success());
+            if !matches!(node.call_type.as_str(), "CREATE" | "CREATE2") {
+                return;
+            }
+
+            let init_code_hash = H256(keccak256(&node.input));
+            // CREATE2 pops value, offset, size and salt; the stack is reported bottom to top
+            let salt = (node.call_type == "CREATE2")
+                .then(|| call_steps.get(path))
+                .flatten()
+                .and_then(|step| step_trace?.logs[*step].stack.as_ref())
+                .and_then(|stack| stack.get(stack.len().checked_sub(4)?))
+                .map(|raw| {
+                    let mut salt = H256::zero();
+                    raw.to_big_endian(salt.as_bytes_mut());
+                    salt
+                });
+            let expected_address = match (path.is_empty(), salt) {
+                (_, Some(salt)) => Some(get_create2_address_from_hash(node.from, salt, init_code_hash)),
+                (true, None) if node.call_type == "CREATE" => Some(get_contract_address(tx.from, tx.nonce)),
+                _ => None,
+            };
+
+            deployments.push(Self {
+                path: path.to_vec(),
+                call_type: node.call_type.clone(),
+                deployer: node.from,
+                address: node.to,
+                salt,
+                init_code_hash,
+                init_code_size: node.input.len(),
+                runtime_code_size: node.output.as_ref().filter(|_| node.is_success()).map(|o| o.len()).unwrap_or_default(),
+                value: node.value.unwrap_or_default(),
+                success: node.is_success(),
+                error: node.error.clone(),
+                reverted,
+                expected_address,
+            });
+        });
+        deployments
+    }
+
+    /// Whether the reported address matches the derived one, `None` when either is unknown
+    pub fn address_verified(&self) -> Option<bool> {
+        Some(self.address? == self.expected_address?)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "path": self.path,
+            "type": self.call_type,
+            "deployer": format!("{:?}", self.deployer),
+            // A failed deployment leaves nothing behind, but the derived address is still useful
+            "address": self.address.or(self.expected_address).map(|a| format!("{:?}", a)),
+            "salt": self.salt.map(|s| format!("{:?}", s)),
+            "initCodeHash": format!("{:?}", self.init_code_hash),
+            "initCodeSize": self.init_code_size,
+            "runtimeCodeSize": self.runtime_code_size,
+            "value": self.value.to_string(),
+            "success": self.success,
+            "error": self.error,
+            "reverted": self.reverted,
+            "expectedAddress": self.expected_address.map(|a| format!("{:?}", a)),
+            "addressVerified": self.address_verified(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::StructLog;
+    use crate::core::step_trace::StepFrame;
+
+    fn address(value: &str) -> Address {
+        value.parse().unwrap()
+    }
+
+    fn frame(call_type: &str, from: Address, to: Option<Address>, input: Vec<u8>, calls: Vec<CallNode>) -> CallNode {
+        CallNode {
+            call_type: call_type.to_string(),
+            from,
+            to,
+            input: input.into(),
+            calls,
+            ..Default::default()
+        }
+    }
+
+    /// Step trace of a root frame whose first call is a CREATE2 with the given stack
+    fn create2_steps(stack: Vec<U256>) -> StepTrace {
+        StepTrace {
+            failed: false,
+            logs: vec![StructLog { op: "CREATE2".to_string(), stack: Some(stack), ..Default::default() }],
+            frames: vec![StepFrame { path: Vec::new(), code_address: None, storage_address: None, is_create: false }],
+            step_frames: vec![0],
+        }
+    }
+
+    #[test]
+    fn reads_create2_salt_below_value_offset_and_size() {
+        // EIP-1014 example 5
+        let factory = address("0x00000000000000000000000000000000deadbeef");
+        let expected = address("0x60f3f640a8508fc6a86d45df051962668e1e8ac7");
+        let create2 = frame("CREATE2", factory, Some(expected), vec![0xde, 0xad, 0xbe, 0xef], Vec::new());
+        let root = frame("CALL", Address::repeat_byte(0x01), Some(factory), Vec::new(), vec![create2]);
+        // Bottom to top: unrelated item, salt, size, offset, value
+        let steps = create2_steps(vec![U256::from(0x1234), U256::from(0xcafebabe_u64), U256::from(4), U256::zero(), U256::zero()]);
+
+        let deployments = Deployment::collect(&root, &Transaction::default(), Some(&steps));
+        assert_eq!(deployments.len(), 1);
+        let deployment = &deployments[0];
+        assert_eq!(deployment.path, vec![0]);
+        assert_eq!(deployment.salt, Some(H256::from_low_u64_be(0xcafebabe)));
+        assert_eq!(deployment.expected_address, Some(expected));
+        assert_eq!(deployment.address_verified(), Some(true));
+        assert_eq!(deployment.init_code_size, 4);
+    }
+
+    #[test]
+    fn derives_create2_addresses_from_eip_1014_vectors() {
+        let vectors = [
+            ("0x0000000000000000000000000000000000000000", 0u64, vec![0x00], "0x4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38"),
+            ("0xdeadbeef00000000000000000000000000000000", 0, vec![0x00], "0xb928f69bb1d91cd65274e3c79d8986362984fda3"),
+            ("0x00000000000000000000000000000000deadbeef", 0xcafebabe, vec![0xde, 0xad, 0xbe, 0xef], "0x60f3f640a8508fc6a86d45df051962668e1e8ac7"),
+        ];
+        for (deployer, salt, init_code, expected) in vectors {
+            let create2 = frame("CREATE2", address(deployer), None, init_code, Vec::new());
+            let root = frame("CALL", Address::zero(), Some(address(deployer)), Vec::new(), vec![create2]);
+            let steps = create2_steps(vec![U256::from(salt), U256::one(), U256::zero(), U256::zero()]);
+
+            let deployment = &Deployment::collect(&root, &Transaction::default(), Some(&steps))[0];
+            assert_eq!(deployment.expected_address, Some(address(expected)));
+            // No reported address: the derived one is shown, but not verified
+            assert_eq!(deployment.address_verified(), None);
+            assert_eq!(deployment.to_json()["address"], expected);
+        }
+    }
+
+    #[test]
+    fn leaves_salt_unknown_without_step_trace() {
+        let create2 = frame("CREATE2", Address::repeat_byte(0x02), Some(Address::repeat_byte(0x03)), vec![0x00], Vec::new());
+        let root = frame("CALL", Address::repeat_byte(0x01), Some(Address::repeat_byte(0x02)), Vec::new(), vec![create2]);
+
+        let deployment = &Deployment::collect(&root, &Transaction::default(), None)[0];
+        assert_eq!(deployment.salt, None);
+        assert_eq!(deployment.expected_address, None);
+        assert_eq!(deployment.to_json()["salt"], Value::Null);
+    }
+
+    #[test]
+    fn derives_deployment_transaction_address_from_nonce() {
+        let sender = address("0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0");
+        let tx = Transaction { from: sender, nonce: U256::one(), ..Default::default() };
+        let root = frame("CREATE", sender, Some(Address::repeat_byte(0x09)), vec![0x60, 0x00], Vec::new());
+
+        let deployment = &Deployment::collect(&root, &tx, None)[0];
+        assert_eq!(deployment.expected_address, Some(address("0x343c43a37d37dff08ae8c4a11544c718abb4fcf8")));
+        assert_eq!(deployment.address_verified(), Some(false));
+    }
+
+    #[test]
+    fn marks_deployments_under_failed_calls_as_reverted() {
+        let create = frame("CREATE", Address::repeat_byte(0x02), Some(Address::repeat_byte(0x03)), Vec::new(), Vec::new());
+        let mut failed = frame("CALL", Address::repeat_byte(0x01), Some(Address::repeat_byte(0x02)), Vec::new(), vec![create]);
+        failed.error = Some("execution reverted".to_string());
+        let sibling = frame("CREATE", Address::repeat_byte(0x01), Some(Address::repeat_byte(0x04)), Vec::new(), Vec::new());
+        let root = frame("CALL", Address::repeat_byte(0x05), Some(Address::repeat_byte(0x01)), Vec::new(), vec![failed, sibling]);
+
+        let deployments = Deployment::collect(&root, &Transaction::default(), None);
+        assert_eq!(deployments.iter().map(|d| (d.path.clone(), d.reverted)).collect::<Vec<_>>(), vec![
+            (vec![0, 0], true),
+            (vec![1], false),
+        ]);
+    }
+}
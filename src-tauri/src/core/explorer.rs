@@ -0,0 +1,234 @@
+use ethers::types::Address;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use crate::types::{AbiEntry, VerifiedContract};
+use super::cache::{DiskCache, NOT_VERIFIED_TTL};
+use super::contract_registry::ContractRegistry;
+
+/// Number of attempts when the explorer reports a rate limit
+const MAX_RATE_LIMIT_RETRIES: usize = 3;
+
+/// Client for the Etherscan-style `getsourcecode` API
+/// Works with Etherscan, Blockscout, Routescan and anything else serving the same format
+#[derive(Debug, Clone)]
+pub struct ExplorerClient {
+    api_url: String,
+    api_key: Option<String>,
+    chain_id: u64,
+    http: reqwest::Client,
+    cache: Option<DiskCache>,
+    offline: bool,
+}
+
+impl ExplorerClient {
+    pub fn new(api_url: &str, api_key: Option<String>, chain_id: u64) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(20))
+            .build()
+            .unwrap_or_default();
+        Self {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            api_key: api_key.filter(|k| !k.is_empty()),
+            chain_id,
+            http,
+            cache: None,
+            offline: false,
+        }
+    }
+
+    /// Store results on disk so that repeated traces work offline
+    pub fn with_cache(mut self, cache: DiskCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Only answer from the cache
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Cached answers are per explorer, so a custom API URL for a chain does not reuse another's
+    fn cache_namespace(&self) -> String {
+        let host = reqwest::Url::parse(&self.api_url).ok()
+            .and_then(|url| Some(match url.port() {
+                Some(port) => format!("{}_{}", url.host_str()?, port),
+                None => url.host_str()?.to_string(),
+            }))
+            .unwrap_or_else(|| "unknown".to_string());
+        let host: String = host.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+            .collect();
+        format!("etherscan/{}/{}", self.chain_id, host)
+    }
+
+    /// Fetch the verified source of a contract
+    /// Unverified contracts return a `VerifiedContract` with `verified: false`, cached for
+    /// `NOT_VERIFIED_TTL` only (offline mode still uses expired entries)
+    pub async fn source_code(&self, address: Address) -> anyhow::Result<Option<VerifiedContract>> {
+        let key = format!("{:?}", address);
+        let cached = self.cache.as_ref()
+            .and_then(|c| c.get::<VerifiedContract>(&self.cache_namespace(), &key))
+            .filter(|c| self.offline || c.is_fresh(NOT_VERIFIED_TTL));
+        if let Some(cached) = cached {
+            return Ok(Some(cached));
+        }
+        if self.offline {
+            return Ok(None);
+        }
+
+        let result = self.request_source_code(&key).await?;
+        let contract = parse_source_code_result(&key, &result)?;
+
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.put(&self.cache_namespace(), &key, &contract) {
+                println!("[EXPLORER] Failed to cache {}: {}", key, e);
+            }
+        }
+        Ok(Some(contract))
+    }
+
+    async fn request_source_code(&self, address: &str) -> anyhow::Result<Value> {
+        let mut query = vec![
+            ("module", "contract"),
+            ("action", "getsourcecode"),
+            ("address", address),
+        ];
+        if let Some(key) = &self.api_key {
+            query.push(("apikey", key.as_str()));
+        }
+
+        for attempt in 1..=MAX_RATE_LIMIT_RETRIES {
+            let response: Value = self.http.get(&self.api_url)
+                .query(&query)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            let result = response.get("result").cloned().unwrap_or(Value::Null);
+            if let Some(message) = result.as_str() {
+                // Errors are reported as a string result with status "0"
+                if message.to_lowercase().contains("rate limit") && attempt < MAX_RATE_LIMIT_RETRIES {
+                    println!("[EXPLORER] Rate limited, retrying ({}/{})", attempt, MAX_RATE_LIMIT_RETRIES);
+                    tokio::time::sleep(Duration::from_millis(1100)).await;
+                    continue;
+                }
+                return Err(anyhow::anyhow!("Explorer error: {}", message));
+            }
+            return Ok(result);
+        }
+        Err(anyhow::anyhow!("Explorer rate limit exceeded"))
+    }
+
+    /// Look up every address not yet known to the registry and register verified contracts
+    /// Returns the number of contracts registered
+    pub async fn register_verified(&self, addresses: &[Address], registry: &mut ContractRegistry) -> usize {
+        let mut registered = 0;
+        for address in addresses {
+            if registry.get(address).map(|c| c.abi.is_some()).unwrap_or(false) {
+                continue;
+            }
+            match self.source_code(*address).await {
+                Ok(Some(contract)) if contract.verified => {
+                    println!("[EXPLORER] {:?} verified as {}", address, contract.contract_name.as_deref().unwrap_or("?"));
+                    registry.register(contract.to_contract_info());
+                    registered += 1;
+                }
+                Ok(_) => {}
+                Err(e) => println!("[EXPLORER] Failed to fetch source for {:?}: {}", address, e),
+            }
+        }
+        registered
+    }
+}
+
+/// Parse the first item of a `getsourcecode` result
+fn parse_source_code_result(address: &str, result: &Value) -> anyhow::Result<VerifiedContract> {
+    let item = result.as_array()
+        .and_then(|items| items.first())
+        .ok_or_else(|| anyhow::anyhow!("Unexpected getsourcecode response"))?;
+    let field = |name: &str| item.get(name)
+        .and_then(|v| v.as_str())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+
+    let contract_name = field("ContractName");
+    let abi = field("ABI").and_then(|abi| serde_json::from_str::<Vec<AbiEntry>>(&abi).ok());
+    let raw_source = field("SourceCode").unwrap_or_default();
+
+    let mut sources = parse_sources(&raw_source, field("FileName").or_else(|| contract_name.as_ref().map(|n| format!("{}.sol", n))));
+    // Blockscout lists imported files separately
+    if let Some(additional) = item.get("AdditionalSources").and_then(|a| a.as_array()) {
+        for source in additional {
+            if let (Some(name), Some(code)) = (source["Filename"].as_str(), source["SourceCode"].as_str()) {
+                sources.insert(name.to_string(), code.to_string());
+            }
+        }
+    }
+
+    Ok(VerifiedContract {
+        address: address.to_string(),
+        verified: abi.is_some() && !sources.is_empty(),
+        contract_name,
+        compiler_version: field("CompilerVersion"),
+        optimization_used: field("OptimizationUsed").map(|v| v == "1" || v.eq_ignore_ascii_case("true")),
+        runs: field("Runs").and_then(|v| v.parse().ok()),
+        evm_version: field("EVMVersion"),
+        abi,
+        sources,
+        implementation: field("Implementation"),
+        fetched_at: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+/// Split the `SourceCode` field into files
+/// It is either plain Solidity, a `{ path: { content } }` map, or standard JSON input wrapped in `{{ }}`
+fn parse_sources(raw: &str, file_name: Option<String>) -> BTreeMap<String, String> {
+    let mut sources = BTreeMap::new();
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return sources;
+    }
+
+    let json_text = if trimmed.starts_with("{{") && trimmed.ends_with("}}") {
+        Some(&trimmed[1..trimmed.len() - 1])
+    } else if trimmed.starts_with('{') {
+        Some(trimmed)
+    } else {
+        None
+    };
+
+    if let Some(parsed) = json_text.and_then(|text| serde_json::from_str::<Value>(text).ok()) {
+        let files = parsed.get("sources").unwrap_or(&parsed);
+        if let Some(files) = files.as_object() {
+            for (path, file) in files {
+                if let Some(content) = file.get("content").and_then(|c| c.as_str()) {
+                    sources.insert(path.clone(), content.to_string());
+                }
+            }
+        }
+        if !sources.is_empty() {
+            return sources;
+        }
+    }
+
+    sources.insert(file_name.unwrap_or_else(|| "Contract.sol".to_string()), raw.to_string());
+    sources
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caches_per_chain_and_explorer_host() {
+        let etherscan = ExplorerClient::new("https://api.etherscan.io/v2/api?chainid=1", None, 1);
+        let private = ExplorerClient::new("http://127.0.0.1:4000/api/", None, 1);
+        assert_eq!(etherscan.cache_namespace(), "etherscan/1/api.etherscan.io");
+        assert_eq!(private.cache_namespace(), "etherscan/1/127.0.0.1_4000");
+        assert_eq!(ExplorerClient::new("not a url", None, 5).cache_namespace(), "etherscan/5/unknown");
+    }
+}
@@ -0,0 +1,615 @@
+use ethers::types::H256;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::csv;
+use super::gas_profiler::{FrameGas, GasProfile};
+use super::trace_formatter::items;
+
+/// Version of the exported JSON document, bumped whenever its layout changes incompatibly
+pub const EXPORT_SCHEMA_VERSION: u32 = 1;
+/// Call frames listed in Markdown and HTML reports; CSV and JSON exports are never truncated
+const MAX_REPORT_FRAMES: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+    Markdown,
+    Html,
+    /// Gas profile as folded stacks for flamegraph.pl
+    Folded,
+    /// Gas profile as a speedscope file
+    Speedscope,
+    /// Gas profile in the Chrome Trace Event format
+    ChromeTrace,
+}
+
+impl ExportFormat {
+    pub fn parse(format: &str) -> anyhow::Result<Self> {
+        match format.to_lowercase().as_str() {
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "html" => Ok(ExportFormat::Html),
+            "folded" => Ok(ExportFormat::Folded),
+            "speedscope" => Ok(ExportFormat::Speedscope),
+            "chrome" | "chrome-trace" => Ok(ExportFormat::ChromeTrace),
+            other => Err(anyhow::anyhow!("Unsupported export format: {}", other)),
+        }
+    }
+}
+
+/// Write a trace produced by `trace_transaction` to `directory` and return the files written
+/// Files are named after the transaction hash; CSV writes one file per table
+pub fn write_export(trace: &Value, format: ExportFormat, directory: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    // Saved traces are untrusted input, so only a well-formed hash ends up in a file name
+    let stem = match transaction_hash(trace) {
+        Some(hash) => format!("trace-{:?}", hash),
+        None => "trace-unknown".to_string(),
+    };
+    let files: Vec<(String, String)> = match format {
+        ExportFormat::Json => vec![(format!("{}.json", stem), serde_json::to_string_pretty(&versioned_json(trace))?)],
+        ExportFormat::Csv => csv_tables(trace).into_iter()
+            .map(|(table, content)| (format!("{}-{}.csv", stem, table), content))
+            .collect(),
+        ExportFormat::Markdown => vec![(format!("{}.md", stem), Report::from_trace(trace).to_markdown())],
+        ExportFormat::Html => vec![(format!("{}.html", stem), Report::from_trace(trace).to_html(trace))],
+        ExportFormat::Folded => vec![(format!("{}.folded", stem), gas_profile(trace)?.folded_stacks())],
+        ExportFormat::Speedscope => vec![(
+            format!("{}.speedscope.json", stem),
+            serde_json::to_string(&gas_profile(trace)?.speedscope(&stem))?,
+        )],
+        ExportFormat::ChromeTrace => vec![(
+            format!("{}.chrome-trace.json", stem),
+            serde_json::to_string(&gas_profile(trace)?.chrome_trace(&stem))?,
+        )],
+    };
+
+    fs::create_dir_all(directory)?;
+    let mut written = Vec::with_capacity(files.len());
+    for (name, content) in files {
+        let path = directory.join(name);
+        fs::write(&path, content)?;
+        written.push(path);
+    }
+    println!("[EXPORT] Wrote {} file(s) to {}", written.len(), directory.display());
+    Ok(written)
+}
+
+/// Transaction hash of a trace, if it is a valid 32-byte hash
+pub fn transaction_hash(trace: &Value) -> Option<H256> {
+    let hash = trace["overview"]["transactionHash"].as_str()?;
+    let bytes = hex::decode(hash.strip_prefix("0x")?).ok()?;
+    (bytes.len() == 32).then(|| H256::from_slice(&bytes))
+}
+
+/// The trace wrapped with the schema version and export metadata
+pub fn versioned_json(trace: &Value) -> Value {
+    json!({
+        "schemaVersion": EXPORT_SCHEMA_VERSION,
+        "generator": concat!("raliet ", env!("CARGO_PKG_VERSION")),
+        "exportedAt": chrono::Utc::now().to_rfc3339(),
+        "trace": trace,
+    })
+}
+
+/// Gas profile rebuilt from the trace's `gasProfile` frames
+fn gas_profile(trace: &Value) -> anyhow::Result<GasProfile> {
+    let frames = trace["gasProfile"].get("frames")
+        .ok_or_else(|| anyhow::anyhow!("Trace has no gas profile"))?;
+    let frames: Vec<FrameGas> = serde_json::from_value(frames.clone())?;
+    Ok(GasProfile::from_frames(frames))
+}
+
+/// CSV tables of calls, events, transfers and state changes, keyed by table name
+pub fn csv_tables(trace: &Value) -> Vec<(&'static str, String)> {
+    vec![
+        ("calls", calls_csv(trace)),
+        ("events", events_csv(trace)),
+        ("transfers", transfers_csv(trace)),
+        ("state-changes", state_changes_csv(trace)),
+    ]
+}
+
+fn table(header: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut out = csv::format_row(header);
+    out.push('\n');
+    for row in rows {
+        out.push_str(&csv::format_row(&row));
+        out.push('\n');
+    }
+    out
+}
+
+fn calls_csv(trace: &Value) -> String {
+    let rows = frames(&trace["callTree"]).into_iter().map(|frame| vec![
+        path_text(&frame["path"]),
+        text(&frame["depth"]),
+        text(&frame["type"]),
+        text(&frame["from"]),
+        text(&frame["to"]),
+        text(&frame["contract"]),
+        text(&frame["function"]),
+        text(&frame["value"]),
+        text(&frame["gas"]),
+        text(&frame["gasUsed"]),
+        text(&frame["success"]),
+        text(&frame["error"]),
+        text(&frame["revertReason"]),
+        text(&frame["revertStatus"]),
+    ]).collect();
+    table(&["path", "depth", "type", "from", "to", "contract", "function", "value", "gas", "gasUsed", "success", "error", "revertReason", "revertStatus"], rows)
+}
+
+fn events_csv(trace: &Value) -> String {
+    let committed = items(trace, "events").iter().map(|event| (event, false));
+    let reverted = items(trace, "revertedEvents").iter().map(|event| (event, true));
+    let rows = committed.chain(reverted).map(|(event, reverted)| vec![
+        text(&event["index"]),
+        path_text(&event["frame"]),
+        text(&event["position"]),
+        text(&event["address"]),
+        text(&event["contract"]),
+        text(&event["name"]),
+        event["topics"].as_array().into_iter().flatten().map(text).collect::<Vec<_>>().join(" "),
+        text(&event["data"]),
+        reverted.to_string(),
+    ]).collect();
+    table(&["index", "frame", "position", "address", "contract", "name", "topics", "data", "reverted"], rows)
+}
+
+fn transfers_csv(trace: &Value) -> String {
+    let native = trace["network"]["nativeCurrency"]["symbol"].as_str().unwrap_or("ETH").to_string();
+    let tokens = items(trace, "tokenTransfers").iter().map(|transfer| vec![
+        "token".to_string(),
+        text(&transfer["standard"]),
+        text(&transfer["token"]),
+        text(&transfer["symbol"]),
+        text(&transfer["from"]),
+        text(&transfer["to"]),
+        text(&transfer["amount"]),
+        text(&transfer["formattedAmount"]),
+        text(&transfer["tokenId"]),
+        format!("log {}", text(&transfer["logIndex"])),
+        "false".to_string(),
+    ]);
+    let internal = items(trace, "internalTransfers").iter().map(|transfer| vec![
+        "internal".to_string(),
+        text(&transfer["type"]),
+        String::new(),
+        native.clone(),
+        text(&transfer["from"]),
+        text(&transfer["to"]),
+        text(&transfer["valueWei"]),
+        text(&transfer["value"]),
+        String::new(),
+        format!("frame {}", path_text(&transfer["path"])),
+        text(&transfer["reverted"]),
+    ]);
+    table(
+        &["kind", "type", "token", "symbol", "from", "to", "amount", "formattedAmount", "tokenId", "reference", "reverted"],
+        tokens.chain(internal).collect(),
+    )
+}
+
+fn state_changes_csv(trace: &Value) -> String {
+    table(&["address", "contract", "field", "slot", "variable", "before", "after"], state_changes(trace))
+}
+
+/// One row per changed balance, nonce, code or decoded storage variable (raw slot when undecoded)
+fn state_changes(trace: &Value) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    for account in items(trace, "stateDiff") {
+        let row = |field: &str, slot: &Value, variable: &Value, before: String, after: String| vec![
+            text(&account["address"]),
+            text(&account["contract"]),
+            field.to_string(),
+            text(slot),
+            text(variable),
+            before,
+            after,
+        ];
+        if !account["balance"].is_null() {
+            rows.push(row("balance", &Value::Null, &Value::Null, text(&account["balance"]["before"]), text(&account["balance"]["after"])));
+        }
+        if !account["nonce"].is_null() {
+            rows.push(row("nonce", &Value::Null, &Value::Null, text(&account["nonce"]["before"]), text(&account["nonce"]["after"])));
+        }
+        if account["codeChanged"] == true {
+            rows.push(row("code", &Value::Null, &Value::Null, String::new(), "changed".to_string()));
+        }
+        for slot in account["storage"].as_array().into_iter().flatten() {
+            let decoded = slot["decoded"].as_array().map(|d| d.as_slice()).unwrap_or_default();
+            if decoded.is_empty() {
+                rows.push(row("storage", &slot["slot"], &Value::Null, text(&slot["before"]), text(&slot["after"])));
+            }
+            for variable in decoded {
+                rows.push(row("storage", &slot["slot"], &variable["variable"], text(&variable["before"]), text(&variable["after"])));
+            }
+        }
+    }
+    rows
+}
+
+/// A report section's content, rendered to Markdown or HTML
+enum Block {
+    Fields(Vec<(&'static str, String)>),
+    Table { headers: Vec<&'static str>, rows: Vec<Vec<String>> },
+    /// Indented lines, as (depth, text)
+    Tree(Vec<(usize, String)>),
+    Note(String),
+}
+
+struct Section {
+    title: &'static str,
+    blocks: Vec<Block>,
+}
+
+/// Human-readable summary of a trace, shared by the Markdown and HTML exports
+struct Report {
+    title: String,
+    sections: Vec<Section>,
+}
+
+impl Report {
+    fn from_trace(trace: &Value) -> Self {
+        let overview = &trace["overview"];
+        let info = &trace["transactionInfo"];
+        let gas = &trace["gasDetails"];
+        let mut sections = Vec::new();
+
+        let mut fields = vec![
+            ("Status", text(&overview["status"])),
+            ("Transaction", text(&overview["transactionHash"])),
+            ("Block", text(&overview["block"])),
+            ("Time", text(&overview["dateTime"])),
+            ("Network", text(&trace["network"]["name"])),
+            ("From", address(info, "from")),
+            ("To", address(info, "to")),
+            ("Function", text(&info["function"])),
+            ("Value", text(&info["value"])),
+            ("Gas used", format!("{} / {}", text(&gas["gasUsed"]), text(&gas["gasLimit"]))),
+            ("Gas price", text(&gas["effectiveGasPrice"])),
+            ("Fee", text(&gas["totalCost"])),
+        ];
+        fields.retain(|(_, value)| !value.is_empty());
+        sections.push(Section { title: "Overview", blocks: vec![Block::Fields(fields)] });
+
+        let cause = &trace["revertCause"];
+        if cause["reverted"] == true {
+            let mut blocks = vec![Block::Tree(items(cause, "path").iter().map(|frame| (
+                frame["path"].as_array().map(|p| p.len()).unwrap_or(0),
+                format!("{}.{}{}",
+                    frame["contract"].as_str().or_else(|| frame["to"].as_str()).unwrap_or("?"),
+                    text(&frame["function"]),
+                    frame["reason"].as_str().or_else(|| frame["error"].as_str()).map(|r| format!(": {}", r)).unwrap_or_default()
+                ),
+            )).collect())];
+            let caught = items(cause, "caught").len();
+            if caught > 0 {
+                blocks.push(Block::Note(format!("{} caught revert(s) did not affect the outcome.", caught)));
+            }
+            sections.push(Section { title: "Revert", blocks });
+        }
+
+        let warnings = items(trace, "warnings");
+        if !warnings.is_empty() {
+            sections.push(Section { title: "Warnings", blocks: vec![Block::Table {
+                headers: vec!["Severity", "Warning", "Frame", "Reverted"],
+                rows: warnings.iter().map(|w| vec![
+                    text(&w["severity"]).to_uppercase(),
+                    text(&w["message"]),
+                    path_text(&w["path"]),
+                    text(&w["reverted"]),
+                ]).collect(),
+            }] });
+        }
+
+        let all_frames = frames(&trace["callTree"]);
+        if !all_frames.is_empty() {
+            let mut blocks = vec![Block::Tree(all_frames.iter().take(MAX_REPORT_FRAMES).map(|frame| (
+                frame["depth"].as_u64().unwrap_or(0) as usize,
+                format!("{} {}.{}{} ({} gas){}",
+                    text(&frame["type"]),
+                    frame["contract"].as_str().map(str::to_string).unwrap_or_else(|| address(frame, "to")),
+                    text(&frame["function"]),
+                    frame["value"].as_str().filter(|v| *v != "0")
+                        .map(|v| format!(" value {}", frame["formattedValue"].as_str().unwrap_or(v)))
+                        .unwrap_or_default(),
+                    text(&frame["gasUsed"]),
+                    if frame["success"] == false {
+                        format!(" ✗ {}", frame["revertReason"].as_str().or_else(|| frame["error"].as_str()).unwrap_or("failed"))
+                    } else {
+                        String::new()
+                    }
+                ),
+            )).collect())];
+            if all_frames.len() > MAX_REPORT_FRAMES {
+                blocks.push(Block::Note(format!("{} more frames omitted; see the JSON or CSV export.", all_frames.len() - MAX_REPORT_FRAMES)));
+            }
+            sections.push(Section { title: "Call Tree", blocks });
+        }
+
+        let events = items(trace, "events");
+        if !events.is_empty() {
+            sections.push(Section { title: "Events", blocks: vec![Block::Table {
+                headers: vec!["#", "Event", "Emitter", "Frame"],
+                rows: events.iter().map(|e| vec![text(&e["index"]), text(&e["name"]), address(e, "address"), path_text(&e["frame"])]).collect(),
+            }] });
+        }
+
+        let token_transfers = items(trace, "tokenTransfers");
+        if !token_transfers.is_empty() {
+            sections.push(Section { title: "Token Transfers", blocks: vec![Block::Table {
+                headers: vec!["Token", "From", "To", "Amount"],
+                rows: token_transfers.iter().map(|t| vec![
+                    t["symbol"].as_str().map(str::to_string).unwrap_or_else(|| address(t, "token")),
+                    address(t, "from"),
+                    address(t, "to"),
+                    match t["tokenId"].as_str() {
+                        Some(id) => format!("{} of #{}", text(&t["formattedAmount"]), id),
+                        None => text(&t["formattedAmount"]),
+                    },
+                ]).collect(),
+            }] });
+        }
+
+        let internal = items(trace, "internalTransfers");
+        if !internal.is_empty() {
+            sections.push(Section { title: "Internal Transfers", blocks: vec![Block::Table {
+                headers: vec!["Type", "From", "To", "Amount", "Frame"],
+                rows: internal.iter().map(|t| vec![
+                    text(&t["type"]),
+                    address(t, "from"),
+                    address(t, "to"),
+                    format!("{}{}", text(&t["value"]), if t["reverted"] == true { " (reverted)" } else { "" }),
+                    path_text(&t["path"]),
+                ]).collect(),
+            }] });
+        }
+
+        let balances = items(trace, "balanceChanges");
+        if !balances.is_empty() {
+            sections.push(Section { title: "Balance Changes", blocks: vec![Block::Table {
+                headers: vec!["Address", "Changes"],
+                rows: balances.iter().map(|b| vec![
+                    address(b, "address"),
+                    b["changes"].as_array().into_iter().flatten()
+                        .map(|c| format!("{} {}", text(&c["formattedAmount"]), c["symbol"].as_str().unwrap_or_default()).trim().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ]).collect(),
+            }] });
+        }
+
+        let state = state_changes(trace);
+        if !state.is_empty() {
+            sections.push(Section { title: "State Changes", blocks: vec![Block::Table {
+                headers: vec!["Address", "Field", "Before", "After"],
+                rows: state.into_iter().map(|row| {
+                    let contract = if row[1].is_empty() { row[0].clone() } else { format!("{} ({})", row[1], row[0]) };
+                    let field = match (row[4].is_empty(), row[3].is_empty()) {
+                        (false, _) => row[4].clone(),
+                        (true, false) => format!("slot {}", row[3]),
+                        _ => row[2].clone(),
+                    };
+                    vec![contract, field, row[5].clone(), row[6].clone()]
+                }).collect(),
+            }] });
+        }
+
+        Self {
+            title: format!("Transaction {}", text(&overview["transactionHash"])),
+            sections,
+        }
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut out = format!("# {}\n", self.title);
+        for section in &self.sections {
+            out.push_str(&format!("\n## {}\n", section.title));
+            for block in &section.blocks {
+                out.push('\n');
+                match block {
+                    Block::Fields(fields) => {
+                        out.push_str("| | |\n|---|---|\n");
+                        for (name, value) in fields {
+                            out.push_str(&format!("| **{}** | {} |\n", name, markdown_cell(value)));
+                        }
+                    }
+                    Block::Table { headers, rows } => {
+                        out.push_str(&format!("| {} |\n", headers.join(" | ")));
+                        out.push_str(&format!("|{}\n", "---|".repeat(headers.len())));
+                        for row in rows {
+                            let cells: Vec<String> = row.iter().map(|cell| markdown_cell(cell)).collect();
+                            out.push_str(&format!("| {} |\n", cells.join(" | ")));
+                        }
+                    }
+                    Block::Tree(lines) => {
+                        for (depth, line) in lines {
+                            out.push_str(&format!("{}- `{}`\n", "  ".repeat(*depth), line.replace('`', "'")));
+                        }
+                    }
+                    Block::Note(note) => out.push_str(&format!("_{}_\n", note)),
+                }
+            }
+        }
+        out.push_str(&format!("\n---\nExported by raliet {} (schema {})\n", env!("CARGO_PKG_VERSION"), EXPORT_SCHEMA_VERSION));
+        out
+    }
+
+    /// A standalone page: styles are inlined and the full trace is embedded for reference
+    fn to_html(&self, trace: &Value) -> String {
+        let mut body = format!("<h1>{}</h1>\n", html_escape(&self.title));
+        for section in &self.sections {
+            body.push_str(&format!("<section>\n<h2>{}</h2>\n", html_escape(section.title)));
+            for block in &section.blocks {
+                match block {
+                    Block::Fields(fields) => {
+                        body.push_str("<table class=\"fields\">\n");
+                        for (name, value) in fields {
+                            body.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", html_escape(name), html_escape(value)));
+                        }
+                        body.push_str("</table>\n");
+                    }
+                    Block::Table { headers, rows } => {
+                        body.push_str("<table>\n<tr>");
+                        for header in headers {
+                            body.push_str(&format!("<th>{}</th>", html_escape(header)));
+                        }
+                        body.push_str("</tr>\n");
+                        for row in rows {
+                            body.push_str("<tr>");
+                            for cell in row {
+                                body.push_str(&format!("<td>{}</td>", html_escape(cell)));
+                            }
+                            body.push_str("</tr>\n");
+                        }
+                        body.push_str("</table>\n");
+                    }
+                    Block::Tree(lines) => {
+                        body.push_str("<div class=\"tree\">\n");
+                        for (depth, line) in lines {
+                            body.push_str(&format!("<div style=\"padding-left: {}em\">{}</div>\n", depth * 2, html_escape(line)));
+                        }
+                        body.push_str("</div>\n");
+                    }
+                    Block::Note(note) => body.push_str(&format!("<p class=\"note\">{}</p>\n", html_escape(note))),
+                }
+            }
+            body.push_str("</section>\n");
+        }
+        let raw = serde_json::to_string_pretty(&versioned_json(trace)).unwrap_or_default();
+        body.push_str(&format!("<details>\n<summary>Full trace (JSON)</summary>\n<pre>{}</pre>\n</details>\n", html_escape(&raw)));
+
+        format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}<footer>Exported by raliet {} (schema {})</footer>\n</body>\n</html>\n",
+            html_escape(&self.title),
+            HTML_STYLE,
+            body,
+            env!("CARGO_PKG_VERSION"),
+            EXPORT_SCHEMA_VERSION,
+        )
+    }
+}
+
+const HTML_STYLE: &str = "
+body { font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', sans-serif; margin: 2em auto; max-width: 1100px; padding: 0 1em; color: #1f2328; }
+h1 { font-size: 1.4em; word-break: break-all; }
+h2 { font-size: 1.15em; border-bottom: 1px solid #d0d7de; padding-bottom: 0.3em; margin-top: 1.8em; }
+table { border-collapse: collapse; width: 100%; font-size: 0.9em; }
+th, td { border: 1px solid #d0d7de; padding: 0.35em 0.6em; text-align: left; vertical-align: top; word-break: break-all; }
+th { background: #f6f8fa; }
+table.fields th { width: 12em; }
+.tree, pre { font-family: ui-monospace, SFMono-Regular, Menlo, monospace; font-size: 0.85em; }
+.tree div { white-space: pre-wrap; padding-top: 0.1em; padding-bottom: 0.1em; }
+pre { background: #f6f8fa; padding: 1em; overflow: auto; max-height: 40em; }
+.note, footer { color: #59636e; font-size: 0.85em; }
+footer { margin-top: 2em; }
+";
+
+/// Frames of a formatted call tree in depth-first order
+fn frames(root: &Value) -> Vec<&Value> {
+    let mut out = Vec::new();
+    let mut stack = vec![root];
+    while let Some(frame) = stack.pop() {
+        if frame.is_null() {
+            continue;
+        }
+        out.push(frame);
+        stack.extend(items(frame, "calls").iter().rev());
+    }
+    out
+}
+
+/// Strings as-is, null as empty and anything else as JSON
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Address with its label, as added to the trace by the address book
+fn address(object: &Value, key: &str) -> String {
+    let address = text(&object[key]);
+    match object[format!("{}Label", key)].as_str() {
+        Some(label) if !address.is_empty() => format!("{} ({})", address, label),
+        _ => address,
+    }
+}
+
+/// Call tree path as "1.0", "root" for the top-level frame
+fn path_text(path: &Value) -> String {
+    match path.as_array() {
+        Some(path) if path.is_empty() => "root".to_string(),
+        Some(path) => path.iter().map(text).collect::<Vec<_>>().join("."),
+        None => String::new(),
+    }
+}
+
+fn markdown_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace('\n', " ")
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060";
+
+    fn export_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("raliet-export-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn file_names(paths: &[PathBuf]) -> Vec<String> {
+        paths.iter().filter_map(|p| p.file_name()?.to_str().map(str::to_string)).collect()
+    }
+
+    #[test]
+    fn names_files_after_valid_transaction_hash() {
+        let dir = export_dir("valid");
+        let trace = json!({ "overview": { "transactionHash": HASH } });
+        let written = write_export(&trace, ExportFormat::Json, &dir).unwrap();
+
+        assert_eq!(file_names(&written), vec![format!("trace-{}.json", HASH)]);
+        let document: Value = serde_json::from_str(&fs::read_to_string(&written[0]).unwrap()).unwrap();
+        assert_eq!(document["schemaVersion"], EXPORT_SCHEMA_VERSION);
+        assert_eq!(document["trace"], trace);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn names_files_unknown_without_valid_hash() {
+        for hash in [json!("../../outside"), json!("0xabc"), json!(format!("{}00", HASH)), Value::Null] {
+            let dir = export_dir("invalid");
+            let trace = json!({ "overview": { "transactionHash": hash } });
+            let written = write_export(&trace, ExportFormat::Csv, &dir).unwrap();
+
+            assert!(!written.is_empty());
+            assert!(written.iter().all(|p| p.parent() == Some(dir.as_path())));
+            assert!(file_names(&written).iter().all(|name| name.starts_with("trace-unknown-") && name.ends_with(".csv")));
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn parses_transaction_hash() {
+        assert_eq!(transaction_hash(&json!({ "overview": { "transactionHash": HASH } })), HASH.parse().ok());
+        assert_eq!(transaction_hash(&json!({ "overview": { "transactionHash": HASH.trim_start_matches("0x") } })), None);
+        assert_eq!(transaction_hash(&json!({})), None);
+    }
+}
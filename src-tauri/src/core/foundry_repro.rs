@@ -0,0 +1,308 @@
+use ethers::types::{Address, H256, U256};
+use ethers::utils::to_checksum;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::types::ReproOptions;
+use super::export::transaction_hash;
+
+const DEFAULT_RPC_ENV: &str = "ETH_RPC_URL";
+/// Base cost of a transaction, and of calldata bytes before EIP-7623
+const TX_BASE_GAS: u64 = 21_000;
+const TX_DATA_ZERO_GAS: u64 = 4;
+const TX_DATA_NON_ZERO_GAS: u64 = 16;
+const TX_CREATE_GAS: u64 = 32_000;
+
+/// A generated Foundry test replaying a transaction
+#[derive(Debug, Clone)]
+pub struct FoundryTest {
+    pub file_name: String,
+    pub source: String,
+}
+
+/// Generate a forge test that forks before the transaction and replays its call from the sender
+pub fn generate_foundry_test(trace: &Value, options: &ReproOptions) -> anyhow::Result<FoundryTest> {
+    let info = &trace["transactionInfo"];
+    let overview = &trace["overview"];
+    // The hash ends up in the file name and the source, so it must be a real one
+    let tx_hash = format!("{:?}", transaction_hash(trace).ok_or_else(|| anyhow::anyhow!("Trace has no valid transaction hash"))?);
+    let from = address(&info["from"]).ok_or_else(|| anyhow::anyhow!("Trace has no sender"))?;
+    let to = address(&info["to"]);
+    let input = info["input"].as_str()
+        .or_else(|| trace["callTree"]["input"].as_str())
+        .ok_or_else(|| anyhow::anyhow!("Trace has no calldata"))?;
+    let input = hex::decode(input.trim_start_matches("0x"))?;
+    let value = uint(&info["valueWei"]).unwrap_or_default();
+    let gas_limit = uint(&trace["gasDetails"]["gasLimit"]).map(|g| g.low_u64());
+    let block = uint(&overview["block"]).map(|b| b.low_u64())
+        .ok_or_else(|| anyhow::anyhow!("Trace has no block number"))?;
+    let fork_block = options.fork_block.unwrap_or(block.saturating_sub(1));
+    let rpc_env = options.rpc_env.as_deref().unwrap_or(DEFAULT_RPC_ENV);
+    // Pasted into a Solidity string literal, so only plain variable names are accepted
+    if !is_env_var_name(rpc_env) {
+        return Err(anyhow::anyhow!("Invalid RPC environment variable name {:?}", rpc_env));
+    }
+    let success = overview["status"].as_str().map(|s| s.contains("Success")).unwrap_or(false);
+
+    let short_hash = tx_hash.trim_start_matches("0x").chars().take(8).collect::<String>();
+    let contract_name = format!("Replay_{}Test", short_hash);
+    let mut src = String::new();
+    let mut line = |indent: usize, text: &str| {
+        src.push_str(&"    ".repeat(indent));
+        src.push_str(text);
+        src.push('\n');
+    };
+
+    line(0, "// SPDX-License-Identifier: UNLICENSED");
+    line(0, "pragma solidity ^0.8.13;");
+    line(0, "");
+    line(0, "import {Test, Vm} from \"forge-std/Test.sol\";");
+    line(0, "");
+    line(0, &format!("/// Replays transaction {}", tx_hash));
+    if let Some(network) = trace["network"]["name"].as_str() {
+        line(0, &format!("/// on {} (chain ID {})", comment(network), trace["network"]["chainId"].as_u64().unwrap_or_default()));
+    }
+    line(0, &format!("/// Generated by raliet {}", env!("CARGO_PKG_VERSION")));
+    line(0, &format!("contract {} is Test {{", contract_name));
+    line(1, &format!("address constant FROM = {};", to_checksum(&from, None)));
+    if let Some(to) = to {
+        line(1, &format!("address constant TO = {};", to_checksum(&to, None)));
+    }
+    line(1, &format!("uint256 constant VALUE = {};", value));
+    if let Some(gas) = gas_limit {
+        let intrinsic = intrinsic_gas(&input, to.is_none());
+        line(1, &format!("// Gas limit {} minus the intrinsic cost of the calldata{}", gas, if to.is_none() { " and contract creation" } else { "" }));
+        line(1, &format!("uint256 constant GAS = {};", gas.saturating_sub(intrinsic)));
+    }
+    line(0, "");
+    line(1, "function setUp() public {");
+    match trace["overview"]["transactionIndex"].as_u64().filter(|i| *i > 0 && options.fork_block.is_none()) {
+        Some(index) => {
+            line(2, &format!("// State at the end of block {}; the {} transaction(s) before this one in block {} are not replayed.", fork_block, index, block));
+            line(2, &format!("// Use vm.createSelectFork(vm.envString(\"{}\"), bytes32({})) to include them.", rpc_env, tx_hash));
+        }
+        None => line(2, &format!("// State at the end of block {}", fork_block)),
+    }
+    line(2, &format!("vm.createSelectFork(vm.envString(\"{}\"), {});", rpc_env, fork_block));
+    line(1, "}");
+    line(0, "");
+    line(1, &format!("function test_replay_{}() public {{", short_hash));
+    if !value.is_zero() {
+        line(2, "if (FROM.balance < VALUE) vm.deal(FROM, VALUE);");
+    }
+    if options.assert_events {
+        line(2, "vm.recordLogs();");
+    }
+    line(2, "vm.prank(FROM, FROM);");
+    let gas = if gas_limit.is_some() { ", gas: GAS" } else { "" };
+    // A failed transaction also checks its revert data, when there is any
+    let revert_data = bytes(&trace["callTree"]["output"])
+        .filter(|o| !success && to.is_some() && !o.is_empty());
+    match to {
+        Some(_) => {
+            if let Some(function) = info["function"].as_str() {
+                let target = info["contract"].as_str().map(|c| format!(" on {}", c)).unwrap_or_default();
+                line(2, &format!("// {}{}", comment(function), comment(&target)));
+            }
+            let return_data = if revert_data.is_some() { "bytes memory returnData" } else { "" };
+            line(2, &format!("(bool success, {}) = TO.call{{value: VALUE{}}}(", return_data, gas));
+            line(3, &format!("hex\"{}\"", hex::encode(&input)));
+            line(2, ");");
+        }
+        None => {
+            line(2, &format!("bytes memory initCode = hex\"{}\";", hex::encode(&input)));
+            line(2, "address deployed;");
+            line(2, "assembly {");
+            line(3, "deployed := create(VALUE, add(initCode, 0x20), mload(initCode))");
+            line(2, "}");
+            line(2, "bool success = deployed != address(0);");
+        }
+    }
+    line(0, "");
+
+    if success {
+        line(2, "assertTrue(success, \"transaction reverted\");");
+    } else {
+        line(2, "assertFalse(success, \"transaction no longer reverts\");");
+        if let Some(output) = revert_data {
+            if let Some(reason) = trace["callTree"]["revertReason"].as_str() {
+                line(2, &format!("// {}", comment(reason)));
+            }
+            line(2, &format!("assertEq(returnData, hex\"{}\");", hex::encode(output)));
+        }
+    }
+
+    let events = trace["events"].as_array().map(|e| e.as_slice()).unwrap_or_default();
+    if options.assert_events && !events.is_empty() {
+        line(0, "");
+        line(2, "// Expected events in order; recorded logs may also hold those of reverted calls");
+        line(2, "Vm.Log[] memory logs = vm.getRecordedLogs();");
+        line(2, "bytes32[] memory topics;");
+        line(2, "uint256 cursor;");
+        for event in events {
+            let topics: Vec<H256> = event["topics"].as_array().into_iter().flatten()
+                .filter_map(|t| t.as_str()?.parse().ok())
+                .collect();
+            let Some(emitter) = address(&event["address"]) else { continue };
+            line(2, &format!("// {}", comment(event["name"].as_str().unwrap_or("unknown event"))));
+            line(2, &format!("topics = new bytes32[]({});", topics.len()));
+            for (idx, topic) in topics.iter().enumerate() {
+                line(2, &format!("topics[{}] = {:?};", idx, topic));
+            }
+            line(2, &format!(
+                "cursor = _expectLog(logs, cursor, {}, topics, hex\"{}\");",
+                to_checksum(&emitter, None),
+                hex::encode(bytes(&event["data"]).unwrap_or_default())
+            ));
+        }
+    }
+    line(1, "}");
+
+    if options.assert_events && !events.is_empty() {
+        line(0, "");
+        line(1, "/// Index after the first log at or after `start` matching the expected one");
+        line(1, "function _expectLog(Vm.Log[] memory logs, uint256 start, address emitter, bytes32[] memory topics, bytes memory data)");
+        line(2, "internal");
+        line(2, "pure");
+        line(2, "returns (uint256)");
+        line(1, "{");
+        line(2, "for (uint256 i = start; i < logs.length; i++) {");
+        line(3, "if (");
+        line(4, "logs[i].emitter == emitter && keccak256(abi.encode(logs[i].topics)) == keccak256(abi.encode(topics))");
+        line(5, "&& keccak256(logs[i].data) == keccak256(data)");
+        line(3, ") return i + 1;");
+        line(2, "}");
+        line(2, "revert(\"expected event not emitted\");");
+        line(1, "}");
+    }
+    line(0, "}");
+
+    Ok(FoundryTest { file_name: format!("Replay_{}.t.sol", short_hash), source: src })
+}
+
+/// Write the generated test to `directory` and return its path
+pub fn write_foundry_test(trace: &Value, options: &ReproOptions, directory: &Path) -> anyhow::Result<PathBuf> {
+    let test = generate_foundry_test(trace, options)?;
+    fs::create_dir_all(directory)?;
+    let path = directory.join(&test.file_name);
+    fs::write(&path, test.source)?;
+    println!("[EXPORT] Wrote Foundry test to {}", path.display());
+    Ok(path)
+}
+
+/// Gas charged before execution starts, without access list costs or the EIP-7623 floor
+fn intrinsic_gas(input: &[u8], create: bool) -> u64 {
+    let data: u64 = input.iter().map(|b| if *b == 0 { TX_DATA_ZERO_GAS } else { TX_DATA_NON_ZERO_GAS }).sum();
+    TX_BASE_GAS + data + if create { TX_CREATE_GAS + 2 * (input.len() as u64).div_ceil(32) } else { 0 }
+}
+
+/// `[A-Za-z_][A-Za-z0-9_]*`
+fn is_env_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Text from the trace made safe for a single-line Solidity comment
+fn comment(text: &str) -> String {
+    text.chars().map(|c| if c.is_control() { ' ' } else { c }).collect()
+}
+
+fn bytes(value: &Value) -> Option<Vec<u8>> {
+    hex::decode(value.as_str()?.trim_start_matches("0x")).ok()
+}
+
+fn address(value: &Value) -> Option<Address> {
+    value.as_str()?.parse().ok()
+}
+
+/// Decimal or 0x-prefixed hex number, as a JSON string or number
+fn uint(value: &Value) -> Option<U256> {
+    match value {
+        Value::Number(n) => n.as_u64().map(U256::from),
+        Value::String(s) if s.starts_with("0x") => U256::from_str_radix(&s[2..], 16).ok(),
+        Value::String(s) => U256::from_dec_str(s).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const HASH: &str = "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060";
+
+    /// Call with 4 non-zero and 2 zero calldata bytes: 21000 + 4 * 16 + 2 * 4 intrinsic gas
+    fn trace(status: &str, value: &str, output: &str) -> Value {
+        json!({
+            "overview": { "transactionHash": HASH, "block": 100, "status": status, "transactionIndex": 0 },
+            "transactionInfo": {
+                "from": "0x1111111111111111111111111111111111111111",
+                "to": "0x2222222222222222222222222222222222222222",
+                "input": "0x123456780000",
+                "valueWei": value,
+            },
+            "gasDetails": { "gasLimit": 100000 },
+            "callTree": { "output": output },
+        })
+    }
+
+    fn source(trace: &Value, options: &ReproOptions) -> String {
+        generate_foundry_test(trace, options).unwrap().source
+    }
+
+    #[test]
+    fn replays_successful_call_from_previous_block() {
+        let test = generate_foundry_test(&trace("Success", "0", "0x"), &ReproOptions::default()).unwrap();
+
+        assert_eq!(test.file_name, "Replay_5c504ed4.t.sol");
+        assert!(test.source.contains("vm.createSelectFork(vm.envString(\"ETH_RPC_URL\"), 99);"));
+        assert!(test.source.contains("uint256 constant GAS = 78928;"));
+        assert!(test.source.contains("TO.call{value: VALUE, gas: GAS}("));
+        assert!(test.source.contains("hex\"123456780000\""));
+        assert!(test.source.contains("assertTrue(success, \"transaction reverted\");"));
+        assert!(!test.source.contains("vm.deal"));
+        assert!(!test.source.contains("returnData"));
+    }
+
+    #[test]
+    fn checks_revert_data_of_failed_call_with_value() {
+        let source = source(&trace("Reverted", "1000", "0xdeadbeef"), &ReproOptions::default());
+
+        assert!(source.contains("uint256 constant VALUE = 1000;"));
+        assert!(source.contains("if (FROM.balance < VALUE) vm.deal(FROM, VALUE);"));
+        assert!(source.contains("(bool success, bytes memory returnData) = TO.call"));
+        assert!(source.contains("assertFalse(success, \"transaction no longer reverts\");"));
+        assert!(source.contains("assertEq(returnData, hex\"deadbeef\");"));
+    }
+
+    #[test]
+    fn charges_creation_in_intrinsic_gas() {
+        assert_eq!(intrinsic_gas(&[0x12, 0x00], false), 21_000 + 16 + 4);
+        // Plus 32000 and 2 gas per word of init code (EIP-3860)
+        assert_eq!(intrinsic_gas(&[0x12; 33], true), 21_000 + 33 * 16 + 32_000 + 2 * 2);
+    }
+
+    #[test]
+    fn honours_fork_block_and_rpc_env() {
+        let options = ReproOptions { rpc_env: Some("MAINNET_RPC_2".to_string()), fork_block: Some(42), assert_events: false };
+        let source = source(&trace("Success", "0", "0x"), &options);
+        assert!(source.contains("vm.createSelectFork(vm.envString(\"MAINNET_RPC_2\"), 42);"));
+    }
+
+    #[test]
+    fn rejects_unsafe_rpc_env() {
+        for name in ["RPC\"), 1); vm.ffi(cmd); (\"", "RPC\\URL", "1RPC", "", "RPC URL"] {
+            let options = ReproOptions { rpc_env: Some(name.to_string()), ..Default::default() };
+            assert!(generate_foundry_test(&trace("Success", "0", "0x"), &options).is_err(), "{:?} accepted", name);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_transaction_hash() {
+        let mut trace = trace("Success", "0", "0x");
+        trace["overview"]["transactionHash"] = json!("0x1234\\nevil");
+        assert!(generate_foundry_test(&trace, &ReproOptions::default()).is_err());
+    }
+}
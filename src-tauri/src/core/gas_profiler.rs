@@ -0,0 +1,510 @@
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use crate::types::serialize_address;
+use super::call_tree::CallNode;
+use super::contract_registry::ContractRegistry;
+use super::precompiles::precompile;
+
+/// Number of entries in each hotspot list of the trace output
+pub const DEFAULT_TOP_N: usize = 10;
+
+/// Gas of a single call frame
+/// The root frame's gas also covers the intrinsic cost (21000 + calldata) of the transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameGas {
+    pub path: Vec<usize>,
+    pub depth: usize,
+    #[serde(rename = "type")]
+    pub call_type: String,
+    #[serde(serialize_with = "serialize_address")]
+    pub address: Option<Address>,
+    pub contract: Option<String>,
+    /// Function signature, or the raw selector when the ABI is unknown
+    pub function: String,
+    /// Gas used by the frame and everything it called
+    pub inclusive: u64,
+    /// Gas used by the frame's own code
+    pub exclusive: u64,
+    pub success: bool,
+}
+
+impl FrameGas {
+    /// "Contract.function" or "0xaddress.function"
+    pub fn name(&self) -> String {
+        let contract = match (&self.contract, self.address) {
+            (Some(name), _) => name.clone(),
+            (None, Some(address)) => format!("{:?}", address),
+            (None, None) => "unknown".to_string(),
+        };
+        format!("{}.{}", contract, self.function)
+    }
+}
+
+/// Gas summed over the frames sharing a key
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GasAggregate {
+    pub key: String,
+    pub calls: usize,
+    /// Frames nested in a frame with the same key are not counted twice
+    pub inclusive: u64,
+    pub exclusive: u64,
+}
+
+/// Inclusive and exclusive gas of every frame of a call tree
+#[derive(Debug, Clone)]
+pub struct GasProfile {
+    /// Frames in depth-first order
+    frames: Vec<FrameGas>,
+    /// Index of each frame's parent in `frames`
+    parents: Vec<Option<usize>>,
+}
+
+impl GasProfile {
+    pub fn from_call_tree(root: &CallNode, registry: &ContractRegistry) -> Self {
+        let mut profile = Self { frames: Vec::new(), parents: Vec::new() };
+        let mut stack: Vec<usize> = Vec::new();
+        root.walk(&mut |path, node| {
+            stack.truncate(path.len());
+            let children: u64 = node.calls.iter().map(|c| c.gas_used.low_u64()).sum();
+            let inclusive = node.gas_used.low_u64();
+            profile.parents.push(stack.last().copied());
+            stack.push(profile.frames.len());
+            profile.frames.push(FrameGas {
+                path: path.to_vec(),
+                depth: path.len(),
+                call_type: node.call_type.clone(),
+                address: node.to,
+                contract: node.to.and_then(|to| registry.name(&to).map(str::to_string)),
+                function: function_label(node, registry),
+                inclusive,
+                exclusive: inclusive.saturating_sub(children),
+                success: node.is_success(),
+            });
+        });
+        profile
+    }
+
+    /// Rebuild a profile from the `frames` of its JSON output, e.g. from a saved trace
+    pub fn from_frames(frames: Vec<FrameGas>) -> Self {
+        let mut indices: HashMap<Vec<usize>, usize> = HashMap::new();
+        let parents = frames.iter().enumerate().map(|(index, frame)| {
+            indices.insert(frame.path.clone(), index);
+            frame.path.split_last().and_then(|(_, parent)| indices.get(parent).copied())
+        }).collect();
+        Self { frames, parents }
+    }
+
+    pub fn frames(&self) -> &[FrameGas] {
+        &self.frames
+    }
+
+    pub fn total(&self) -> u64 {
+        self.frames.first().map(|f| f.inclusive).unwrap_or_default()
+    }
+
+    /// Gas per contract address
+    pub fn by_contract(&self) -> Vec<GasAggregate> {
+        self.aggregate(|frame| match (&frame.contract, frame.address) {
+            (Some(name), Some(address)) => format!("{} ({:?})", name, address),
+            (None, Some(address)) => format!("{:?}", address),
+            _ => "unknown".to_string(),
+        })
+    }
+
+    /// Gas per called function, across contracts
+    pub fn by_function(&self) -> Vec<GasAggregate> {
+        self.aggregate(|frame| frame.function.clone())
+    }
+
+    /// Gas per call depth
+    pub fn by_depth(&self) -> Vec<GasAggregate> {
+        let mut aggregates = self.aggregate(|frame| frame.depth.to_string());
+        aggregates.sort_by_key(|a| a.key.parse::<usize>().unwrap_or_default());
+        aggregates
+    }
+
+    /// Aggregate frames by key, sorted by exclusive gas (highest first)
+    fn aggregate<F: Fn(&FrameGas) -> String>(&self, key_of: F) -> Vec<GasAggregate> {
+        let keys: Vec<String> = self.frames.iter().map(&key_of).collect();
+        let mut aggregates: HashMap<&str, GasAggregate> = HashMap::new();
+        for (index, frame) in self.frames.iter().enumerate() {
+            let key = keys[index].as_str();
+            let entry = aggregates.entry(key).or_insert_with(|| GasAggregate { key: key.to_string(), ..Default::default() });
+            entry.calls += 1;
+            entry.exclusive += frame.exclusive;
+            if !self.ancestors(index).any(|ancestor| keys[ancestor] == key) {
+                entry.inclusive += frame.inclusive;
+            }
+        }
+        let mut aggregates: Vec<GasAggregate> = aggregates.into_values().collect();
+        aggregates.sort_by(|a, b| b.exclusive.cmp(&a.exclusive).then_with(|| a.key.cmp(&b.key)));
+        aggregates
+    }
+
+    fn ancestors(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(self.parents[index], move |&i| self.parents[i])
+    }
+
+    /// Frames using the most gas in their own code
+    pub fn hotspots(&self, n: usize) -> Vec<&FrameGas> {
+        let mut frames: Vec<&FrameGas> = self.frames.iter().collect();
+        frames.sort_by_key(|frame| std::cmp::Reverse(frame.exclusive));
+        frames.truncate(n);
+        frames
+    }
+
+    /// Nested `{name, value, children}` nodes (d3-flame-graph format), valued by inclusive gas
+    pub fn flame_graph(&self) -> Value {
+        let mut children: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (index, parent) in self.parents.iter().enumerate() {
+            if let Some(parent) = parent {
+                children.entry(*parent).or_default().push(index);
+            }
+        }
+        if self.frames.is_empty() {
+            return Value::Null;
+        }
+        self.flame_node(0, &children)
+    }
+
+    fn flame_node(&self, index: usize, children: &BTreeMap<usize, Vec<usize>>) -> Value {
+        let frame = &self.frames[index];
+        let nested: Vec<Value> = children.get(&index)
+            .into_iter()
+            .flatten()
+            .map(|child| self.flame_node(*child, children))
+            .collect();
+        json!({
+            "name": frame.name(),
+            "value": frame.inclusive,
+            "self": frame.exclusive,
+            "path": frame.path,
+            "success": frame.success,
+            "children": nested,
+        })
+    }
+
+    /// Start and end of each frame on a gas axis: children are laid out one after the other from
+    /// their parent's start, and a frame spans at least its inclusive gas
+    fn spans(&self) -> Vec<(u64, u64)> {
+        let mut extents: Vec<u64> = self.frames.iter().map(|f| f.inclusive).collect();
+        let mut children_total = vec![0u64; self.frames.len()];
+        for index in (0..self.frames.len()).rev() {
+            extents[index] = extents[index].max(children_total[index]);
+            if let Some(parent) = self.parents[index] {
+                children_total[parent] += extents[index];
+            }
+        }
+        let mut spans = Vec::with_capacity(self.frames.len());
+        let mut cursors = vec![0u64; self.frames.len()];
+        for (index, parent) in self.parents.iter().enumerate() {
+            let start = match parent {
+                Some(parent) => {
+                    let start = cursors[*parent];
+                    cursors[*parent] += extents[index];
+                    start
+                }
+                None => 0,
+            };
+            cursors[index] = start;
+            spans.push((start, start + extents[index]));
+        }
+        spans
+    }
+
+    /// Stack of frame names from the root, for flame graph tools
+    fn stack(&self, index: usize) -> Vec<String> {
+        let mut stack: Vec<String> = std::iter::once(index).chain(self.ancestors(index))
+            .map(|i| self.frames[i].name().replace(';', ","))
+            .collect();
+        stack.reverse();
+        stack
+    }
+
+    /// Folded stacks for flamegraph.pl and inferno: one `root;child;frame <exclusive gas>` line per frame
+    pub fn folded_stacks(&self) -> String {
+        self.frames.iter().enumerate()
+            .filter(|(_, frame)| frame.exclusive > 0)
+            .map(|(index, frame)| format!("{} {}\n", self.stack(index).join(";"), frame.exclusive))
+            .collect()
+    }
+
+    /// Evented speedscope profile, with gas as the time unit
+    pub fn speedscope(&self, name: &str) -> Value {
+        let mut names: Vec<String> = Vec::new();
+        let mut frame_ids: HashMap<String, usize> = HashMap::new();
+        let ids: Vec<usize> = self.frames.iter().map(|frame| {
+            let name = frame.name();
+            *frame_ids.entry(name.clone()).or_insert_with(|| {
+                names.push(name);
+                names.len() - 1
+            })
+        }).collect();
+
+        // Frames are in depth-first order, so a frame closes before the next one that is not its descendant opens
+        let spans = self.spans();
+        let mut events = Vec::with_capacity(self.frames.len() * 2);
+        let mut open: Vec<usize> = Vec::new();
+        for (index, (start, _)) in spans.iter().enumerate() {
+            while let Some(&top) = open.last() {
+                if self.ancestors(index).any(|a| a == top) {
+                    break;
+                }
+                events.push(json!({ "type": "C", "frame": ids[top], "at": spans[top].1 }));
+                open.pop();
+            }
+            events.push(json!({ "type": "O", "frame": ids[index], "at": start }));
+            open.push(index);
+        }
+        while let Some(top) = open.pop() {
+            events.push(json!({ "type": "C", "frame": ids[top], "at": spans[top].1 }));
+        }
+
+        json!({
+            "$schema": "https://www.speedscope.app/file-format-schema.json",
+            "name": name,
+            "exporter": concat!("raliet ", env!("CARGO_PKG_VERSION")),
+            "activeProfileIndex": 0,
+            "shared": { "frames": names.iter().map(|name| json!({ "name": name })).collect::<Vec<_>>() },
+            "profiles": [{
+                "type": "evented",
+                "name": name,
+                "unit": "none",
+                "startValue": 0,
+                "endValue": spans.first().map(|s| s.1).unwrap_or_default(),
+                "events": events,
+            }],
+        })
+    }
+
+    /// Chrome Trace Event format (chrome://tracing, Perfetto): one complete event per frame,
+    /// with one gas unit shown as one microsecond
+    pub fn chrome_trace(&self, name: &str) -> Value {
+        let events: Vec<Value> = self.frames.iter().zip(self.spans()).map(|(frame, (start, end))| json!({
+            "name": frame.name(),
+            "cat": frame.call_type,
+            "ph": "X",
+            "ts": start,
+            "dur": end - start,
+            "pid": 1,
+            "tid": 1,
+            "args": {
+                "path": frame.path,
+                "address": frame.address.map(|a| format!("{:?}", a)),
+                "inclusiveGas": frame.inclusive,
+                "exclusiveGas": frame.exclusive,
+                "success": frame.success,
+            },
+        })).collect();
+        json!({
+            "traceEvents": events,
+            "displayTimeUnit": "ms",
+            "otherData": { "name": name, "unit": "gas" },
+        })
+    }
+
+    pub fn to_json(&self, top_n: usize) -> Value {
+        let top = |mut aggregates: Vec<GasAggregate>| {
+            aggregates.truncate(top_n);
+            aggregates
+        };
+        json!({
+            "totalGas": self.total(),
+            "frames": self.frames,
+            "byContract": top(self.by_contract()),
+            "byFunction": top(self.by_function()),
+            "byDepth": self.by_depth(),
+            "hotspots": self.hotspots(top_n),
+            "flameGraph": self.flame_graph(),
+        })
+    }
+}
+
+pub fn function_label(node: &CallNode, registry: &ContractRegistry) -> String {
+    if matches!(node.call_type.as_str(), "CREATE" | "CREATE2") {
+        return "constructor".to_string();
+    }
+    // Precompiles take raw input, so even short calldata is not a receive or fallback
+    if let Some(precompile) = node.to.and_then(|to| precompile(&to)) {
+        return precompile.name.to_string();
+    }
+    if node.input.len() < 4 {
+        return if node.input.is_empty() { "receive()".to_string() } else { "fallback()".to_string() };
+    }
+    node.to
+        .and_then(|to| registry.function_signature(&to, &node.input))
+        .unwrap_or_else(|| format!("0x{}", hex::encode(&node.input[..4])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(selector: u8, gas_used: u64, calls: Vec<CallNode>) -> CallNode {
+        CallNode {
+            call_type: "CALL".to_string(),
+            to: Some(Address::repeat_byte(selector)),
+            gas_used: gas_used.into(),
+            input: vec![selector; 4].into(),
+            calls,
+            ..Default::default()
+        }
+    }
+
+    /// execute (100) -> [deposit (30) -> deposit (10), transfer (20)]
+    fn call_tree_profile() -> GasProfile {
+        let root = call(0xee, 100, vec![
+            call(0xdd, 30, vec![call(0xdd, 10, Vec::new())]),
+            call(0xaa, 20, Vec::new()),
+        ]);
+        GasProfile::from_call_tree(&root, &ContractRegistry::new())
+    }
+
+    #[test]
+    fn subtracts_children_from_exclusive_gas() {
+        let profile = call_tree_profile();
+        let gas: Vec<(Vec<usize>, u64, u64)> = profile.frames().iter()
+            .map(|f| (f.path.clone(), f.inclusive, f.exclusive))
+            .collect();
+        assert_eq!(gas, vec![
+            (vec![], 100, 50),
+            (vec![0], 30, 20),
+            (vec![0, 0], 10, 10),
+            (vec![1], 20, 20),
+        ]);
+        assert_eq!(profile.total(), 100);
+        assert_eq!(profile.hotspots(1)[0].function, "0xeeeeeeee");
+    }
+
+    #[test]
+    fn aggregates_nested_frames_once() {
+        let profile = call_tree_profile();
+        let deposit = profile.by_function().into_iter().find(|a| a.key == "0xdddddddd").unwrap();
+        assert_eq!((deposit.calls, deposit.inclusive, deposit.exclusive), (2, 30, 30));
+
+        let depths: Vec<(String, u64)> = profile.by_depth().into_iter().map(|a| (a.key, a.inclusive)).collect();
+        assert_eq!(depths, vec![("0".to_string(), 100), ("1".to_string(), 50), ("2".to_string(), 10)]);
+    }
+
+    #[test]
+    fn labels_functions_without_abi() {
+        let registry = ContractRegistry::new();
+        let mut node = call(0xaa, 0, Vec::new());
+        assert_eq!(function_label(&node, &registry), "0xaaaaaaaa");
+        node.input = vec![0xaa].into();
+        assert_eq!(function_label(&node, &registry), "fallback()");
+        node.input = Default::default();
+        assert_eq!(function_label(&node, &registry), "receive()");
+        node.call_type = "CREATE2".to_string();
+        assert_eq!(function_label(&node, &registry), "constructor");
+    }
+
+    fn frame(path: &[usize], function: &str, inclusive: u64, exclusive: u64) -> FrameGas {
+        FrameGas {
+            path: path.to_vec(),
+            depth: path.len(),
+            call_type: "CALL".to_string(),
+            address: None,
+            contract: Some("Vault".to_string()),
+            function: function.to_string(),
+            inclusive,
+            exclusive,
+            success: true,
+        }
+    }
+
+    /// root (100) -> [deposit (30) -> transfer (10), deposit (40)]
+    fn profile() -> GasProfile {
+        GasProfile::from_frames(vec![
+            frame(&[], "execute", 100, 30),
+            frame(&[0], "deposit", 30, 20),
+            frame(&[0, 0], "transfer", 10, 10),
+            frame(&[1], "deposit", 40, 40),
+        ])
+    }
+
+    fn events(speedscope: &Value) -> Vec<(String, u64, u64)> {
+        speedscope["profiles"][0]["events"].as_array().unwrap().iter().map(|e| (
+            e["type"].as_str().unwrap().to_string(),
+            e["frame"].as_u64().unwrap(),
+            e["at"].as_u64().unwrap(),
+        )).collect()
+    }
+
+    #[test]
+    fn lays_out_children_after_each_other() {
+        assert_eq!(profile().spans(), vec![(0, 100), (0, 30), (0, 10), (30, 70)]);
+    }
+
+    #[test]
+    fn widens_frames_to_fit_their_children() {
+        // Children reporting more gas than their parent must not overlap
+        let profile = GasProfile::from_frames(vec![
+            frame(&[], "execute", 50, 0),
+            frame(&[0], "a", 40, 40),
+            frame(&[1], "b", 30, 30),
+        ]);
+        assert_eq!(profile.spans(), vec![(0, 70), (0, 40), (40, 70)]);
+        assert_eq!(profile.speedscope("tx")["profiles"][0]["endValue"], 70);
+    }
+
+    #[test]
+    fn orders_speedscope_events() {
+        let speedscope = profile().speedscope("0xabc");
+        let o = |frame: u64, at: u64| ("O".to_string(), frame, at);
+        let c = |frame: u64, at: u64| ("C".to_string(), frame, at);
+        assert_eq!(events(&speedscope), vec![
+            o(0, 0),
+            o(1, 0),
+            o(2, 0),
+            c(2, 10),
+            c(1, 30),
+            o(1, 30),
+            c(1, 70),
+            c(0, 100),
+        ]);
+        // Frames with the same name share an entry
+        assert_eq!(speedscope["shared"]["frames"], json!([
+            { "name": "Vault.execute" },
+            { "name": "Vault.deposit" },
+            { "name": "Vault.transfer" },
+        ]));
+        assert_eq!(speedscope["profiles"][0]["endValue"], 100);
+    }
+
+    #[test]
+    fn closes_every_opened_frame_in_order() {
+        let profile = GasProfile::from_frames(vec![
+            frame(&[], "execute", 100, 10),
+            frame(&[0], "a", 60, 10),
+            frame(&[0, 0], "b", 50, 10),
+            frame(&[0, 0, 0], "c", 40, 40),
+            frame(&[1], "d", 30, 30),
+        ]);
+        let events = events(&profile.speedscope("tx"));
+        let mut open = Vec::new();
+        let mut at = 0;
+        for (kind, frame, time) in events {
+            assert!(time >= at, "events go back in time");
+            at = time;
+            match kind.as_str() {
+                "O" => open.push(frame),
+                _ => assert_eq!(open.pop(), Some(frame)),
+            }
+        }
+        assert!(open.is_empty());
+    }
+
+    #[test]
+    fn exports_empty_profile() {
+        let profile = GasProfile::from_frames(Vec::new());
+        assert!(profile.spans().is_empty());
+        let speedscope = profile.speedscope("tx");
+        assert!(events(&speedscope).is_empty());
+        assert_eq!(speedscope["profiles"][0]["endValue"], 0);
+    }
+}
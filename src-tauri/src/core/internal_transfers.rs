@@ -0,0 +1,117 @@
+use ethers::types::{Address, U256};
+use serde_json::{json, Value};
+use super::call_tree::CallNode;
+use super::units::UnitFormatter;
+
+/// Ether moved by a call frame below the top-level call
+/// Covers CALL with value, CREATE/CREATE2 endowments and SELFDESTRUCT beneficiaries
+#[derive(Debug, Clone)]
+pub struct InternalTransfer {
+    /// Path of the frame in the call tree
+    pub path: Vec<usize>,
+    pub call_type: String,
+    pub from: Address,
+    pub to: Address,
+    pub value: U256,
+    /// The frame or one of its callers reverted, so no ether actually moved
+    pub reverted: bool,
+}
+
+impl InternalTransfer {
+    /// Transfers in execution order, reverted ones included
+    pub fn collect(root: &CallNode) -> Vec<Self> {
+        let mut transfers = Vec::new();
+        // Whether each frame on the current path failed
+        let mut failed: Vec<bool> = Vec::new();
+        root.walk(&mut |path, node| {
+            failed.truncate(path.len());
+            failed.push(!node.is_success());
+            // The top-level value is the transaction's own, not an internal transfer
+            if path.is_empty() || !node.transfers_value() {
+                return;
+            }
+            if let (Some(to), Some(value)) = (node.to, node.value) {
+                transfers.push(Self {
+                    path: path.to_vec(),
+                    call_type: node.call_type.clone(),
+                    from: node.from,
+                    to,
+                    value,
+                    reverted: failed.contains(&true),
+                });
+            }
+        });
+        transfers
+    }
+
+    pub fn to_json(&self, units: &UnitFormatter) -> Value {
+        json!({
+            "path": self.path,
+            "type": self.call_type,
+            "from": format!("{:?}", self.from),
+            "to": format!("{:?}", self.to),
+            "value": units.native(self.value),
+            "valueWei": self.value.to_string(),
+            "reverted": self.reverted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(call_type: &str, to: u8, value: u64, error: Option<&str>, calls: Vec<CallNode>) -> CallNode {
+        CallNode {
+            call_type: call_type.to_string(),
+            to: Some(Address::repeat_byte(to)),
+            value: Some(value.into()),
+            error: error.map(str::to_string),
+            calls,
+            ..Default::default()
+        }
+    }
+
+    fn transfers(root: &CallNode) -> Vec<(Vec<usize>, u64, bool)> {
+        InternalTransfer::collect(root).into_iter()
+            .map(|t| (t.path, t.value.low_u64(), t.reverted))
+            .collect()
+    }
+
+    #[test]
+    fn flags_transfers_under_reverted_frames() {
+        let root = call("CALL", 1, 5, None, vec![
+            call("CALL", 2, 1, None, vec![call("CALL", 3, 2, None, Vec::new())]),
+            call("CALL", 4, 0, Some("execution reverted"), vec![
+                call("CALL", 5, 3, None, vec![call("SELFDESTRUCT", 6, 3, None, Vec::new())]),
+            ]),
+            call("CREATE", 7, 4, None, Vec::new()),
+        ]);
+        assert_eq!(transfers(&root), vec![
+            (vec![0], 1, false),
+            (vec![0, 0], 2, false),
+            (vec![1, 0], 3, true),
+            (vec![1, 0, 0], 3, true),
+            (vec![2], 4, false),
+        ]);
+    }
+
+    #[test]
+    fn flags_failed_transfer_itself() {
+        let root = call("CALL", 1, 0, None, vec![
+            call("CALL", 2, 1, Some("out of gas"), Vec::new()),
+            call("CALL", 3, 2, None, Vec::new()),
+        ]);
+        assert_eq!(transfers(&root), vec![(vec![0], 1, true), (vec![1], 2, false)]);
+    }
+
+    #[test]
+    fn skips_calls_that_cannot_move_value() {
+        let root = call("CALL", 1, 5, None, vec![
+            call("DELEGATECALL", 2, 5, None, Vec::new()),
+            call("STATICCALL", 3, 5, None, Vec::new()),
+            call("CALL", 4, 0, None, Vec::new()),
+        ]);
+        assert!(transfers(&root).is_empty());
+    }
+}
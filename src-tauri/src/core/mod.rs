@@ -7,6 +7,37 @@ pub use transaction_simulator::TransactionSimulator;
 
 pub mod simulator_debug;
 pub mod trace_formatter;
+pub mod abi_decoder;
+pub mod nested_calls;
+pub mod cache;
+pub mod explorer;
+pub mod sourcify;
+pub mod proxy;
+pub mod address_book;
+pub mod csv;
+pub mod tokens;
+pub mod balance_changes;
+pub mod artifacts;
+pub mod call_tree;
+pub mod contract_registry;
+pub mod source_map;
+pub mod step_trace;
+pub mod state_diff;
+pub mod storage_layout;
+pub mod units;
+pub mod block_context;
+pub mod gas_profiler;
+pub mod opcode_gas;
+pub mod access_list;
+pub mod warnings;
+pub mod trace_diff;
+pub mod revert_cause;
+pub mod internal_transfers;
+pub mod deployments;
+pub mod precompiles;
+pub mod export;
+pub mod foundry_repro;
 
 pub use simulator_debug::simulate_transaction;
-pub use simulator_debug::trace_transaction;
\ No newline at end of file
+pub use simulator_debug::trace_transaction;
+pub use simulator_debug::TraceContext;
\ No newline at end of file
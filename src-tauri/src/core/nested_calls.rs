@@ -0,0 +1,430 @@
+use ethers::abi::{Function, HumanReadableParser, Token};
+use ethers::types::{Address, U256};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use crate::types::{DecodedCall, VirtualCall};
+use super::abi_decoder;
+use super::contract_registry::ContractRegistry;
+
+/// Deepest level of batches inside batches that is unpacked
+const MAX_NESTING: usize = 8;
+
+/// Universal Router command flag allowing the command to revert without failing the batch
+const ROUTER_ALLOW_REVERT: u8 = 0x80;
+/// Universal Router command type bits
+const ROUTER_COMMAND_MASK: u8 = 0x3f;
+/// Universal Router command running a nested `(commands, inputs)` plan
+const ROUTER_EXECUTE_SUB_PLAN: u8 = 0x21;
+
+/// How the inner calls of a batching function are laid out in its arguments
+#[derive(Debug, Clone, Copy)]
+enum BatchFormat {
+    /// `bytes[]` of calls made on the contract itself, at an argument index
+    SelfCalls(usize),
+    /// Array of `(address target, [bool allowFailure], [uint256 value], bytes callData)` at an argument index
+    TargetCalls(usize),
+    /// Safe `multiSend(bytes)`: packed `(uint8 operation, address to, uint256 value, uint256 length, bytes data)`
+    SafeMultiSend,
+    /// Safe `execTransaction`: a single call with an operation
+    SafeTransaction,
+    /// Uniswap Universal Router `execute(bytes commands, bytes[] inputs)`
+    UniversalRouter,
+    /// 0x Exchange Proxy `transformERC20`: `(uint32 deploymentNonce, bytes data)[]` transformations
+    ZeroExTransformations,
+    /// Aggregator swap handing opaque calldata (at an argument index) to the executor in argument 0
+    ExecutorData(usize),
+}
+
+/// Known batching functions (the human-readable parser takes no names inside tuples)
+const BATCH_FUNCTIONS: &[(&str, BatchFormat)] = &[
+    // Uniswap V3 periphery / OpenZeppelin Multicall
+    ("function multicall(bytes[] data)", BatchFormat::SelfCalls(0)),
+    ("function multicall(uint256 deadline, bytes[] data)", BatchFormat::SelfCalls(1)),
+    ("function multicall(bytes32 previousBlockhash, bytes[] data)", BatchFormat::SelfCalls(1)),
+    // Multicall / Multicall2 / Multicall3
+    ("function aggregate((address,bytes)[] calls)", BatchFormat::TargetCalls(0)),
+    ("function tryAggregate(bool requireSuccess, (address,bytes)[] calls)", BatchFormat::TargetCalls(1)),
+    ("function blockAndAggregate((address,bytes)[] calls)", BatchFormat::TargetCalls(0)),
+    ("function tryBlockAndAggregate(bool requireSuccess, (address,bytes)[] calls)", BatchFormat::TargetCalls(1)),
+    ("function aggregate3((address,bool,bytes)[] calls)", BatchFormat::TargetCalls(0)),
+    ("function aggregate3Value((address,bool,uint256,bytes)[] calls)", BatchFormat::TargetCalls(0)),
+    // Safe
+    ("function multiSend(bytes transactions)", BatchFormat::SafeMultiSend),
+    (
+        "function execTransaction(address to, uint256 value, bytes data, uint8 operation, uint256 safeTxGas, uint256 baseGas, uint256 gasPrice, address gasToken, address refundReceiver, bytes signatures)",
+        BatchFormat::SafeTransaction,
+    ),
+    // Uniswap Universal Router
+    ("function execute(bytes commands, bytes[] inputs)", BatchFormat::UniversalRouter),
+    ("function execute(bytes commands, bytes[] inputs, uint256 deadline)", BatchFormat::UniversalRouter),
+    // 0x Exchange Proxy and Settler
+    (
+        "function transformERC20(address inputToken, address outputToken, uint256 inputTokenAmount, uint256 minOutputTokenAmount, (uint32,bytes)[] transformations)",
+        BatchFormat::ZeroExTransformations,
+    ),
+    (
+        "function execute((address,address,uint256) slippage, bytes[] actions, bytes32 zidAndAffiliate)",
+        BatchFormat::SelfCalls(1),
+    ),
+    // 1inch Aggregation Router v5 and v6
+    (
+        "function swap(address executor, (address,address,address,address,uint256,uint256,uint256) desc, bytes permit, bytes data)",
+        BatchFormat::ExecutorData(3),
+    ),
+    (
+        "function swap(address executor, (address,address,address,address,uint256,uint256,uint256) desc, bytes data)",
+        BatchFormat::ExecutorData(2),
+    ),
+];
+
+/// Universal Router commands whose inputs are the same across router versions
+const ROUTER_COMMANDS: &[(u8, &str)] = &[
+    (0x00, "function V3_SWAP_EXACT_IN(address recipient, uint256 amountIn, uint256 amountOutMin, bytes path, bool payerIsUser)"),
+    (0x01, "function V3_SWAP_EXACT_OUT(address recipient, uint256 amountOut, uint256 amountInMax, bytes path, bool payerIsUser)"),
+    (0x02, "function PERMIT2_TRANSFER_FROM(address token, address recipient, uint160 amount)"),
+    (0x03, "function PERMIT2_PERMIT_BATCH(((address,uint160,uint48,uint48)[],address,uint256) permitBatch, bytes signature)"),
+    (0x04, "function SWEEP(address token, address recipient, uint256 amountMin)"),
+    (0x05, "function TRANSFER(address token, address recipient, uint256 value)"),
+    (0x06, "function PAY_PORTION(address token, address recipient, uint256 bips)"),
+    (0x08, "function V2_SWAP_EXACT_IN(address recipient, uint256 amountIn, uint256 amountOutMin, address[] path, bool payerIsUser)"),
+    (0x09, "function V2_SWAP_EXACT_OUT(address recipient, uint256 amountOut, uint256 amountInMax, address[] path, bool payerIsUser)"),
+    (0x0a, "function PERMIT2_PERMIT(((address,uint160,uint48,uint48),address,uint256) permitSingle, bytes signature)"),
+    (0x0b, "function WRAP_ETH(address recipient, uint256 amountMin)"),
+    (0x0c, "function UNWRAP_WETH(address recipient, uint256 amountMin)"),
+    (0x0d, "function PERMIT2_TRANSFER_FROM_BATCH((address,address,uint160,address)[] batchDetails)"),
+    (0x0e, "function BALANCE_CHECK_ERC20(address owner, address token, uint256 minBalance)"),
+    (ROUTER_EXECUTE_SUB_PLAN, "function EXECUTE_SUB_PLAN(bytes commands, bytes[] inputs)"),
+];
+
+fn batch_functions() -> &'static [(Function, BatchFormat)] {
+    static FUNCTIONS: OnceLock<Vec<(Function, BatchFormat)>> = OnceLock::new();
+    FUNCTIONS.get_or_init(|| {
+        BATCH_FUNCTIONS.iter()
+            .filter_map(|(signature, format)| Some((HumanReadableParser::parse_function(signature).ok()?, *format)))
+            .collect()
+    })
+}
+
+fn router_commands() -> &'static HashMap<u8, Function> {
+    static COMMANDS: OnceLock<HashMap<u8, Function>> = OnceLock::new();
+    COMMANDS.get_or_init(|| {
+        ROUTER_COMMANDS.iter()
+            .filter_map(|(command, signature)| Some((*command, HumanReadableParser::parse_function(signature).ok()?)))
+            .collect()
+    })
+}
+
+/// Attach the calls nested in a known batching function to its decoded call
+/// Calls to batching functions are decoded with the built-in signature when no ABI matched
+pub fn unpack(
+    registry: &ContractRegistry,
+    target: Option<Address>,
+    input: &[u8],
+    decoded: Option<DecodedCall>,
+    depth: usize,
+) -> Option<DecodedCall> {
+    let Some((function, format)) = (input.len() >= 4)
+        .then(|| batch_functions().iter().find(|(f, _)| f.short_signature() == input[..4]))
+        .flatten()
+    else {
+        return decoded;
+    };
+    let mut decoded = decoded.or_else(|| abi_decoder::decode_function_call(function, input))?;
+    if depth < MAX_NESTING {
+        if let Ok(tokens) = function.decode_input(&input[4..]) {
+            decoded.virtual_calls = virtual_calls(registry, target, *format, &tokens, depth + 1);
+        }
+    }
+    Some(decoded)
+}
+
+fn virtual_calls(
+    registry: &ContractRegistry,
+    target: Option<Address>,
+    format: BatchFormat,
+    tokens: &[Token],
+    depth: usize,
+) -> Vec<VirtualCall> {
+    match format {
+        BatchFormat::SelfCalls(arg) => array_arg(tokens, arg).iter().enumerate().filter_map(|(index, token)| {
+            let data = token.clone().into_bytes()?;
+            Some(virtual_call(registry, index, target, data, depth))
+        }).collect(),
+
+        BatchFormat::TargetCalls(arg) => array_arg(tokens, arg).iter().enumerate().filter_map(|(index, token)| {
+            let fields = token.clone().into_tuple()?;
+            let mut call_target = None;
+            let mut data = Vec::new();
+            let mut value = None;
+            let mut allow_failure = None;
+            for field in fields {
+                match field {
+                    Token::Address(address) => call_target = Some(address),
+                    Token::Bytes(bytes) => data = bytes,
+                    Token::Uint(amount) => value = Some(amount),
+                    Token::Bool(flag) => allow_failure = Some(flag),
+                    _ => {}
+                }
+            }
+            let mut call = virtual_call(registry, index, call_target, data, depth);
+            call.value = value.map(|v| v.to_string());
+            call.allow_failure = allow_failure;
+            Some(call)
+        }).collect(),
+
+        BatchFormat::SafeMultiSend => {
+            let Some(Token::Bytes(packed)) = tokens.first() else { return Vec::new() };
+            parse_multi_send(packed).into_iter().enumerate().map(|(index, (operation, to, value, data))| {
+                let mut call = virtual_call(registry, index, Some(to), data, depth);
+                call.value = Some(value.to_string());
+                call.operation = Some(safe_operation(operation));
+                call
+            }).collect()
+        }
+
+        BatchFormat::SafeTransaction => {
+            let (Some(Token::Address(to)), Some(Token::Uint(value)), Some(Token::Bytes(data)), Some(Token::Uint(operation))) =
+                (tokens.first(), tokens.get(1), tokens.get(2), tokens.get(3))
+            else {
+                return Vec::new();
+            };
+            let mut call = virtual_call(registry, 0, Some(*to), data.clone(), depth);
+            call.value = Some(value.to_string());
+            call.operation = Some(safe_operation(operation.low_u32() as u8));
+            vec![call]
+        }
+
+        BatchFormat::UniversalRouter => {
+            let (Some(Token::Bytes(commands)), Some(Token::Array(inputs))) = (tokens.first(), tokens.get(1)) else {
+                return Vec::new();
+            };
+            router_calls(target, commands, inputs, depth)
+        }
+
+        BatchFormat::ZeroExTransformations => array_arg(tokens, 4).iter().enumerate().filter_map(|(index, token)| {
+            let fields = token.clone().into_tuple()?;
+            let nonce = fields.first()?.clone().into_uint()?;
+            let data = fields.get(1)?.clone().into_bytes()?;
+            let mut call = virtual_call(registry, index, None, data, depth);
+            call.label = Some(format!("transformer (deployment nonce {})", nonce));
+            Some(call)
+        }).collect(),
+
+        BatchFormat::ExecutorData(arg) => {
+            let (Some(Token::Address(executor)), Some(Token::Bytes(data))) = (tokens.first(), tokens.get(arg)) else {
+                return Vec::new();
+            };
+            let mut call = virtual_call(registry, 0, Some(*executor), data.clone(), depth);
+            call.label = Some("executor".to_string());
+            vec![call]
+        }
+    }
+}
+
+/// Universal Router commands, one byte each, paired with their ABI-encoded inputs
+fn router_calls(
+    router: Option<Address>,
+    commands: &[u8],
+    inputs: &[Token],
+    depth: usize,
+) -> Vec<VirtualCall> {
+    commands.iter().zip(inputs).enumerate().filter_map(|(index, (command, input))| {
+        let data = input.clone().into_bytes()?;
+        let command_type = command & ROUTER_COMMAND_MASK;
+        let function = router_commands().get(&command_type);
+
+        let decoded = function.and_then(|function| {
+            let tokens = function.decode_input(&data).ok()?;
+            let mut decoded = DecodedCall {
+                name: function.name.clone(),
+                signature: abi_decoder::function_signature(function),
+                selector: format!("0x{:02x}", command_type),
+                args: abi_decoder::decode_params(function, &tokens),
+                virtual_calls: Vec::new(),
+            };
+            if command_type == ROUTER_EXECUTE_SUB_PLAN && depth < MAX_NESTING {
+                if let (Some(Token::Bytes(commands)), Some(Token::Array(inputs))) = (tokens.first(), tokens.get(1)) {
+                    decoded.virtual_calls = router_calls(router, commands, inputs, depth + 1);
+                }
+            }
+            Some(decoded)
+        });
+
+        Some(VirtualCall {
+            index,
+            target: router.map(|r| format!("{:?}", r)),
+            value: None,
+            operation: None,
+            allow_failure: Some(command & ROUTER_ALLOW_REVERT != 0),
+            label: Some(function.map(|f| f.name.clone()).unwrap_or_else(|| format!("COMMAND_0x{:02x}", command_type))),
+            data: format!("0x{}", hex::encode(&data)),
+            decoded,
+        })
+    }).collect()
+}
+
+/// Decode an inner call against the registry, unpacking further batches
+fn virtual_call(registry: &ContractRegistry, index: usize, target: Option<Address>, data: Vec<u8>, depth: usize) -> VirtualCall {
+    VirtualCall {
+        index,
+        target: target.map(|t| format!("{:?}", t)),
+        value: None,
+        operation: None,
+        allow_failure: None,
+        label: None,
+        decoded: registry.decode_nested_call(target.as_ref(), &data, depth),
+        data: format!("0x{}", hex::encode(&data)),
+    }
+}
+
+fn array_arg(tokens: &[Token], arg: usize) -> Vec<Token> {
+    tokens.get(arg).cloned().and_then(|t| t.into_array()).unwrap_or_default()
+}
+
+fn safe_operation(operation: u8) -> String {
+    match operation {
+        0 => "CALL".to_string(),
+        1 => "DELEGATECALL".to_string(),
+        other => format!("OPERATION_{}", other),
+    }
+}
+
+/// Split Safe `multiSend` packed transactions
+fn parse_multi_send(packed: &[u8]) -> Vec<(u8, Address, U256, Vec<u8>)> {
+    let mut transactions = Vec::new();
+    let mut offset = 0;
+    while offset + 85 <= packed.len() {
+        let operation = packed[offset];
+        let to = Address::from_slice(&packed[offset + 1..offset + 21]);
+        let value = U256::from_big_endian(&packed[offset + 21..offset + 53]);
+        let length = U256::from_big_endian(&packed[offset + 53..offset + 85]);
+        let start = offset + 85;
+        if length > U256::from(packed.len() - start) {
+            break;
+        }
+        let end = start + length.as_usize();
+        transactions.push((operation, to, value, packed[start..end].to_vec()));
+        offset = end;
+    }
+    transactions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::encode;
+
+    fn multi_send_entry(operation: u8, to: Address, value: u64, data: &[u8]) -> Vec<u8> {
+        let mut entry = vec![operation];
+        entry.extend_from_slice(to.as_bytes());
+        entry.extend_from_slice(&encode(&[Token::Uint(U256::from(value))]));
+        entry.extend_from_slice(&encode(&[Token::Uint(U256::from(data.len()))]));
+        entry.extend_from_slice(data);
+        entry
+    }
+
+    fn router_input(command: u8, args: &[Token]) -> Vec<u8> {
+        let function = &router_commands()[&command];
+        function.encode_input(args).unwrap()[4..].to_vec()
+    }
+
+    #[test]
+    fn splits_multi_send_transactions() {
+        let first = Address::repeat_byte(0x11);
+        let second = Address::repeat_byte(0x22);
+        let mut packed = multi_send_entry(0, first, 5, &[0xde, 0xad, 0xbe, 0xef]);
+        packed.extend(multi_send_entry(1, second, 0, &[]));
+
+        assert_eq!(parse_multi_send(&packed), vec![
+            (0, first, U256::from(5), vec![0xde, 0xad, 0xbe, 0xef]),
+            (1, second, U256::zero(), vec![]),
+        ]);
+    }
+
+    #[test]
+    fn stops_at_truncated_multi_send_transaction() {
+        let to = Address::repeat_byte(0x11);
+        let mut packed = multi_send_entry(0, to, 0, &[0x01]);
+        let mut truncated = multi_send_entry(0, to, 0, &[0x02; 10]);
+        truncated.truncate(90);
+        packed.extend(truncated);
+
+        assert_eq!(parse_multi_send(&packed), vec![(0, to, U256::zero(), vec![0x01])]);
+        assert!(parse_multi_send(&[0u8; 84]).is_empty());
+    }
+
+    #[test]
+    fn unpacks_multi_send_calldata() {
+        let to = Address::repeat_byte(0x33);
+        let packed = multi_send_entry(1, to, 7, &[0xaa, 0xbb]);
+        let (function, _) = batch_functions().iter().find(|(f, _)| f.name == "multiSend").unwrap();
+        let input = function.encode_input(&[Token::Bytes(packed)]).unwrap();
+
+        let decoded = unpack(&ContractRegistry::new(), None, &input, None, 0).unwrap();
+        assert_eq!(decoded.name, "multiSend");
+        assert_eq!(decoded.virtual_calls.len(), 1);
+        let call = &decoded.virtual_calls[0];
+        assert_eq!(call.target.as_deref(), Some("0x3333333333333333333333333333333333333333"));
+        assert_eq!(call.value.as_deref(), Some("7"));
+        assert_eq!(call.operation.as_deref(), Some("DELEGATECALL"));
+        assert_eq!(call.data, "0xaabb");
+    }
+
+    #[test]
+    fn parses_router_commands() {
+        let router = Address::repeat_byte(0x44);
+        let recipient = Token::Address(Address::repeat_byte(0x55));
+        let inputs = vec![
+            Token::Bytes(router_input(0x0b, &[recipient.clone(), Token::Uint(U256::from(1))])),
+            Token::Bytes(router_input(0x0c, &[recipient, Token::Uint(U256::zero())])),
+            Token::Bytes(vec![0x12, 0x34]),
+        ];
+        // WRAP_ETH, UNWRAP_WETH allowed to revert, and an unknown command
+        let calls = router_calls(Some(router), &[0x0b, 0x8c, 0x3f], &inputs, 1);
+
+        assert_eq!(calls.len(), 3);
+        assert_eq!(calls[0].label.as_deref(), Some("WRAP_ETH"));
+        assert_eq!(calls[0].allow_failure, Some(false));
+        assert_eq!(calls[0].target.as_deref(), Some("0x4444444444444444444444444444444444444444"));
+        let wrap = calls[0].decoded.as_ref().unwrap();
+        assert_eq!(wrap.selector, "0x0b");
+        assert_eq!(wrap.args.iter().map(|a| a.name.as_str()).collect::<Vec<_>>(), vec!["recipient", "amountMin"]);
+
+        assert_eq!(calls[1].label.as_deref(), Some("UNWRAP_WETH"));
+        assert_eq!(calls[1].allow_failure, Some(true));
+        assert_eq!(calls[1].decoded.as_ref().unwrap().selector, "0x0c");
+
+        assert_eq!(calls[2].label.as_deref(), Some("COMMAND_0x3f"));
+        assert_eq!(calls[2].data, "0x1234");
+        assert!(calls[2].decoded.is_none());
+    }
+
+    #[test]
+    fn pairs_router_commands_with_available_inputs() {
+        let input = router_input(0x0b, &[Token::Address(Address::zero()), Token::Uint(U256::zero())]);
+        let calls = router_calls(None, &[0x0b, 0x0c], &[Token::Bytes(input)], 1);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].target, None);
+    }
+
+    #[test]
+    fn unpacks_router_sub_plans() {
+        let inner = router_input(0x04, &[
+            Token::Address(Address::repeat_byte(0x66)),
+            Token::Address(Address::repeat_byte(0x77)),
+            Token::Uint(U256::zero()),
+        ]);
+        let sub_plan = router_input(ROUTER_EXECUTE_SUB_PLAN, &[
+            Token::Bytes(vec![0x04]),
+            Token::Array(vec![Token::Bytes(inner)]),
+        ]);
+        let calls = router_calls(None, &[ROUTER_EXECUTE_SUB_PLAN | ROUTER_ALLOW_REVERT], &[Token::Bytes(sub_plan)], 1);
+
+        assert_eq!(calls[0].label.as_deref(), Some("EXECUTE_SUB_PLAN"));
+        assert_eq!(calls[0].allow_failure, Some(true));
+        let nested = &calls[0].decoded.as_ref().unwrap().virtual_calls;
+        assert_eq!(nested.len(), 1);
+        assert_eq!(nested[0].label.as_deref(), Some("SWEEP"));
+        assert_eq!(nested[0].decoded.as_ref().unwrap().args.len(), 3);
+    }
+}
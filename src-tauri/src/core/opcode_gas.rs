@@ -0,0 +1,528 @@
+use ethers::types::transaction::eip2930::{AccessList, AccessListItem};
+use ethers::types::{Address, Transaction, H256, U256};
+use ethers::utils::get_contract_address;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use super::call_tree::CallNode;
+use super::step_trace::{is_call_op, StepTrace};
+
+/// Gas added to the callee of a value-bearing CALL without being charged to the caller
+const CALL_STIPEND: u64 = 2300;
+/// Precompiles 0x01-0x11 (through the Prague BLS12-381 ones) are warm from the start of every
+/// transaction (EIP-2929)
+const PRECOMPILE_COUNT: u64 = 0x11;
+/// Access list entries cost 2400 gas per address and 1900 per storage key (EIP-2930) and save
+/// 2500 per cold account access and 2000 per cold slot access, so a key nets 100 gas and an
+/// address that is warm anyway needs more than 24 keys to pay for itself
+const MIN_KEYS_FOR_WARM_ADDRESS: usize = 25;
+/// Memory sizes past this many words cannot be paid for and would run out of gas
+const MAX_MEMORY_WORDS: u64 = 1 << 32;
+
+/// Number of steps and gas spent on them
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct GasCount {
+    pub count: u64,
+    pub gas: u64,
+}
+
+impl GasCount {
+    fn add(&mut self, gas: u64) {
+        self.count += 1;
+        self.gas += gas;
+    }
+}
+
+/// Cold (first) and warm (repeated) accesses under EIP-2929
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct AccessCost {
+    pub cold: GasCount,
+    pub warm: GasCount,
+}
+
+impl AccessCost {
+    fn add(&mut self, cold: bool, gas: u64) {
+        if cold { self.cold.add(gas) } else { self.warm.add(gas) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum AccessKey {
+    Account(Address),
+    Slot(Address, H256),
+}
+
+/// Accessed addresses and slots; entries added by a reverted frame are forgotten
+#[derive(Debug, Default)]
+struct AccessSet {
+    entries: HashSet<AccessKey>,
+    journal: Vec<AccessKey>,
+}
+
+impl AccessSet {
+    /// Mark an entry as accessed, returning whether it was cold
+    fn touch(&mut self, key: AccessKey) -> bool {
+        let cold = self.entries.insert(key);
+        if cold {
+            self.journal.push(key);
+        }
+        cold
+    }
+
+    fn checkpoint(&self) -> usize {
+        self.journal.len()
+    }
+
+    fn revert(&mut self, checkpoint: usize) {
+        for key in self.journal.drain(checkpoint..) {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+/// Gas per opcode from the struct log
+/// Call opcodes only count their own cost, not the gas forwarded to the callee, and
+/// memory expansion is reported separately from the opcode that caused it
+#[derive(Debug, Clone, Default)]
+pub struct OpcodeGasProfile {
+    opcodes: HashMap<String, GasCount>,
+    memory_expansion: GasCount,
+    sload: AccessCost,
+    sstore: AccessCost,
+    /// BALANCE, EXTCODE*, CALL-family and SELFDESTRUCT targets
+    account_access: AccessCost,
+    /// Refund counter increases and decreases caused by SSTORE (EIP-3529)
+    refund_added: GasCount,
+    refund_removed: GasCount,
+    refund_total: Option<u64>,
+    /// Accounts and slots accessed by the transaction's code, for access list suggestions
+    touched_accounts: BTreeSet<Address>,
+    touched_slots: BTreeMap<Address, BTreeSet<H256>>,
+    /// Sender, recipient, precompiles, fee recipient and created contracts
+    always_warm: HashSet<Address>,
+}
+
+impl OpcodeGasProfile {
+    pub fn from_steps(trace: &StepTrace, tx: &Transaction, call_tree: Option<&CallNode>) -> Self {
+        let mut profile = Self::default();
+        let logs = &trace.logs;
+        let created = tx.to.is_none().then(|| get_contract_address(tx.from, tx.nonce));
+
+        profile.always_warm.insert(tx.from);
+        profile.always_warm.insert(tx.to.or(created).unwrap_or_default());
+        profile.always_warm.extend((1..=PRECOMPILE_COUNT).map(Address::from_low_u64_be));
+
+        let mut accessed = AccessSet::default();
+        for address in &profile.always_warm {
+            accessed.touch(AccessKey::Account(*address));
+        }
+        for item in tx.access_list.iter().flat_map(|list| list.0.iter()) {
+            accessed.touch(AccessKey::Account(item.address));
+            for key in &item.storage_keys {
+                accessed.touch(AccessKey::Slot(item.address, *key));
+            }
+        }
+
+        let return_steps = return_steps(trace);
+        let mut memory_words = vec![0u64; trace.frames.len()];
+        let mut call_counts = vec![0usize; trace.frames.len()];
+        // (frame, access set checkpoint) of every active frame
+        let mut active: Vec<(usize, usize)> = vec![(0, 0)];
+
+        for (idx, log) in logs.iter().enumerate() {
+            let frame_idx = trace.step_frames[idx];
+            let frame = &trace.frames[frame_idx];
+            let depth = log.depth.max(1) as usize;
+
+            while active.len() > depth {
+                let Some((exited, checkpoint)) = active.pop() else { break };
+                let reverted = call_tree
+                    .and_then(|tree| tree.node_at(&trace.frames[exited].path))
+                    .map(|node| !node.is_success())
+                    .unwrap_or(false);
+                if reverted {
+                    accessed.revert(checkpoint);
+                }
+            }
+            if depth > active.len() {
+                // Created contracts are warm before their init code runs
+                if let Some(address) = frame.code_address.filter(|_| frame.is_create) {
+                    accessed.touch(AccessKey::Account(address));
+                    profile.always_warm.insert(address);
+                }
+                active.push((frame_idx, accessed.checkpoint()));
+            }
+
+            let op = match log.op.as_str() {
+                "SHA3" => "KECCAK256",
+                op => op,
+            };
+            let stack = log.stack.as_deref().unwrap_or_default();
+            let arg = |n: usize| stack.len().checked_sub(n + 1).map(|i| stack[i]).unwrap_or_default();
+
+            let memory_gas = memory_regions(op, &arg)
+                .and_then(|regions| expand_memory(&mut memory_words[frame_idx], &regions))
+                .unwrap_or(0);
+            if memory_gas > 0 {
+                profile.memory_expansion.add(memory_gas);
+            }
+
+            let gas = if is_call_op(op) {
+                let mut path = frame.path.clone();
+                path.push(call_counts[frame_idx]);
+                call_counts[frame_idx] += 1;
+                let callee = call_tree.and_then(|tree| tree.node_at(&path));
+                let stipend = if matches!(op, "CALL" | "CALLCODE") && !arg(2).is_zero() { CALL_STIPEND } else { 0 };
+                match (return_steps[idx], callee) {
+                    // Gas left before the call, minus gas left after it, minus what the callee used
+                    (Some(ret), Some(callee)) => (log.gas + stipend).saturating_sub(logs[ret].gas + callee.gas_used.low_u64()),
+                    (Some(ret), None) if ret == idx + 1 => (log.gas + stipend).saturating_sub(logs[ret].gas),
+                    _ => log.gas_cost,
+                }
+            } else {
+                log.gas_cost
+            };
+            let gas = gas.saturating_sub(memory_gas);
+            profile.opcodes.entry(op.to_string()).or_default().add(gas);
+
+            match op {
+                "SLOAD" | "SSTORE" => {
+                    let address = frame.storage_address.or(created).unwrap_or_default();
+                    let mut slot = H256::zero();
+                    arg(0).to_big_endian(slot.as_bytes_mut());
+                    let cold = accessed.touch(AccessKey::Slot(address, slot));
+                    profile.touched_slots.entry(address).or_default().insert(slot);
+                    if op == "SLOAD" {
+                        profile.sload.add(cold, gas);
+                    } else {
+                        profile.sstore.add(cold, gas);
+                        let refund = logs.get(idx + 1).and_then(|next| Some((log.refund_counter?, next.refund_counter?)));
+                        match refund {
+                            Some((before, after)) if after > before => profile.refund_added.add(after - before),
+                            Some((before, after)) if after < before => profile.refund_removed.add(before - after),
+                            _ => {}
+                        }
+                    }
+                }
+                "BALANCE" | "EXTCODESIZE" | "EXTCODECOPY" | "EXTCODEHASH" | "SELFDESTRUCT"
+                | "CALL" | "CALLCODE" | "DELEGATECALL" | "STATICCALL" => {
+                    let target = if op.contains("CALL") { arg(1) } else { arg(0) };
+                    let address = word_address(target);
+                    let cold = accessed.touch(AccessKey::Account(address));
+                    profile.account_access.add(cold, gas);
+                    profile.touched_accounts.insert(address);
+                }
+                // The fee recipient is warm from the start (EIP-3651); learn it from the value pushed
+                "COINBASE" => {
+                    let pushed = logs.get(idx + 1).and_then(|next| next.stack.as_ref()?.last().copied());
+                    if let Some(coinbase) = pushed.map(word_address) {
+                        accessed.entries.insert(AccessKey::Account(coinbase));
+                        profile.always_warm.insert(coinbase);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        profile.refund_total = logs.last().and_then(|log| log.refund_counter);
+        profile
+    }
+
+    /// Cheapest EIP-2930 access list for the accounts and slots the transaction accessed
+    /// Accounts that are warm anyway are only listed when they have enough storage keys
+    pub fn suggested_access_list(&self) -> AccessList {
+        let accounts = self.touched_accounts.iter().chain(self.touched_slots.keys()).collect::<BTreeSet<_>>();
+        let items = accounts.into_iter().filter_map(|address| {
+            let storage_keys: Vec<H256> = self.touched_slots.get(address).into_iter().flatten().copied().collect();
+            if self.always_warm.contains(address) && storage_keys.len() < MIN_KEYS_FOR_WARM_ADDRESS {
+                return None;
+            }
+            Some(AccessListItem { address: *address, storage_keys })
+        }).collect();
+        AccessList(items)
+    }
+
+    /// Opcodes sorted by gas spent, highest first
+    pub fn opcodes(&self) -> Vec<(&str, GasCount)> {
+        let mut opcodes: Vec<(&str, GasCount)> = self.opcodes.iter().map(|(op, gas)| (op.as_str(), *gas)).collect();
+        opcodes.sort_by(|a, b| b.1.gas.cmp(&a.1.gas).then_with(|| a.0.cmp(b.0)));
+        opcodes
+    }
+
+    /// `gas_used` is the transaction's gas after refunds, as in the receipt
+    pub fn to_json(&self, gas_used: Option<U256>) -> Value {
+        // The refund is capped at a fifth of the gas used before refunds (EIP-3529),
+        // which is a quarter of the gas used after them
+        let applied_refund = match (self.refund_total, gas_used) {
+            (Some(total), Some(gas_used)) => Some(total.min(gas_used.low_u64() / 4)),
+            _ => None,
+        };
+        json!({
+            "opcodes": self.opcodes().into_iter().map(|(op, gas)| json!({
+                "op": op,
+                "count": gas.count,
+                "gas": gas.gas,
+            })).collect::<Vec<_>>(),
+            "memoryExpansion": self.memory_expansion,
+            "storage": {
+                "sload": self.sload,
+                "sstore": self.sstore,
+                "accountAccess": self.account_access,
+            },
+            "refunds": {
+                "sstoreAdded": self.refund_added,
+                "sstoreRemoved": self.refund_removed,
+                "total": self.refund_total,
+                "applied": applied_refund,
+            },
+        })
+    }
+}
+
+fn word_address(word: U256) -> Address {
+    let mut bytes = [0u8; 32];
+    word.to_big_endian(&mut bytes);
+    Address::from_slice(&bytes[12..])
+}
+
+/// For each call opcode, the next step executed by the calling frame
+fn return_steps(trace: &StepTrace) -> Vec<Option<usize>> {
+    let mut returns = vec![None; trace.logs.len()];
+    let mut pending: Vec<usize> = Vec::new();
+    for (idx, log) in trace.logs.iter().enumerate() {
+        while let Some(&call) = pending.last() {
+            if log.depth > trace.logs[call].depth {
+                break;
+            }
+            returns[call] = Some(idx);
+            pending.pop();
+        }
+        if is_call_op(&log.op) {
+            pending.push(idx);
+        }
+    }
+    returns
+}
+
+/// Memory ranges (offset, size) an opcode reads or writes, from its stack arguments
+fn memory_regions(op: &str, arg: &dyn Fn(usize) -> U256) -> Option<Vec<(U256, U256)>> {
+    let regions = match op {
+        "MLOAD" | "MSTORE" => vec![(arg(0), U256::from(32))],
+        "MSTORE8" => vec![(arg(0), U256::one())],
+        "KECCAK256" | "RETURN" | "REVERT" => vec![(arg(0), arg(1))],
+        "CALLDATACOPY" | "CODECOPY" | "RETURNDATACOPY" => vec![(arg(0), arg(2))],
+        "EXTCODECOPY" => vec![(arg(1), arg(3))],
+        "MCOPY" => vec![(arg(0), arg(2)), (arg(1), arg(2))],
+        "CREATE" | "CREATE2" => vec![(arg(1), arg(2))],
+        "CALL" | "CALLCODE" => vec![(arg(3), arg(4)), (arg(5), arg(6))],
+        "DELEGATECALL" | "STATICCALL" => vec![(arg(2), arg(3)), (arg(4), arg(5))],
+        op if op.starts_with("LOG") => vec![(arg(0), arg(1))],
+        _ => return None,
+    };
+    Some(regions)
+}
+
+/// Grow a frame's memory to cover the regions and return the expansion cost
+fn expand_memory(words: &mut u64, regions: &[(U256, U256)]) -> Option<u64> {
+    let mut required = *words;
+    for (offset, size) in regions.iter().filter(|(_, size)| !size.is_zero()) {
+        let end = offset.checked_add(*size)?;
+        if end > U256::from(MAX_MEMORY_WORDS * 32) {
+            return None;
+        }
+        required = required.max(end.as_u64().div_ceil(32));
+    }
+    let cost = |words: u64| 3 * words + words * words / 512;
+    let expansion = cost(required) - cost(*words);
+    *words = required;
+    Some(expansion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::StructLog;
+    use crate::core::step_trace::StepFrame;
+
+    const SENDER: Address = Address::repeat_byte(0x05);
+    const A: Address = Address::repeat_byte(0x0a);
+    const B: Address = Address::repeat_byte(0x0b);
+    const C: Address = Address::repeat_byte(0x0c);
+
+    fn word(address: Address) -> U256 {
+        U256::from_big_endian(H256::from(address).as_bytes())
+    }
+
+    /// Struct log step; `stack` is bottom to top, as reported by the tracer
+    fn step(frame: usize, depth: u64, op: &str, gas: u64, gas_cost: u64, stack: Vec<U256>) -> (usize, StructLog) {
+        (frame, StructLog { depth, op: op.to_string(), gas, gas_cost, stack: Some(stack), ..Default::default() })
+    }
+
+    fn frame(path: Vec<usize>, address: Address) -> StepFrame {
+        StepFrame { path, code_address: Some(address), storage_address: Some(address), is_create: false }
+    }
+
+    fn step_trace(frames: Vec<StepFrame>, steps: Vec<(usize, StructLog)>) -> StepTrace {
+        let (step_frames, logs) = steps.into_iter().unzip();
+        StepTrace { failed: false, logs, frames, step_frames }
+    }
+
+    fn tx() -> Transaction {
+        Transaction { from: SENDER, to: Some(A), ..Default::default() }
+    }
+
+    fn node(to: Address, gas_used: u64, error: Option<&str>, calls: Vec<CallNode>) -> CallNode {
+        CallNode {
+            call_type: "CALL".to_string(),
+            to: Some(to),
+            gas_used: U256::from(gas_used),
+            error: error.map(str::to_string),
+            calls,
+            ..Default::default()
+        }
+    }
+
+    /// CALL arguments, bottom to top: retSize, retOffset, argsSize, argsOffset, value, address, gas
+    fn call_stack(to: Address, value: u64) -> Vec<U256> {
+        vec![U256::zero(), U256::zero(), U256::zero(), U256::zero(), U256::from(value), word(to), U256::from(50_000)]
+    }
+
+    #[test]
+    fn splits_cold_and_warm_sloads() {
+        let trace = step_trace(vec![frame(Vec::new(), A)], vec![
+            step(0, 1, "SLOAD", 1000, 2100, vec![U256::one()]),
+            step(0, 1, "SLOAD", 900, 100, vec![U256::one()]),
+            step(0, 1, "SLOAD", 800, 2100, vec![U256::from(2)]),
+            step(0, 1, "STOP", 700, 0, Vec::new()),
+        ]);
+        let profile = OpcodeGasProfile::from_steps(&trace, &tx(), None);
+
+        assert_eq!((profile.sload.cold.count, profile.sload.cold.gas), (2, 4200));
+        assert_eq!((profile.sload.warm.count, profile.sload.warm.gas), (1, 100));
+        assert_eq!(profile.opcodes()[0].0, "SLOAD");
+    }
+
+    fn accesses_after_call(child_error: Option<&str>) -> AccessCost {
+        // A calls B, which reads the balance of C; A then reads it twice itself
+        let tree = node(A, 20_000, None, vec![node(B, 5000, child_error, Vec::new())]);
+        let trace = step_trace(vec![frame(Vec::new(), A), frame(vec![0], B)], vec![
+            step(0, 1, "CALL", 100_000, 2600, call_stack(B, 0)),
+            step(1, 2, "BALANCE", 40_000, 2600, vec![word(C)]),
+            step(1, 2, "REVERT", 37_000, 0, vec![U256::zero(), U256::zero()]),
+            step(0, 1, "BALANCE", 92_400, 2600, vec![word(C)]),
+            step(0, 1, "BALANCE", 89_800, 100, vec![word(C)]),
+            step(0, 1, "STOP", 89_700, 0, Vec::new()),
+        ]);
+        OpcodeGasProfile::from_steps(&trace, &tx(), Some(&tree)).account_access
+    }
+
+    #[test]
+    fn forgets_accesses_of_reverted_frames() {
+        // B itself stays warm: the CALL touched it in A's frame
+        let access = accesses_after_call(Some("execution reverted"));
+        assert_eq!((access.cold.count, access.warm.count), (3, 1));
+
+        let access = accesses_after_call(None);
+        assert_eq!((access.cold.count, access.warm.count), (2, 2));
+    }
+
+    #[test]
+    fn charges_calls_without_callee_gas() {
+        let tree = node(A, 30_000, None, vec![node(B, 10_000, None, Vec::new())]);
+        // The value transfer adds the 2300 stipend to the callee without charging the caller
+        let trace = step_trace(vec![frame(Vec::new(), A), frame(vec![0], B)], vec![
+            step(0, 1, "CALL", 50_000, 45_000, call_stack(B, 1)),
+            step(1, 2, "STOP", 12_300, 0, Vec::new()),
+            step(0, 1, "STOP", 30_700, 0, Vec::new()),
+        ]);
+        let profile = OpcodeGasProfile::from_steps(&trace, &tx(), Some(&tree));
+        assert_eq!(profile.opcodes.get("CALL").map(|c| c.gas), Some(52_300 - 30_700 - 10_000));
+        assert_eq!(profile.account_access.cold.gas, 11_600);
+
+        // Calls running no code (EOAs, precompiles) continue at the next step
+        let trace = step_trace(vec![frame(Vec::new(), A)], vec![
+            step(0, 1, "STATICCALL", 50_000, 3000, vec![U256::zero(), U256::zero(), U256::zero(), U256::zero(), word(C), U256::from(40_000)]),
+            step(0, 1, "STOP", 47_400, 0, Vec::new()),
+        ]);
+        let profile = OpcodeGasProfile::from_steps(&trace, &tx(), None);
+        assert_eq!(profile.opcodes.get("STATICCALL").map(|c| c.gas), Some(2600));
+    }
+
+    #[test]
+    fn charges_memory_expansion_once() {
+        let trace = step_trace(vec![frame(Vec::new(), A)], vec![
+            step(0, 1, "MSTORE", 1000, 6, vec![U256::one(), U256::zero()]),
+            step(0, 1, "MSTORE", 994, 3, vec![U256::one(), U256::zero()]),
+            step(0, 1, "MSTORE", 991, 6, vec![U256::one(), U256::from(32)]),
+            step(0, 1, "STOP", 985, 0, Vec::new()),
+        ]);
+        let profile = OpcodeGasProfile::from_steps(&trace, &tx(), None);
+
+        assert_eq!((profile.memory_expansion.count, profile.memory_expansion.gas), (2, 6));
+        assert_eq!(profile.opcodes.get("MSTORE").map(|c| (c.count, c.gas)), Some((3, 9)));
+    }
+
+    #[test]
+    fn prices_memory_expansion() {
+        let mut words = 0;
+        assert_eq!(expand_memory(&mut words, &[(U256::zero(), U256::from(32))]), Some(3));
+        // 32 words: 3 * 32 + 32^2 / 512, minus the word already paid for
+        assert_eq!(expand_memory(&mut words, &[(U256::from(992), U256::from(32))]), Some(98 - 3));
+        assert_eq!(words, 32);
+        assert_eq!(expand_memory(&mut words, &[(U256::zero(), U256::from(64))]), Some(0));
+        // Empty regions never expand memory, wherever they point
+        assert_eq!(expand_memory(&mut words, &[(U256::MAX, U256::zero())]), Some(0));
+        assert_eq!(expand_memory(&mut words, &[(U256::MAX, U256::one())]), None);
+        assert_eq!(words, 32);
+    }
+
+    #[test]
+    fn counts_sstore_refund_changes() {
+        let refund = |mut step: (usize, StructLog), counter: u64| {
+            step.1.refund_counter = Some(counter);
+            step
+        };
+        let trace = step_trace(vec![frame(Vec::new(), A)], vec![
+            refund(step(0, 1, "SSTORE", 30_000, 2900, vec![U256::zero(), U256::one()]), 0),
+            refund(step(0, 1, "SSTORE", 27_100, 100, vec![U256::one(), U256::one()]), 4800),
+            refund(step(0, 1, "SSTORE", 27_000, 2900, vec![U256::one(), U256::from(2)]), 4800),
+            refund(step(0, 1, "STOP", 24_100, 0, Vec::new()), 2000),
+        ]);
+        let profile = OpcodeGasProfile::from_steps(&trace, &tx(), None);
+
+        assert_eq!((profile.refund_added.count, profile.refund_added.gas), (1, 4800));
+        assert_eq!((profile.refund_removed.count, profile.refund_removed.gas), (1, 2800));
+        let json = profile.to_json(Some(U256::from(6000)));
+        assert_eq!(json["refunds"]["total"], 2000);
+        // Capped at a quarter of the gas used after refunds
+        assert_eq!(json["refunds"]["applied"], 1500);
+    }
+
+    #[test]
+    fn lists_warm_accounts_only_with_enough_keys() {
+        let keys = |n: u64| (0..n).map(H256::from_low_u64_be).collect::<BTreeSet<_>>();
+        let profile = OpcodeGasProfile {
+            touched_accounts: BTreeSet::from([A, B, C]),
+            touched_slots: BTreeMap::from([(A, keys(3)), (B, keys(MIN_KEYS_FOR_WARM_ADDRESS as u64))]),
+            always_warm: HashSet::from([A, B]),
+            ..Default::default()
+        };
+        let list = profile.suggested_access_list();
+
+        let listed: Vec<(Address, usize)> = list.0.iter().map(|item| (item.address, item.storage_keys.len())).collect();
+        assert_eq!(listed, vec![(B, MIN_KEYS_FOR_WARM_ADDRESS), (C, 0)]);
+    }
+
+    #[test]
+    fn treats_sender_recipient_and_precompiles_as_warm() {
+        let trace = step_trace(vec![frame(Vec::new(), A)], vec![
+            step(0, 1, "BALANCE", 1000, 100, vec![word(SENDER)]),
+            step(0, 1, "BALANCE", 900, 100, vec![word(A)]),
+            step(0, 1, "BALANCE", 800, 100, vec![word(Address::from_low_u64_be(0x11))]),
+            step(0, 1, "BALANCE", 700, 2600, vec![word(Address::from_low_u64_be(0x12))]),
+            step(0, 1, "STOP", 600, 0, Vec::new()),
+        ]);
+        let access = OpcodeGasProfile::from_steps(&trace, &tx(), None).account_access;
+        assert_eq!((access.cold.count, access.warm.count), (1, 3));
+    }
+}
@@ -0,0 +1,333 @@
+use ethers::types::{Address, U256};
+use serde_json::{json, Value};
+
+/// Largest modexp operand decoded, the bound EIP-7823 puts on each length
+const MAX_MODEXP_LENGTH: usize = 1024;
+/// secp256k1 curve order
+const SECP256K1_N: &str = "0xfffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141";
+
+/// A precompiled contract, identified by its address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Precompile {
+    pub address: u8,
+    pub name: &'static str,
+    /// Inputs described like a function signature, for display in the call tree
+    pub signature: &'static str,
+}
+
+const PRECOMPILES: [Precompile; 17] = [
+    Precompile { address: 0x01, name: "ecrecover", signature: "ecrecover(bytes32 hash, uint8 v, bytes32 r, bytes32 s)" },
+    Precompile { address: 0x02, name: "sha256", signature: "sha256(bytes data)" },
+    Precompile { address: 0x03, name: "ripemd160", signature: "ripemd160(bytes data)" },
+    Precompile { address: 0x04, name: "identity", signature: "identity(bytes data)" },
+    Precompile { address: 0x05, name: "modexp", signature: "modexp(bytes base, bytes exponent, bytes modulus)" },
+    Precompile { address: 0x06, name: "bn254Add", signature: "bn254Add(G1 a, G1 b)" },
+    Precompile { address: 0x07, name: "bn254Mul", signature: "bn254Mul(G1 point, uint256 scalar)" },
+    Precompile { address: 0x08, name: "bn254Pairing", signature: "bn254Pairing((G1, G2)[] pairs)" },
+    Precompile { address: 0x09, name: "blake2f", signature: "blake2f(uint32 rounds, bytes64 h, bytes128 m, bytes16 t, bool final)" },
+    Precompile { address: 0x0a, name: "pointEvaluation", signature: "pointEvaluation(bytes32 versionedHash, bytes32 z, bytes32 y, bytes48 commitment, bytes48 proof)" },
+    Precompile { address: 0x0b, name: "bls12G1Add", signature: "bls12G1Add(G1 a, G1 b)" },
+    Precompile { address: 0x0c, name: "bls12G1Msm", signature: "bls12G1Msm((G1, uint256)[] pairs)" },
+    Precompile { address: 0x0d, name: "bls12G2Add", signature: "bls12G2Add(G2 a, G2 b)" },
+    Precompile { address: 0x0e, name: "bls12G2Msm", signature: "bls12G2Msm((G2, uint256)[] pairs)" },
+    Precompile { address: 0x0f, name: "bls12PairingCheck", signature: "bls12PairingCheck((G1, G2)[] pairs)" },
+    Precompile { address: 0x10, name: "bls12MapFpToG1", signature: "bls12MapFpToG1(Fp element)" },
+    Precompile { address: 0x11, name: "bls12MapFp2ToG2", signature: "bls12MapFp2ToG2(Fp2 element)" },
+];
+
+/// Precompile living at an address, if any
+pub fn precompile(address: &Address) -> Option<Precompile> {
+    let bytes = address.as_bytes();
+    if bytes[..19].iter().any(|b| *b != 0) {
+        return None;
+    }
+    PRECOMPILES.iter().find(|p| p.address == bytes[19]).copied()
+}
+
+pub fn is_precompile(address: &Address) -> bool {
+    precompile(address).is_some()
+}
+
+/// Decode the input and output of a precompile call
+/// `output` is `None` when the call failed; precompiles right-pad short inputs with zeros,
+/// and so does the decoding
+pub fn decode(address: &Address, input: &[u8], output: Option<&[u8]>) -> Option<Value> {
+    let precompile = precompile(address)?;
+    let output = output.filter(|o| !o.is_empty());
+    let mut notes: Vec<String> = Vec::new();
+
+    let (inputs, outputs) = match precompile.address {
+        0x01 => {
+            let v = U256::from_big_endian(&padded(input, 32, 32));
+            let r = U256::from_big_endian(&padded(input, 64, 32));
+            let s = U256::from_big_endian(&padded(input, 96, 32));
+            let n: U256 = SECP256K1_N.parse().unwrap_or_default();
+            if v != U256::from(27) && v != U256::from(28) {
+                notes.push(format!("v is {}, only 27 and 28 are accepted", v));
+            }
+            if r.is_zero() || r >= n || s.is_zero() || s >= n {
+                notes.push("r or s is outside the range of the curve order".to_string());
+            } else if s > n / 2 {
+                notes.push("s is in the upper half of the curve order (malleable signature)".to_string());
+            }
+            let signer = output.map(|o| Address::from_slice(&padded(o, 12, 20)));
+            if output.is_none() {
+                notes.push("recovery failed and returned no data; callers must not treat this as address(0)".to_string());
+            }
+            (
+                json!({ "hash": hex_at(input, 0, 32), "v": v.to_string(), "r": hex_at(input, 64, 32), "s": hex_at(input, 96, 32) }),
+                json!({ "signer": signer.map(|a| format!("{:?}", a)) }),
+            )
+        }
+        0x02 => (
+            json!({ "data": hex(input), "length": input.len() }),
+            json!({ "hash": output.map(hex) }),
+        ),
+        0x03 => (
+            json!({ "data": hex(input), "length": input.len() }),
+            json!({ "hash": output.map(|o| hex_at(o, 12, 20)) }),
+        ),
+        0x04 => (json!({ "data": hex(input) }), json!({ "data": output.map(hex) })),
+        0x05 => {
+            let lengths: Vec<usize> = (0..3)
+                .map(|i| U256::from_big_endian(&padded(input, i * 32, 32)))
+                .map(|len| if len > U256::from(u32::MAX) { u32::MAX as usize } else { len.as_usize() })
+                .collect();
+            let (base, exponent, modulus) = (lengths[0], lengths[1], lengths[2]);
+            let field = |offset: usize, len: usize| hex(&padded(input, offset, len.min(MAX_MODEXP_LENGTH)));
+            (
+                json!({
+                    "baseLength": base,
+                    "exponentLength": exponent,
+                    "modulusLength": modulus,
+                    "base": field(96, base),
+                    "exponent": field(96 + base, exponent),
+                    "modulus": field(96 + base + exponent, modulus),
+                }),
+                json!({ "result": output.map(hex) }),
+            )
+        }
+        0x06 => (
+            json!({ "a": point(input, 0, 32), "b": point(input, 64, 32) }),
+            json!({ "result": output.map(|o| point(o, 0, 32)) }),
+        ),
+        0x07 => (
+            json!({ "point": point(input, 0, 32), "scalar": U256::from_big_endian(&padded(input, 64, 32)).to_string() }),
+            json!({ "result": output.map(|o| point(o, 0, 32)) }),
+        ),
+        0x08 => (
+            json!({ "pairs": chunks(input, 192, |pair| json!({ "g1": point(pair, 0, 32), "g2": g2(pair, 64, 32) })) }),
+            json!({ "success": output.map(is_true) }),
+        ),
+        0x09 => (
+            json!({
+                "rounds": u32::from_be_bytes(padded(input, 0, 4).try_into().unwrap_or_default()),
+                "h": hex_at(input, 4, 64),
+                "m": hex_at(input, 68, 128),
+                "t": hex_at(input, 196, 16),
+                "final": padded(input, 212, 1)[0] == 1,
+            }),
+            json!({ "h": output.map(hex) }),
+        ),
+        0x0a => (
+            json!({
+                "versionedHash": hex_at(input, 0, 32),
+                "z": hex_at(input, 32, 32),
+                "y": hex_at(input, 64, 32),
+                "commitment": hex_at(input, 96, 48),
+                "proof": hex_at(input, 144, 48),
+            }),
+            json!({
+                "fieldElementsPerBlob": output.map(|o| U256::from_big_endian(&padded(o, 0, 32)).to_string()),
+                "blsModulus": output.map(|o| U256::from_big_endian(&padded(o, 32, 32)).to_string()),
+            }),
+        ),
+        // BLS12-381 field elements are 64 bytes, 48 of them significant
+        0x0b => (
+            json!({ "a": point(input, 0, 64), "b": point(input, 128, 64) }),
+            json!({ "result": output.map(|o| point(o, 0, 64)) }),
+        ),
+        0x0c => (
+            json!({ "pairs": chunks(input, 160, |pair| json!({ "point": point(pair, 0, 64), "scalar": hex_at(pair, 128, 32) })) }),
+            json!({ "result": output.map(|o| point(o, 0, 64)) }),
+        ),
+        0x0d => (
+            json!({ "a": g2(input, 0, 64), "b": g2(input, 256, 64) }),
+            json!({ "result": output.map(|o| g2(o, 0, 64)) }),
+        ),
+        0x0e => (
+            json!({ "pairs": chunks(input, 288, |pair| json!({ "point": g2(pair, 0, 64), "scalar": hex_at(pair, 256, 32) })) }),
+            json!({ "result": output.map(|o| g2(o, 0, 64)) }),
+        ),
+        0x0f => (
+            json!({ "pairs": chunks(input, 384, |pair| json!({ "g1": point(pair, 0, 64), "g2": g2(pair, 128, 64) })) }),
+            json!({ "success": output.map(is_true) }),
+        ),
+        0x10 => (
+            json!({ "element": hex_at(input, 0, 64) }),
+            json!({ "result": output.map(|o| point(o, 0, 64)) }),
+        ),
+        _ => (
+            json!({ "element": [hex_at(input, 0, 64), hex_at(input, 64, 64)] }),
+            json!({ "result": output.map(|o| g2(o, 0, 64)) }),
+        ),
+    };
+
+    if output.is_none() && precompile.address != 0x01 {
+        notes.push("the precompile rejected its input".to_string());
+    }
+
+    Some(json!({
+        "name": precompile.name,
+        "signature": precompile.signature,
+        "inputs": inputs,
+        "outputs": outputs,
+        "notes": notes,
+    }))
+}
+
+/// `len` bytes at `offset`, zero-padded past the end of the data
+fn padded(data: &[u8], offset: usize, len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    if offset < data.len() {
+        let available = &data[offset..data.len().min(offset + len)];
+        bytes[..available.len()].copy_from_slice(available);
+    }
+    bytes
+}
+
+fn hex(data: &[u8]) -> String {
+    format!("0x{}", hex::encode(data))
+}
+
+fn hex_at(data: &[u8], offset: usize, len: usize) -> String {
+    hex(&padded(data, offset, len))
+}
+
+fn is_true(output: &[u8]) -> bool {
+    output.last() == Some(&1)
+}
+
+/// Affine point over the base field, with coordinates of `size` bytes
+fn point(data: &[u8], offset: usize, size: usize) -> Value {
+    json!({ "x": hex_at(data, offset, size), "y": hex_at(data, offset + size, size) })
+}
+
+/// Point over the quadratic extension field, each coordinate made of two elements
+fn g2(data: &[u8], offset: usize, size: usize) -> Value {
+    json!({
+        "x": [hex_at(data, offset, size), hex_at(data, offset + size, size)],
+        "y": [hex_at(data, offset + 2 * size, size), hex_at(data, offset + 3 * size, size)],
+    })
+}
+
+fn chunks(data: &[u8], size: usize, decode: impl Fn(&[u8]) -> Value) -> Vec<Value> {
+    data.chunks(size).map(decode).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(value: U256) -> Vec<u8> {
+        let mut word = [0u8; 32];
+        value.to_big_endian(&mut word);
+        word.to_vec()
+    }
+
+    fn ecrecover_input(v: u64, r: U256, s: U256) -> Vec<u8> {
+        let mut input = vec![0x11; 32];
+        input.extend(word(U256::from(v)));
+        input.extend(word(r));
+        input.extend(word(s));
+        input
+    }
+
+    fn notes(decoded: &Value) -> Vec<&str> {
+        decoded["notes"].as_array().unwrap().iter().filter_map(|n| n.as_str()).collect()
+    }
+
+    #[test]
+    fn finds_precompiles_by_address() {
+        assert_eq!(precompile(&Address::from_low_u64_be(1)).map(|p| p.name), Some("ecrecover"));
+        assert_eq!(precompile(&Address::from_low_u64_be(0x11)).map(|p| p.name), Some("bls12MapFp2ToG2"));
+        assert!(precompile(&Address::from_low_u64_be(0x12)).is_none());
+        assert!(precompile(&Address::from_low_u64_be(0x0100)).is_none());
+    }
+
+    #[test]
+    fn decodes_ecrecover() {
+        let signer = Address::repeat_byte(0xab);
+        let mut output = vec![0u8; 12];
+        output.extend_from_slice(signer.as_bytes());
+        let input = ecrecover_input(28, U256::from(5), U256::from(7));
+
+        let decoded = decode(&Address::from_low_u64_be(1), &input, Some(&output)).unwrap();
+        assert_eq!(decoded["name"], "ecrecover");
+        assert_eq!(decoded["inputs"]["hash"], format!("0x{}", "11".repeat(32)));
+        assert_eq!(decoded["inputs"]["v"], "28");
+        assert_eq!(decoded["inputs"]["r"], hex(&word(U256::from(5))));
+        assert_eq!(decoded["inputs"]["s"], hex(&word(U256::from(7))));
+        assert_eq!(decoded["outputs"]["signer"], "0xabababababababababababababababababababab");
+        assert!(notes(&decoded).is_empty());
+    }
+
+    #[test]
+    fn flags_invalid_ecrecover_signatures() {
+        let n: U256 = SECP256K1_N.parse().unwrap();
+        let ecrecover = Address::from_low_u64_be(1);
+
+        let malleable = decode(&ecrecover, &ecrecover_input(27, U256::one(), n / 2 + 1), Some(&[0u8; 32])).unwrap();
+        assert_eq!(notes(&malleable), vec!["s is in the upper half of the curve order (malleable signature)"]);
+
+        let invalid = decode(&ecrecover, &ecrecover_input(1, n, U256::one()), None).unwrap();
+        assert_eq!(notes(&invalid), vec![
+            "v is 1, only 27 and 28 are accepted",
+            "r or s is outside the range of the curve order",
+            "recovery failed and returned no data; callers must not treat this as address(0)",
+        ]);
+        assert_eq!(invalid["outputs"]["signer"], Value::Null);
+    }
+
+    #[test]
+    fn decodes_modexp() {
+        // EIP-198 example: 3^(p - 2) mod p for the secp256k1 field prime
+        let input = hex::decode(concat!(
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "0000000000000000000000000000000000000000000000000000000000000020",
+            "0000000000000000000000000000000000000000000000000000000000000020",
+            "03",
+            "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e",
+            "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f",
+        )).unwrap();
+        let output = word(U256::one());
+
+        let decoded = decode(&Address::from_low_u64_be(5), &input, Some(&output)).unwrap();
+        assert_eq!(decoded["inputs"], json!({
+            "baseLength": 1,
+            "exponentLength": 32,
+            "modulusLength": 32,
+            "base": "0x03",
+            "exponent": "0xfffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e",
+            "modulus": "0xfffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f",
+        }));
+        assert_eq!(decoded["outputs"]["result"], hex(&output));
+        assert!(notes(&decoded).is_empty());
+    }
+
+    #[test]
+    fn pads_and_caps_modexp_operands() {
+        // Modulus missing from the input reads as zeros; huge lengths are capped
+        let mut input = word(U256::one());
+        input.extend(word(U256::one()));
+        input.extend(word(U256::MAX));
+        input.extend([0x02, 0x05]);
+
+        let decoded = decode(&Address::from_low_u64_be(5), &input, None).unwrap();
+        assert_eq!(decoded["inputs"]["modulusLength"], u32::MAX as u64);
+        assert_eq!(decoded["inputs"]["base"], "0x02");
+        assert_eq!(decoded["inputs"]["exponent"], "0x05");
+        assert_eq!(decoded["inputs"]["modulus"], format!("0x{}", "00".repeat(MAX_MODEXP_LENGTH)));
+        assert_eq!(notes(&decoded), vec!["the precompile rejected its input"]);
+    }
+}
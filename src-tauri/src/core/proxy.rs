@@ -0,0 +1,276 @@
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::types::{transaction::eip2718::TypedTransaction, Address, Bytes, TransactionRequest, H256};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use super::call_tree::CallNode;
+
+/// `bytes32(uint256(keccak256("eip1967.proxy.implementation")) - 1)`
+const EIP1967_IMPLEMENTATION_SLOT: &str = "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+/// `bytes32(uint256(keccak256("eip1967.proxy.beacon")) - 1)`
+const EIP1967_BEACON_SLOT: &str = "0xa3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50";
+/// `bytes32(uint256(keccak256("eip1967.proxy.admin")) - 1)`
+const EIP1967_ADMIN_SLOT: &str = "0xb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103";
+/// `keccak256("org.zeppelinos.proxy.implementation")`
+const OZ_LEGACY_IMPLEMENTATION_SLOT: &str = "0x7050c9e0f4ca769c69bd3a8ef740bc37934f8e2c036e5a723fd8ee048ed3f8c3";
+/// `keccak256("org.zeppelinos.proxy.admin")`
+const OZ_LEGACY_ADMIN_SLOT: &str = "0x10d6a54a4754c8869d6886b5f5d7fbfa5b4522237ea5c60d11bc4e7a1ff9390b";
+/// `keccak256("PROXIABLE")`
+const EIP1822_SLOT: &str = "0xc5f16f0fcc639fa48a6947836d9850f504798523bf8c9a3a87d5876cf622bcf7";
+
+/// `implementation()` on an EIP-1967 beacon
+const BEACON_IMPLEMENTATION_SELECTOR: [u8; 4] = [0x5c, 0x60, 0xda, 0x1b];
+/// `facetAddress(bytes4)` on an EIP-2535 diamond loupe
+const FACET_ADDRESS_SELECTOR: [u8; 4] = [0xcd, 0xff, 0xac, 0xc6];
+
+/// EIP-1167 minimal proxy runtime code around the implementation address
+const EIP1167_PREFIX: &[u8] = &[0x36, 0x3d, 0x3d, 0x37, 0x3d, 0x3d, 0x3d, 0x36, 0x3d, 0x73];
+const EIP1167_SUFFIX: &[u8] = &[0x5a, 0xf4, 0x3d, 0x82, 0x80, 0x3e, 0x90, 0x3d, 0x91, 0x60, 0x2b, 0x57, 0xfd, 0x5b, 0xf3];
+/// ERC-7511 minimal proxy using PUSH0
+const ERC7511_PREFIX: &[u8] = &[0x36, 0x5f, 0x5f, 0x37, 0x5f, 0x5f, 0x36, 0x5f, 0x73];
+const ERC7511_SUFFIX: &[u8] = &[0x5a, 0xf4, 0x3d, 0x5f, 0x5f, 0x3e, 0x5f, 0x3d, 0x91, 0x60, 0x2a, 0x57, 0xfd, 0x5b, 0xf3];
+
+/// Proxy standard detected for a contract
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ProxyKind {
+    #[serde(rename = "EIP-1967")]
+    Eip1967,
+    #[serde(rename = "EIP-1967 Beacon")]
+    Eip1967Beacon,
+    #[serde(rename = "EIP-1167")]
+    Eip1167,
+    #[serde(rename = "OpenZeppelin Legacy")]
+    OpenZeppelinLegacy,
+    #[serde(rename = "EIP-1822")]
+    Eip1822,
+    #[serde(rename = "EIP-2535")]
+    Eip2535Diamond,
+}
+
+/// Proxy configuration at the traced block
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyInfo {
+    pub kind: ProxyKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub implementation: Option<Address>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub beacon: Option<Address>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin: Option<Address>,
+    /// Diamond facets by function selector (hex)
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub facets: BTreeMap<String, Address>,
+}
+
+impl ProxyInfo {
+    fn new(kind: ProxyKind, implementation: Option<Address>) -> Self {
+        Self { kind, implementation, beacon: None, admin: None, facets: BTreeMap::new() }
+    }
+
+    /// Implementation serving a function selector
+    pub fn implementation_for(&self, selector: &[u8]) -> Option<Address> {
+        if selector.len() >= 4 {
+            if let Some(facet) = self.facets.get(&format!("0x{}", hex::encode(&selector[..4]))) {
+                return Some(*facet);
+            }
+        }
+        self.implementation
+    }
+
+    /// Every implementation behind the proxy (facets included)
+    pub fn implementations(&self) -> BTreeSet<Address> {
+        self.implementation.iter().chain(self.facets.values()).copied().collect()
+    }
+}
+
+/// Detect proxies among contracts touched by a transaction
+/// `codes` holds the runtime code of every touched contract
+pub async fn detect_proxies(
+    provider: &Provider<Http>,
+    codes: &HashMap<Address, Bytes>,
+    call_tree: Option<&CallNode>,
+) -> HashMap<Address, ProxyInfo> {
+    // Selectors called on each address, and addresses that delegate calls
+    let mut selectors: HashMap<Address, BTreeSet<[u8; 4]>> = HashMap::new();
+    let mut delegators: BTreeSet<Address> = BTreeSet::new();
+    if let Some(tree) = call_tree {
+        tree.walk(&mut |_, node| {
+            let Some(to) = node.to else { return };
+            if node.input.len() >= 4 {
+                let mut selector = [0u8; 4];
+                selector.copy_from_slice(&node.input[..4]);
+                selectors.entry(to).or_default().insert(selector);
+            }
+            if node.calls.iter().any(|c| c.call_type == "DELEGATECALL") {
+                delegators.insert(to);
+            }
+        });
+    }
+
+    let mut proxies = HashMap::new();
+    for (address, code) in codes {
+        let called = selectors.get(address).cloned().unwrap_or_default();
+        match detect_proxy(provider, *address, code, &called, delegators.contains(address)).await {
+            Ok(Some(info)) => {
+                println!("[PROXY] {:?} is an {:?} proxy -> {:?}", address, info.kind, info.implementations());
+                proxies.insert(*address, info);
+            }
+            Ok(None) => {}
+            Err(e) => println!("[PROXY] Detection failed for {:?}: {}", address, e),
+        }
+    }
+    proxies
+}
+
+/// Detect the proxy standard of a single contract
+/// Diamond lookups are only attempted for contracts seen delegating calls
+pub async fn detect_proxy(
+    provider: &Provider<Http>,
+    address: Address,
+    code: &[u8],
+    selectors: &BTreeSet<[u8; 4]>,
+    delegates: bool,
+) -> anyhow::Result<Option<ProxyInfo>> {
+    if let Some(implementation) = minimal_proxy_target(code) {
+        return Ok(Some(ProxyInfo::new(ProxyKind::Eip1167, Some(implementation))));
+    }
+
+    if let Some(implementation) = read_address_slot(provider, address, EIP1967_IMPLEMENTATION_SLOT).await? {
+        let mut info = ProxyInfo::new(ProxyKind::Eip1967, Some(implementation));
+        info.admin = read_address_slot(provider, address, EIP1967_ADMIN_SLOT).await?;
+        return Ok(Some(info));
+    }
+
+    if let Some(beacon) = read_address_slot(provider, address, EIP1967_BEACON_SLOT).await? {
+        let implementation = call_for_address(provider, beacon, BEACON_IMPLEMENTATION_SELECTOR.to_vec()).await;
+        let mut info = ProxyInfo::new(ProxyKind::Eip1967Beacon, implementation);
+        info.beacon = Some(beacon);
+        info.admin = read_address_slot(provider, address, EIP1967_ADMIN_SLOT).await?;
+        return Ok(Some(info));
+    }
+
+    if let Some(implementation) = read_address_slot(provider, address, OZ_LEGACY_IMPLEMENTATION_SLOT).await? {
+        let mut info = ProxyInfo::new(ProxyKind::OpenZeppelinLegacy, Some(implementation));
+        info.admin = read_address_slot(provider, address, OZ_LEGACY_ADMIN_SLOT).await?;
+        return Ok(Some(info));
+    }
+
+    if let Some(implementation) = read_address_slot(provider, address, EIP1822_SLOT).await? {
+        return Ok(Some(ProxyInfo::new(ProxyKind::Eip1822, Some(implementation))));
+    }
+
+    if delegates && !selectors.is_empty() {
+        let mut info = ProxyInfo::new(ProxyKind::Eip2535Diamond, None);
+        for selector in selectors {
+            let mut data = FACET_ADDRESS_SELECTOR.to_vec();
+            data.extend_from_slice(selector);
+            data.extend_from_slice(&[0u8; 28]);
+            if let Some(facet) = call_for_address(provider, address, data).await {
+                info.facets.insert(format!("0x{}", hex::encode(selector)), facet);
+            }
+        }
+        if !info.facets.is_empty() {
+            return Ok(Some(info));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Implementation address embedded in EIP-1167 / ERC-7511 minimal proxy code
+pub fn minimal_proxy_target(code: &[u8]) -> Option<Address> {
+    for (prefix, suffix) in [(EIP1167_PREFIX, EIP1167_SUFFIX), (ERC7511_PREFIX, ERC7511_SUFFIX)] {
+        if code.len() == prefix.len() + 20 + suffix.len() && code.starts_with(prefix) && code.ends_with(suffix) {
+            return Some(Address::from_slice(&code[prefix.len()..prefix.len() + 20]));
+        }
+    }
+    None
+}
+
+/// Read an address stored in a storage slot; zero means unset
+async fn read_address_slot(provider: &Provider<Http>, address: Address, slot: &str) -> anyhow::Result<Option<Address>> {
+    let slot: H256 = slot.parse()?;
+    let value = provider.get_storage_at(address, slot, None).await?;
+    Ok(word_to_address(value.as_bytes()))
+}
+
+/// Call a view function returning a single address
+async fn call_for_address(provider: &Provider<Http>, to: Address, data: Vec<u8>) -> Option<Address> {
+    let tx: TypedTransaction = TransactionRequest::new().to(to).data(data).into();
+    let output = provider.call(&tx, None).await.ok()?;
+    if output.len() != 32 {
+        return None;
+    }
+    word_to_address(&output)
+}
+
+/// Interpret a 32-byte word as an address, rejecting zero and values with dirty upper bytes
+fn word_to_address(word: &[u8]) -> Option<Address> {
+    if word.len() != 32 || word[..12].iter().any(|b| *b != 0) {
+        return None;
+    }
+    let address = Address::from_slice(&word[12..]);
+    (!address.is_zero()).then_some(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::U256;
+    use ethers::utils::keccak256;
+
+    const IMPLEMENTATION: Address = Address::repeat_byte(0x42);
+
+    /// `bytes32(uint256(keccak256(name)) - 1)`
+    fn eip1967_slot(name: &str) -> String {
+        let mut slot = [0u8; 32];
+        (U256::from_big_endian(&keccak256(name)) - 1).to_big_endian(&mut slot);
+        format!("{:?}", H256::from(slot))
+    }
+
+    #[test]
+    fn uses_eip1967_slots() {
+        assert_eq!(EIP1967_IMPLEMENTATION_SLOT, eip1967_slot("eip1967.proxy.implementation"));
+        assert_eq!(EIP1967_BEACON_SLOT, eip1967_slot("eip1967.proxy.beacon"));
+        assert_eq!(EIP1967_ADMIN_SLOT, eip1967_slot("eip1967.proxy.admin"));
+        assert_eq!(OZ_LEGACY_IMPLEMENTATION_SLOT, format!("{:?}", H256::from(keccak256("org.zeppelinos.proxy.implementation"))));
+        assert_eq!(EIP1822_SLOT, format!("{:?}", H256::from(keccak256("PROXIABLE"))));
+    }
+
+    #[test]
+    fn reads_addresses_from_slot_values() {
+        assert_eq!(word_to_address(H256::from(IMPLEMENTATION).as_bytes()), Some(IMPLEMENTATION));
+        assert_eq!(word_to_address(H256::zero().as_bytes()), None);
+        assert_eq!(word_to_address(H256::repeat_byte(0x42).as_bytes()), None);
+        assert_eq!(word_to_address(&[0u8; 20]), None);
+    }
+
+    fn minimal_proxy(prefix: &[u8], suffix: &[u8]) -> Vec<u8> {
+        [prefix, IMPLEMENTATION.as_bytes(), suffix].concat()
+    }
+
+    #[test]
+    fn detects_minimal_proxy_code() {
+        assert_eq!(minimal_proxy_target(&minimal_proxy(EIP1167_PREFIX, EIP1167_SUFFIX)), Some(IMPLEMENTATION));
+        assert_eq!(minimal_proxy_target(&minimal_proxy(ERC7511_PREFIX, ERC7511_SUFFIX)), Some(IMPLEMENTATION));
+
+        // Mixed or extended code is not a minimal proxy
+        assert_eq!(minimal_proxy_target(&minimal_proxy(EIP1167_PREFIX, ERC7511_SUFFIX)), None);
+        let mut extended = minimal_proxy(EIP1167_PREFIX, EIP1167_SUFFIX);
+        extended.push(0x00);
+        assert_eq!(minimal_proxy_target(&extended), None);
+        assert_eq!(minimal_proxy_target(&[]), None);
+    }
+
+    #[test]
+    fn resolves_facets_before_implementation() {
+        let facet = Address::repeat_byte(0x43);
+        let mut info = ProxyInfo::new(ProxyKind::Eip2535Diamond, Some(IMPLEMENTATION));
+        info.facets.insert("0xa9059cbb".to_string(), facet);
+
+        assert_eq!(info.implementation_for(&[0xa9, 0x05, 0x9c, 0xbb, 0x00]), Some(facet));
+        assert_eq!(info.implementation_for(&[0x09, 0x5e, 0xa7, 0xb3]), Some(IMPLEMENTATION));
+        assert_eq!(info.implementation_for(&[]), Some(IMPLEMENTATION));
+        assert_eq!(info.implementations(), BTreeSet::from([IMPLEMENTATION, facet]));
+    }
+}
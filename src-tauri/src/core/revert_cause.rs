@@ -0,0 +1,215 @@
+use ethers::types::Address;
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::types::serialize_address;
+use super::call_tree::CallNode;
+use super::contract_registry::ContractRegistry;
+use super::gas_profiler::function_label;
+
+/// Role of a failed frame in the outcome of the transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RevertStatus {
+    /// Reverted on its own rather than because a callee did
+    Origin,
+    /// Reverted by passing a callee's revert on to its caller
+    Bubbled,
+    /// The revert was handled (try/catch or a low-level call) and did not affect the outcome
+    Caught,
+}
+
+/// A failed call frame with its decoded reason
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevertFrame {
+    pub path: Vec<usize>,
+    #[serde(rename = "type")]
+    pub call_type: String,
+    #[serde(serialize_with = "serialize_address")]
+    pub to: Option<Address>,
+    pub contract: Option<String>,
+    pub function: String,
+    pub status: RevertStatus,
+    /// Raw tracer error, e.g. "execution reverted" or "out of gas"
+    pub error: Option<String>,
+    /// Decoded revert data: `Error(string)`, `Panic(uint256)` or a custom error
+    pub reason: Option<String>,
+}
+
+/// Where a revert started and how it reached the top of the transaction
+#[derive(Debug, Clone, Default)]
+pub struct RevertCause {
+    /// Frames from the root to the originating frame, empty when the transaction succeeded
+    pub path: Vec<RevertFrame>,
+    /// Failed frames whose revert did not reach the top, either handled by their caller or
+    /// passed on to a frame that was
+    pub caught: Vec<RevertFrame>,
+}
+
+impl RevertCause {
+    pub fn analyze(root: &CallNode, registry: &ContractRegistry) -> Self {
+        let mut cause = Self::default();
+        root.walk(&mut |path, node| {
+            if node.is_success() {
+                return;
+            }
+            let cause_child = bubbled_child(node);
+            let status = match on_revert_path(root, path) {
+                true if cause_child.is_some() => RevertStatus::Bubbled,
+                true => RevertStatus::Origin,
+                false => RevertStatus::Caught,
+            };
+            let frame = RevertFrame {
+                path: path.to_vec(),
+                call_type: node.call_type.clone(),
+                to: node.to,
+                contract: node.to.and_then(|to| registry.name(&to).map(str::to_string)),
+                function: function_label(node, registry),
+                status,
+                error: node.error.clone(),
+                reason: revert_reason(node, registry),
+            };
+            match status {
+                RevertStatus::Caught => cause.caught.push(frame),
+                _ => cause.path.push(frame),
+            }
+        });
+        cause
+    }
+
+    /// Frame the revert started in
+    pub fn origin(&self) -> Option<&RevertFrame> {
+        self.path.last().filter(|frame| frame.status == RevertStatus::Origin)
+    }
+
+    /// Status of every failed frame, for annotating the formatted call tree
+    pub fn statuses(&self) -> impl Iterator<Item = (&[usize], RevertStatus)> {
+        self.path.iter().chain(&self.caught).map(|frame| (frame.path.as_slice(), frame.status))
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "reverted": !self.path.is_empty(),
+            "origin": self.origin(),
+            "path": self.path,
+            "caught": self.caught,
+        })
+    }
+}
+
+/// Failed child whose revert the frame passed on, if the frame failed because of one
+/// Solidity re-raises a callee's revert data unchanged, so the last failed child with the same
+/// output is the cause; a frame that failed with other data reverted on its own
+fn bubbled_child(node: &CallNode) -> Option<usize> {
+    if node.is_success() {
+        return None;
+    }
+    let output = node.output.as_ref().map(|o| o.as_ref()).unwrap_or_default();
+    let (idx, child) = node.calls.iter().enumerate().rev().find(|(_, child)| !child.is_success())?;
+    let child_output = child.output.as_ref().map(|o| o.as_ref()).unwrap_or_default();
+    // Empty revert data carries no identity, so only trust it when the failed call came last
+    let matches = if output.is_empty() {
+        child_output.is_empty() && idx + 1 == node.calls.len()
+    } else {
+        child_output == output
+    };
+    matches.then_some(idx)
+}
+
+/// Whether `path` failed and every frame above it failed by passing on the next one's revert
+fn on_revert_path(root: &CallNode, path: &[usize]) -> bool {
+    let mut node = root;
+    for idx in path {
+        if bubbled_child(node) != Some(*idx) {
+            return false;
+        }
+        node = &node.calls[*idx];
+    }
+    !node.is_success()
+}
+
+/// Reason reported by the tracer, or decoded from the revert data
+pub fn revert_reason(node: &CallNode, registry: &ContractRegistry) -> Option<String> {
+    node.revert_reason.clone().or_else(|| {
+        let output = node.output.as_ref().filter(|o| !o.is_empty())?;
+        registry.decode_revert(node.to.as_ref(), &node.input, output)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(output: &[u8], calls: Vec<CallNode>) -> CallNode {
+        CallNode {
+            call_type: "CALL".to_string(),
+            to: Some(Address::repeat_byte(0x0a)),
+            output: Some(output.to_vec().into()),
+            calls,
+            ..Default::default()
+        }
+    }
+
+    fn failed(output: &[u8], calls: Vec<CallNode>) -> CallNode {
+        CallNode { error: Some("execution reverted".to_string()), ..frame(output, calls) }
+    }
+
+    fn statuses(cause: &RevertCause) -> Vec<(Vec<usize>, RevertStatus)> {
+        cause.statuses().map(|(path, status)| (path.to_vec(), status)).collect()
+    }
+
+    #[test]
+    fn traces_bubbled_revert_to_deepest_frame() {
+        let root = failed(b"oops", vec![frame(b"", Vec::new()), failed(b"oops", vec![failed(b"oops", Vec::new())])]);
+        let cause = RevertCause::analyze(&root, &ContractRegistry::new());
+
+        assert_eq!(statuses(&cause), vec![
+            (vec![], RevertStatus::Bubbled),
+            (vec![1], RevertStatus::Bubbled),
+            (vec![1, 0], RevertStatus::Origin),
+        ]);
+        assert_eq!(cause.origin().map(|f| f.path.clone()), Some(vec![1, 0]));
+        assert!(cause.caught.is_empty());
+        assert_eq!(cause.to_json()["reverted"], true);
+    }
+
+    #[test]
+    fn marks_revert_caught_by_successful_caller() {
+        let root = frame(b"", vec![frame(b"", vec![failed(b"oops", Vec::new())])]);
+        let cause = RevertCause::analyze(&root, &ContractRegistry::new());
+
+        assert!(cause.path.is_empty());
+        assert!(cause.origin().is_none());
+        assert_eq!(statuses(&cause), vec![(vec![0, 0], RevertStatus::Caught)]);
+        assert_eq!(cause.to_json()["reverted"], false);
+    }
+
+    #[test]
+    fn treats_parent_reverting_with_other_data_as_origin() {
+        // The child's revert was caught, then the parent reverted with its own error
+        let root = failed(b"parent", vec![failed(b"child", Vec::new())]);
+        let cause = RevertCause::analyze(&root, &ContractRegistry::new());
+
+        assert_eq!(statuses(&cause), vec![(vec![], RevertStatus::Origin), (vec![0], RevertStatus::Caught)]);
+        assert_eq!(cause.origin().map(|f| f.path.clone()), Some(vec![]));
+    }
+
+    #[test]
+    fn trusts_empty_revert_data_only_from_last_call() {
+        let root = failed(b"", vec![frame(b"", Vec::new()), failed(b"", Vec::new())]);
+        let cause = RevertCause::analyze(&root, &ContractRegistry::new());
+        assert_eq!(statuses(&cause), vec![(vec![], RevertStatus::Bubbled), (vec![1], RevertStatus::Origin)]);
+
+        // A failed call followed by more calls was handled; the frame failed on its own
+        let root = failed(b"", vec![failed(b"", Vec::new()), frame(b"", Vec::new())]);
+        let cause = RevertCause::analyze(&root, &ContractRegistry::new());
+        assert_eq!(statuses(&cause), vec![(vec![], RevertStatus::Origin), (vec![0], RevertStatus::Caught)]);
+    }
+
+    #[test]
+    fn uses_reason_reported_by_tracer() {
+        let root = CallNode { revert_reason: Some("Ownable: caller is not the owner".to_string()), ..failed(b"", Vec::new()) };
+        let cause = RevertCause::analyze(&root, &ContractRegistry::new());
+        assert_eq!(cause.origin().and_then(|f| f.reason.as_deref()), Some("Ownable: caller is not the owner"));
+    }
+}
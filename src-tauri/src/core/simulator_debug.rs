@@ -1,12 +1,83 @@
 use ethers::providers::{Provider, Http, Middleware};
-use ethers::types::{TransactionRequest, GethDebugTracingOptions, GethTrace, transaction::eip2718::TypedTransaction, TxHash};
+use ethers::types::{TransactionRequest, GethDebugTracingOptions, GethTrace, transaction::eip2718::TypedTransaction, TxHash, Address, Bytes};
 use ethers::utils::Anvil;
 use serde_json::{json, Value};
 use std::sync::Arc;
 use std::path::PathBuf;
 use std::env;
-use crate::types::SimulatorDebugInfo;
+use std::collections::{BTreeSet, HashMap};
+use crate::types::{find_network, NativeCurrency, NetworkConfig, SimulatorDebugInfo, TraceOptions};
 use super::trace_formatter::{format_tenderly_style, format_trace_for_display};
+use super::artifacts::ProjectArtifacts;
+use super::call_tree::{attribute_events, fetch_call_tree, format_call_tree, formatted_node_mut};
+use super::contract_registry::ContractRegistry;
+use super::state_diff::StateDiff;
+use super::storage_layout::Preimages;
+use super::step_trace::{format_source_steps, revert_locations, StepTrace, StepTraceOptions};
+use super::cache::DiskCache;
+use super::explorer::ExplorerClient;
+use super::proxy::detect_proxies;
+use super::address_book::AddressBook;
+use super::units::UnitFormatter;
+use super::block_context::BlockContext;
+use super::gas_profiler::{GasProfile, DEFAULT_TOP_N};
+use super::opcode_gas::OpcodeGasProfile;
+use super::access_list::AccessListEstimate;
+use super::warnings::{analyze as analyze_warnings, approval_spenders};
+use super::revert_cause::RevertCause;
+use super::balance_changes::BalanceChanges;
+use super::internal_transfers::InternalTransfer;
+use super::deployments::Deployment;
+use super::tokens::{parse_token_transfers, token_addresses, TokenRegistry};
+use super::sourcify::{SourcifyClient, SourcifyRepository, DEFAULT_SOURCIFY_API, DEFAULT_SOURCIFY_REPOSITORY};
+
+/// Optional sources of contract data consulted while tracing
+#[derive(Debug, Clone, Default)]
+pub struct TraceContext {
+    /// Linked Foundry/Hardhat project used to name and decode matching contracts
+    pub project: Option<Arc<ProjectArtifacts>>,
+    /// Settings sent by the UI
+    pub options: TraceOptions,
+    /// Directory for cached explorer data
+    pub cache_dir: Option<PathBuf>,
+    /// User-defined address labels
+    pub address_book: AddressBook,
+}
+
+/// Network of the fork, with UI overrides applied
+fn resolve_network(chain_id: u64, rpc_url: &str, options: &TraceOptions) -> NetworkConfig {
+    let mut network = find_network(chain_id).unwrap_or_else(|| NetworkConfig {
+        name: format!("Chain {}", chain_id),
+        chain_id,
+        rpc_url: rpc_url.to_string(),
+        block_explorer: None,
+        explorer_api_url: None,
+        native_currency: NativeCurrency::default(),
+    });
+    if let Some(api_url) = options.explorer_api_url.as_ref().filter(|u| !u.is_empty()) {
+        network.explorer_api_url = Some(api_url.clone());
+    }
+    network
+}
+
+/// Fetch the code of each address; accounts without code are left out, and addresses whose
+/// lookup failed are added to `failed`
+async fn fetch_codes(provider: &Provider<Http>, addresses: &BTreeSet<Address>, failed: &mut BTreeSet<Address>) -> HashMap<Address, Bytes> {
+    let mut codes = HashMap::new();
+    for address in addresses {
+        match provider.get_code(*address, None).await {
+            Ok(code) if !code.is_empty() => {
+                codes.insert(*address, code);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                println!("Failed to fetch code for {:?}: {}", address, e);
+                failed.insert(*address);
+            }
+        }
+    }
+    codes
+}
 
 
 /// Get the path to binaries (now uses Tauri sidecar resolution)
@@ -112,6 +183,7 @@ pub async fn trace_transaction(
     tx_hash: TxHash,
     rpc_url: &str,
     block: u64,
+    context: &TraceContext,
 ) -> anyhow::Result<Value> {
     use tokio::time::{timeout, Duration};
     
@@ -194,7 +266,228 @@ pub async fn trace_transaction(
         }
     };
 
-    let trace = format_tenderly_style(&tx_details, &tx_receipt, cast_trace.as_deref());
+    // Structured call tree used for decoding; the cast trace above stays the fallback
+    let call_tree = match fetch_call_tree(&provider, &tx_details).await {
+        Ok(tree) => Some(tree),
+        Err(e) => {
+            println!("Call tracer failed: {}", e);
+            None
+        }
+    };
+
+    let chain_id = provider.get_chainid().await.map(|id| id.as_u64()).unwrap_or_default();
+    let network = resolve_network(chain_id, rpc_url, &context.options);
+    let units = UnitFormatter::new(&network.native_currency, context.options.display_precision);
+
+    let block = match tx_receipt.block_number {
+        Some(number) => match BlockContext::fetch(&provider, number).await {
+            Ok(block) => Some(block),
+            Err(e) => {
+                println!("Failed to fetch block {}: {}", number, e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    let mut addresses = call_tree.as_ref().map(|tree| tree.touched_addresses()).unwrap_or_default();
+    addresses.extend(tx_details.to);
+    addresses.extend(tx_receipt.logs.iter().map(|log| log.address));
+    let mut unknown_codes = BTreeSet::new();
+    let mut codes = fetch_codes(&provider, &addresses, &mut unknown_codes).await;
+
+    // Implementations behind proxies are decoded too, so fetch their code alongside
+    let proxies = detect_proxies(&provider, &codes, call_tree.as_ref()).await;
+    let implementations: BTreeSet<Address> = proxies.values()
+        .flat_map(|p| p.implementations())
+        .filter(|a| !codes.contains_key(a))
+        .collect();
+    codes.extend(fetch_codes(&provider, &implementations, &mut unknown_codes).await);
+    // Approval spenders only need their code checked to flag EOAs
+    let spenders: BTreeSet<Address> = approval_spenders(&tx_receipt.logs)
+        .into_iter()
+        .filter(|a| !codes.contains_key(a) && !addresses.contains(a))
+        .collect();
+    codes.extend(fetch_codes(&provider, &spenders, &mut unknown_codes).await);
+
+    // Linked artifacts are registered first so they take precedence over explorer data
+    let mut registry = ContractRegistry::new();
+    if let Some(project) = &context.project {
+        let matched = project.register_matches(&codes, &mut registry);
+        println!("Matched {} contracts against linked project artifacts", matched);
+    }
+    let offline = context.options.offline.unwrap_or(false);
+    let contracts: Vec<Address> = codes.keys().copied().collect();
+    if let Some(api_url) = &network.explorer_api_url {
+        let mut client = ExplorerClient::new(api_url, context.options.explorer_api_key.clone(), chain_id)
+            .offline(offline);
+        if let Some(dir) = &context.cache_dir {
+            client = client.with_cache(DiskCache::new(dir.clone()));
+        }
+        let verified = client.register_verified(&contracts, &mut registry).await;
+        println!("Registered {} verified contracts from {}", verified, api_url);
+    }
+    let sourcify_location = context.options.sourcify_repository.as_deref().unwrap_or(DEFAULT_SOURCIFY_REPOSITORY);
+    let sourcify = (!sourcify_location.trim().is_empty()).then(|| {
+        let mut client = SourcifyClient::new(SourcifyRepository::parse(sourcify_location), chain_id)
+            .offline(offline);
+        if let Some(dir) = &context.cache_dir {
+            client = client.with_cache(DiskCache::new(dir.clone()));
+        }
+        if sourcify_location == DEFAULT_SOURCIFY_REPOSITORY {
+            client = client.with_api(DEFAULT_SOURCIFY_API);
+        }
+        client
+    });
+    if let Some(client) = &sourcify {
+        let verified = client.register_verified(&contracts, &mut registry).await;
+        println!("Registered {} verified contracts from Sourcify ({})", verified, sourcify_location);
+    }
+    for (address, proxy) in proxies {
+        registry.register_proxy(address, proxy);
+    }
+
+    let mut labels = context.address_book.resolve(chain_id);
+    labels.add_contract_names(&registry);
+    // ENS lives on mainnet; the fork answers reverse lookups as of the traced block
+    if chain_id == 1 {
+        labels.add_ens_names(&provider, &addresses).await;
+    }
+
+    let token_transfers = parse_token_transfers(&tx_receipt.logs);
+    let cache = context.cache_dir.clone().map(DiskCache::new);
+    let tokens = TokenRegistry::fetch(&provider, &token_addresses(&tx_receipt.logs), chain_id, cache.as_ref()).await;
+    let balance_changes = BalanceChanges::collect(&tx_details, &tx_receipt, call_tree.as_ref(), &token_transfers);
+
+    let state_diff = match StateDiff::fetch(&provider, &tx_details).await {
+        Ok(diff) => Some(diff),
+        Err(e) => {
+            println!("Prestate tracer failed: {}", e);
+            None
+        }
+    };
+    // Verified sources come without a storage layout, but Sourcify may have recorded one
+    if let (Some(client), Some(diff)) = (&sourcify, &state_diff) {
+        let changed: BTreeSet<Address> = diff.addresses().into_iter()
+            .filter(|a| !diff.storage_changes(a).is_empty())
+            .collect();
+        let layouts = client.register_storage_layouts(&changed, &mut registry).await;
+        if layouts > 0 {
+            println!("Registered {} storage layouts from Sourcify", layouts);
+        }
+    }
+    // Mapping keys and array indices are recovered from keccak inputs, which requires memory capture
+    let needs_preimages = state_diff.as_ref()
+        .map(|diff| diff.addresses().iter().any(|a| registry.storage_layout(a).is_some()))
+        .unwrap_or(false);
+
+    // Opcode-level steps feed source mapping, storage decoding and the opcode gas breakdown;
+    // large transactions have millions of steps, so they are only fetched when needed
+    let opcode_gas_enabled = context.options.opcode_gas.unwrap_or(false);
+    let step_trace = if registry.has_source_maps() || needs_preimages || opcode_gas_enabled {
+        let options = StepTraceOptions { enable_memory: needs_preimages, ..Default::default() };
+        match StepTrace::fetch(&provider, &tx_details, call_tree.as_ref(), options).await {
+            Ok(steps) => Some(steps),
+            Err(e) => {
+                println!("Step trace failed: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let opcode_gas = step_trace.as_ref()
+        .filter(|_| opcode_gas_enabled)
+        .map(|steps| OpcodeGasProfile::from_steps(steps, &tx_details, call_tree.as_ref()));
+    // Each estimate re-executes the transaction twice, which is pointless for an empty list
+    let suggested_access_list = opcode_gas.as_ref()
+        .map(|profile| profile.suggested_access_list())
+        .filter(|list| !list.0.is_empty());
+    let access_list = match suggested_access_list {
+        Some(list) => match AccessListEstimate::simulate(&provider, &tx_details, list).await {
+            Ok(estimate) => Some(estimate),
+            Err(e) => {
+                println!("Access list simulation failed: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    let warnings = analyze_warnings(call_tree.as_ref(), &tx_receipt.logs, &registry, &codes, &unknown_codes);
+
+    let mut trace = format_tenderly_style(&tx_details, &tx_receipt, cast_trace.as_deref(), &registry, block.as_ref(), &units);
+    if let Some(obj) = trace.as_object_mut() {
+        if let Some(tree) = &call_tree {
+            let mut formatted = format_call_tree(tree, &registry, &units);
+            let revert_cause = RevertCause::analyze(tree, &registry);
+            for (path, status) in revert_cause.statuses() {
+                if let Some(node) = formatted_node_mut(&mut formatted, path) {
+                    node.insert("revertStatus".to_string(), json!(status));
+                }
+            }
+            if let Some(steps) = &step_trace {
+                for (path, location) in revert_locations(steps, tree, &registry) {
+                    if let Some(node) = formatted_node_mut(&mut formatted, &path) {
+                        node.insert("revertLocation".to_string(), json!(location));
+                    }
+                }
+            }
+            obj.insert("callTree".to_string(), formatted);
+            obj.insert("revertCause".to_string(), revert_cause.to_json());
+            obj.insert("gasProfile".to_string(), GasProfile::from_call_tree(tree, &registry).to_json(DEFAULT_TOP_N));
+        }
+        if let Some(steps) = &step_trace {
+            if registry.has_source_maps() {
+                obj.insert("sourceSteps".to_string(), format_source_steps(steps, &registry));
+            }
+        }
+        if let Some(profile) = &opcode_gas {
+            obj.insert("opcodeGas".to_string(), profile.to_json(tx_receipt.gas_used));
+        }
+        if let Some(estimate) = &access_list {
+            obj.insert("accessList".to_string(), estimate.to_json(tx_details.access_list.as_ref()));
+        }
+        if let Some(diff) = &state_diff {
+            let preimages = step_trace.as_ref()
+                .filter(|_| needs_preimages)
+                .map(Preimages::from_steps)
+                .unwrap_or_default();
+            obj.insert("stateDiff".to_string(), diff.to_json(&registry, &preimages, &units));
+        }
+        obj.insert("warnings".to_string(), json!(warnings));
+        obj.insert("network".to_string(), json!({
+            "name": network.name,
+            "chainId": network.chain_id,
+            "blockExplorer": network.block_explorer,
+            "nativeCurrency": network.native_currency,
+        }));
+        obj.insert("contracts".to_string(), registry.to_json());
+        if let Some(events) = obj.get_mut("events") {
+            tokens.annotate_events(events, &tx_receipt.logs, &units);
+        }
+        if let Some(tree) = &call_tree {
+            let reverted_events = obj.get_mut("events")
+                .map(|events| attribute_events(tree, events, &registry))
+                .unwrap_or_default();
+            obj.insert("revertedEvents".to_string(), Value::Array(reverted_events));
+        }
+        obj.insert("tokenTransfers".to_string(), Value::Array(
+            token_transfers.iter().map(|t| t.to_json(&tokens, &units)).collect()
+        ));
+        if let Some(tree) = &call_tree {
+            obj.insert("internalTransfers".to_string(), Value::Array(
+                InternalTransfer::collect(tree).iter().map(|t| t.to_json(&units)).collect()
+            ));
+            obj.insert("deployments".to_string(), Value::Array(
+                Deployment::collect(tree, &tx_details, step_trace.as_ref()).iter().map(Deployment::to_json).collect()
+            ));
+        }
+        obj.insert("tokens".to_string(), tokens.to_json());
+        obj.insert("balanceChanges".to_string(), balance_changes.to_json(&tokens, &units));
+    }
+    labels.apply(&mut trace);
     
     // Print formatted trace to console
     let display = format_trace_for_display(&trace);
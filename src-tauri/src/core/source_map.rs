@@ -0,0 +1,335 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Maximum characters of source kept in a location snippet
+const MAX_SNIPPET_LEN: usize = 160;
+
+/// One decompressed entry of a solc source map (`s:l:f:j:m`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceMapEntry {
+    pub offset: i64,
+    pub length: i64,
+    /// Source file index, -1 for compiler generated code
+    pub file: i64,
+    pub jump: char,
+    pub modifier_depth: i64,
+}
+
+/// Decompress a solc source map; empty fields inherit the previous entry's value
+pub fn parse_source_map(map: &str) -> Vec<SourceMapEntry> {
+    let mut entries = Vec::new();
+    let mut last = SourceMapEntry { offset: -1, length: -1, file: -1, jump: '-', modifier_depth: 0 };
+
+    if map.is_empty() {
+        return entries;
+    }
+
+    for item in map.split(';') {
+        let mut current = last;
+        for (idx, field) in item.split(':').enumerate() {
+            if field.is_empty() {
+                continue;
+            }
+            match idx {
+                0 => current.offset = field.parse().unwrap_or(current.offset),
+                1 => current.length = field.parse().unwrap_or(current.length),
+                2 => current.file = field.parse().unwrap_or(current.file),
+                3 => current.jump = field.chars().next().unwrap_or(current.jump),
+                4 => current.modifier_depth = field.parse().unwrap_or(current.modifier_depth),
+                _ => {}
+            }
+        }
+        entries.push(current);
+        last = current;
+    }
+
+    entries
+}
+
+/// Map each program counter to the index of the instruction starting there
+pub fn instruction_indices(code: &[u8]) -> Vec<Option<usize>> {
+    let mut indices = vec![None; code.len()];
+    let mut pc = 0;
+    let mut instruction = 0;
+    while pc < code.len() {
+        indices[pc] = Some(instruction);
+        let op = code[pc];
+        if (0x60..=0x7f).contains(&op) {
+            pc += (op - 0x5f) as usize;
+        }
+        pc += 1;
+        instruction += 1;
+    }
+    indices
+}
+
+/// A source file of a compilation, indexed by line for location lookups
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub path: String,
+    pub content: Option<String>,
+    line_starts: Vec<usize>,
+    /// Content with comments and string literals blanked out, offsets unchanged
+    code: Option<String>,
+}
+
+impl SourceFile {
+    pub fn new(path: String, content: Option<String>) -> Self {
+        let line_starts = match &content {
+            Some(text) => std::iter::once(0)
+                .chain(text.match_indices('\n').map(|(idx, _)| idx + 1))
+                .collect(),
+            None => Vec::new(),
+        };
+        let code = content.as_deref().map(mask_comments_and_strings);
+        Self { path, content, line_starts, code }
+    }
+
+    /// 1-based line and column of a byte offset
+    pub fn line_column(&self, offset: usize) -> Option<(usize, usize)> {
+        if self.line_starts.is_empty() {
+            return None;
+        }
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        Some((line + 1, offset - self.line_starts[line] + 1))
+    }
+
+    /// Text of a 1-based line without the trailing newline
+    pub fn line_text(&self, line: usize) -> Option<&str> {
+        let content = self.content.as_deref()?;
+        let start = *self.line_starts.get(line - 1)?;
+        let end = self.line_starts.get(line).map(|e| e - 1).unwrap_or(content.len());
+        content.get(start..end).map(|l| l.trim_end_matches('\r'))
+    }
+
+    /// Name of the function, modifier or special function enclosing an offset
+    /// Uses the closest preceding declaration, which is accurate for non-nested Solidity code
+    pub fn enclosing_function(&self, offset: usize) -> Option<String> {
+        let code = self.code.as_deref()?;
+        let before = code.get(..offset.min(code.len()))?;
+
+        let (pos, keyword) = ["function", "modifier", "constructor", "fallback", "receive"]
+            .iter()
+            .filter_map(|kw| last_keyword(before, kw).map(|pos| (pos, *kw)))
+            .max_by_key(|(pos, _)| *pos)?;
+
+        if matches!(keyword, "function" | "modifier") {
+            let name: String = before[pos + keyword.len()..]
+                .trim_start()
+                .chars()
+                .take_while(|c| is_identifier_char(*c))
+                .collect();
+            (!name.is_empty()).then_some(name)
+        } else {
+            Some(keyword.to_string())
+        }
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Offset of the last occurrence of `keyword` as a whole word
+fn last_keyword(text: &str, keyword: &str) -> Option<usize> {
+    text.rmatch_indices(keyword).map(|(pos, _)| pos).find(|pos| {
+        let before = text[..*pos].chars().next_back();
+        let after = text[pos + keyword.len()..].chars().next();
+        !before.map(is_identifier_char).unwrap_or(false) && !after.map(is_identifier_char).unwrap_or(false)
+    })
+}
+
+/// Replace the bytes of comments and string literals with spaces, keeping line breaks and offsets
+fn mask_comments_and_strings(source: &str) -> String {
+    let bytes = source.as_bytes();
+    let mut masked = bytes.to_vec();
+    let mut blank = |range: std::ops::Range<usize>| {
+        for byte in &mut masked[range] {
+            if *byte != b'\n' {
+                *byte = b' ';
+            }
+        }
+    };
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'/', Some(b'/')) => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                blank(start..i);
+            }
+            (b'/', Some(b'*')) => {
+                i += 2;
+                while i < bytes.len() && !(bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/')) {
+                    i += 1;
+                }
+                i = (i + 2).min(bytes.len());
+                blank(start..i);
+            }
+            (quote @ (b'"' | b'\''), _) => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote && bytes[i] != b'\n' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                i = (i + 1).min(bytes.len());
+                blank(start..i);
+            }
+            _ => i += 1,
+        }
+    }
+    // Only whole multi-byte characters inside comments and strings are blanked, so this stays valid UTF-8
+    String::from_utf8(masked).unwrap_or_default()
+}
+
+/// Source files of one compilation keyed by solc source index
+#[derive(Debug, Clone, Default)]
+pub struct SourceTable {
+    pub files: HashMap<i64, SourceFile>,
+}
+
+/// Location in Solidity source of an executed instruction
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    /// Full text of the line
+    pub code: String,
+    /// Source range mapped to the instruction (first line only)
+    pub snippet: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+}
+
+/// Deployed source map of a contract, ready for program counter lookups
+#[derive(Debug, Clone)]
+pub struct ContractSourceMap {
+    instructions: Vec<Option<usize>>,
+    entries: Vec<SourceMapEntry>,
+    sources: Arc<SourceTable>,
+}
+
+impl ContractSourceMap {
+    pub fn new(deployed_bytecode: &[u8], source_map: &str, sources: Arc<SourceTable>) -> Self {
+        Self {
+            instructions: instruction_indices(deployed_bytecode),
+            entries: parse_source_map(source_map),
+            sources,
+        }
+    }
+
+    /// Resolve a program counter to its Solidity source location
+    pub fn location(&self, pc: usize) -> Option<SourceLocation> {
+        let instruction = (*self.instructions.get(pc)?)?;
+        let entry = self.entries.get(instruction)?;
+        if entry.file < 0 || entry.offset < 0 {
+            return None;
+        }
+        let file = self.sources.files.get(&entry.file)?;
+        let offset = entry.offset as usize;
+        let (line, column) = file.line_column(offset)?;
+
+        let end = offset + entry.length.max(0) as usize;
+        let snippet = file.content.as_deref()
+            .and_then(|c| c.get(offset..end.min(c.len())))
+            .and_then(|s| s.lines().next())
+            .unwrap_or_default();
+
+        Some(SourceLocation {
+            file: file.path.clone(),
+            line,
+            column,
+            code: file.line_text(line).unwrap_or_default().trim().to_string(),
+            snippet: snippet.chars().take(MAX_SNIPPET_LEN).collect(),
+            function: file.enclosing_function(offset),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(offset: i64, length: i64, file: i64, jump: char, modifier_depth: i64) -> SourceMapEntry {
+        SourceMapEntry { offset, length, file, jump, modifier_depth }
+    }
+
+    #[test]
+    fn decompresses_source_map() {
+        // Example from the Solidity documentation: same as `1:2:1;1:9:1;2:1:2;2:1:2;2:1:2`
+        let entries = parse_source_map("1:2:1;:9;2:1:2;;");
+        assert_eq!(entries, vec![
+            entry(1, 2, 1, '-', 0),
+            entry(1, 9, 1, '-', 0),
+            entry(2, 1, 2, '-', 0),
+            entry(2, 1, 2, '-', 0),
+            entry(2, 1, 2, '-', 0),
+        ]);
+        assert!(parse_source_map("").is_empty());
+    }
+
+    #[test]
+    fn inherits_jump_and_modifier_depth() {
+        let entries = parse_source_map("0:10:0:i:1;5;:::o;-1:-1:-1:-:0");
+        assert_eq!(entries, vec![
+            entry(0, 10, 0, 'i', 1),
+            entry(5, 10, 0, 'i', 1),
+            entry(5, 10, 0, 'o', 1),
+            entry(-1, -1, -1, '-', 0),
+        ]);
+    }
+
+    #[test]
+    fn indexes_instructions_past_push_data() {
+        // PUSH1 0x01, PUSH2 0x0203, STOP
+        let indices = instruction_indices(&[0x60, 0x01, 0x61, 0x02, 0x03, 0x00]);
+        assert_eq!(indices, vec![Some(0), None, Some(1), None, None, Some(2)]);
+    }
+
+    #[test]
+    fn resolves_program_counter_to_line() {
+        let source = "contract A {\n    function f() external {\n        revert();\n    }\n}\n";
+        let revert_offset = source.find("revert").unwrap() as i64;
+        let mut table = SourceTable::default();
+        table.files.insert(0, SourceFile::new("src/A.sol".to_string(), Some(source.to_string())));
+        let map = format!("0:{}:0;{}:8:0;-1:-1:-1", source.len(), revert_offset);
+        // PUSH1 0x00, DUP1, STOP
+        let source_map = ContractSourceMap::new(&[0x60, 0x00, 0x80, 0x00], &map, Arc::new(table));
+
+        let location = source_map.location(2).unwrap();
+        assert_eq!((location.file.as_str(), location.line, location.column), ("src/A.sol", 3, 9));
+        assert_eq!(location.code, "revert();");
+        assert_eq!(location.snippet, "revert()");
+        assert_eq!(location.function.as_deref(), Some("f"));
+        // Push data and compiler generated code have no location
+        assert!(source_map.location(1).is_none());
+        assert!(source_map.location(3).is_none());
+    }
+
+    #[test]
+    fn finds_enclosing_function_outside_comments_and_strings() {
+        let source = "contract A {\n    // function fake()\n    function real() external {\n        string memory s = \"function bogus\";\n        /* modifier nope */ uint x; // receive\n        x;\n    }\n    function receiveTokens() public { y; }\n    receive() external payable { z; }\n}\n";
+        let file = SourceFile::new("A.sol".to_string(), Some(source.to_string()));
+        let at = |needle: &str| source.find(needle).unwrap();
+        assert_eq!(file.enclosing_function(at("x;")).as_deref(), Some("real"));
+        assert_eq!(file.enclosing_function(at("y;")).as_deref(), Some("receiveTokens"));
+        assert_eq!(file.enclosing_function(at("z;")).as_deref(), Some("receive"));
+        assert_eq!(file.enclosing_function(at("// function")), None);
+    }
+
+    #[test]
+    fn masks_comments_and_strings_in_place() {
+        let source = "a /* b\nc */ d // e\n'f\\'g' \"h\"";
+        let masked = mask_comments_and_strings(source);
+        assert_eq!(masked.len(), source.len());
+        assert_eq!(masked, "a     \n     d     \n          ");
+    }
+}
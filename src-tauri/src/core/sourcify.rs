@@ -0,0 +1,280 @@
+use ethers::types::Address;
+use ethers::utils::to_checksum;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::types::{AbiEntry, VerifiedContract};
+use super::cache::{DiskCache, NOT_VERIFIED_TTL};
+use super::contract_registry::ContractRegistry;
+use super::storage_layout::StorageLayout;
+
+/// Public Sourcify repository used when none is configured
+pub const DEFAULT_SOURCIFY_REPOSITORY: &str = "https://repo.sourcify.dev";
+/// Sourcify API of the public repository, which also serves compiler outputs such as storage layouts
+pub const DEFAULT_SOURCIFY_API: &str = "https://sourcify.dev/server";
+
+/// Match types in the order they are tried
+const MATCH_TYPES: [&str; 2] = ["full_match", "partial_match"];
+
+/// Where a Sourcify repository is served from
+#[derive(Debug, Clone)]
+pub enum SourcifyRepository {
+    Http(String),
+    /// Local mirror of the repository (e.g. an extracted Sourcify snapshot)
+    Local(PathBuf),
+}
+
+impl SourcifyRepository {
+    /// Parse a repository location: `http(s)://...` or a directory path
+    pub fn parse(location: &str) -> Self {
+        let location = location.trim();
+        if location.starts_with("http://") || location.starts_with("https://") {
+            SourcifyRepository::Http(location.trim_end_matches('/').to_string())
+        } else {
+            SourcifyRepository::Local(PathBuf::from(location))
+        }
+    }
+}
+
+/// Resolves verified contracts from a Sourcify repository layout:
+/// `contracts/{full_match,partial_match}/<chainId>/<address>/metadata.json` and `sources/`
+#[derive(Debug, Clone)]
+pub struct SourcifyClient {
+    repository: SourcifyRepository,
+    chain_id: u64,
+    http: reqwest::Client,
+    cache: Option<DiskCache>,
+    offline: bool,
+    /// Sourcify API matching the repository, for data the repository files lack
+    api_url: Option<String>,
+}
+
+impl SourcifyClient {
+    pub fn new(repository: SourcifyRepository, chain_id: u64) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(20))
+            .build()
+            .unwrap_or_default();
+        Self { repository, chain_id, http, cache: None, offline: false, api_url: None }
+    }
+
+    /// Query this Sourcify API for storage layouts
+    pub fn with_api(mut self, api_url: &str) -> Self {
+        self.api_url = Some(api_url.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Cache contracts fetched over HTTP on disk
+    pub fn with_cache(mut self, cache: DiskCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Skip HTTP requests; local repositories and the cache are still used
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    fn cache_namespace(&self) -> String {
+        format!("sourcify/{}", self.chain_id)
+    }
+
+    /// Find a contract in the repository, preferring full matches
+    /// Contracts missing from the repository return a `VerifiedContract` with `verified: false`,
+    /// cached for `NOT_VERIFIED_TTL` only
+    pub async fn contract(&self, address: Address) -> anyhow::Result<Option<VerifiedContract>> {
+        let key = format!("{:?}", address);
+        let is_http = matches!(self.repository, SourcifyRepository::Http(_));
+
+        if is_http {
+            let cached = self.cache.as_ref()
+                .and_then(|c| c.get::<VerifiedContract>(&self.cache_namespace(), &key))
+                .filter(|c| self.offline || c.is_fresh(NOT_VERIFIED_TTL));
+            if let Some(cached) = cached {
+                return Ok(Some(cached));
+            }
+            if self.offline {
+                return Ok(None);
+            }
+        }
+
+        let mut found = None;
+        for match_type in MATCH_TYPES {
+            let dir = format!("contracts/{}/{}/{}", match_type, self.chain_id, to_checksum(&address, None));
+            if let Some(metadata) = self.read_json(&format!("{}/metadata.json", dir)).await? {
+                found = Some(self.load_contract(&key, &dir, &metadata).await?);
+                break;
+            }
+        }
+
+        let contract = found.unwrap_or_else(|| VerifiedContract {
+            address: key.clone(),
+            verified: false,
+            contract_name: None,
+            compiler_version: None,
+            optimization_used: None,
+            runs: None,
+            evm_version: None,
+            abi: None,
+            sources: BTreeMap::new(),
+            implementation: None,
+            fetched_at: chrono::Utc::now().to_rfc3339(),
+        });
+
+        if is_http {
+            if let Some(cache) = &self.cache {
+                if let Err(e) = cache.put(&self.cache_namespace(), &key, &contract) {
+                    println!("[SOURCIFY] Failed to cache {}: {}", key, e);
+                }
+            }
+        }
+        Ok(Some(contract))
+    }
+
+    /// Build a verified contract from `metadata.json` and the `sources/` directory
+    async fn load_contract(&self, address: &str, dir: &str, metadata: &Value) -> anyhow::Result<VerifiedContract> {
+        let abi = metadata.pointer("/output/abi")
+            .and_then(|abi| serde_json::from_value::<Vec<AbiEntry>>(abi.clone()).ok());
+        let contract_name = metadata.pointer("/settings/compilationTarget")
+            .and_then(|t| t.as_object())
+            .and_then(|t| t.values().next())
+            .and_then(|n| n.as_str())
+            .map(|n| n.to_string());
+
+        let mut sources = BTreeMap::new();
+        if let Some(files) = metadata.get("sources").and_then(|s| s.as_object()) {
+            for (path, file) in files {
+                let content = match file.get("content").and_then(|c| c.as_str()) {
+                    Some(content) => Some(content.to_string()),
+                    None => self.read_text(&format!("{}/sources/{}", dir, path)).await?,
+                };
+                match content {
+                    Some(content) => {
+                        sources.insert(path.clone(), content);
+                    }
+                    None => println!("[SOURCIFY] Missing source {} for {}", path, address),
+                }
+            }
+        }
+
+        Ok(VerifiedContract {
+            address: address.to_string(),
+            verified: abi.is_some(),
+            contract_name,
+            compiler_version: metadata.pointer("/compiler/version").and_then(|v| v.as_str()).map(|v| v.to_string()),
+            optimization_used: metadata.pointer("/settings/optimizer/enabled").and_then(|v| v.as_bool()),
+            runs: metadata.pointer("/settings/optimizer/runs").and_then(|v| v.as_u64()),
+            evm_version: metadata.pointer("/settings/evmVersion").and_then(|v| v.as_str()).map(|v| v.to_string()),
+            abi,
+            sources,
+            implementation: None,
+            fetched_at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+
+    async fn read_json(&self, path: &str) -> anyhow::Result<Option<Value>> {
+        match self.read_text(path).await? {
+            Some(text) => Ok(Some(serde_json::from_str(&text)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Read a file from the repository; missing files return `None`
+    async fn read_text(&self, path: &str) -> anyhow::Result<Option<String>> {
+        match &self.repository {
+            SourcifyRepository::Local(root) => {
+                let file = root.join(path);
+                if !file.is_file() {
+                    return Ok(None);
+                }
+                Ok(Some(tokio::fs::read_to_string(file).await?))
+            }
+            SourcifyRepository::Http(base) => {
+                let response = self.http.get(format!("{}/{}", base, path)).send().await?;
+                if response.status() == reqwest::StatusCode::NOT_FOUND {
+                    return Ok(None);
+                }
+                Ok(Some(response.error_for_status()?.text().await?))
+            }
+        }
+    }
+
+    /// Storage layout Sourcify recorded for a contract, if it was compiled with that output
+    /// Only found layouts are cached, as Sourcify may add them later
+    pub async fn storage_layout(&self, address: Address) -> anyhow::Result<Option<Value>> {
+        let Some(api_url) = &self.api_url else { return Ok(None) };
+        let namespace = format!("sourcify-layout/{}", self.chain_id);
+        let key = format!("{:?}", address);
+        if let Some(cached) = self.cache.as_ref().and_then(|c| c.get::<Value>(&namespace, &key)) {
+            return Ok(Some(cached));
+        }
+        if self.offline {
+            return Ok(None);
+        }
+
+        let url = format!("{}/v2/contract/{}/{}", api_url, self.chain_id, to_checksum(&address, None));
+        let response = self.http.get(url).query(&[("fields", "storageLayout")]).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body: Value = response.error_for_status()?.json().await?;
+        let layout = body.get("storageLayout").filter(|l| !l.is_null()).cloned();
+        if let (Some(layout), Some(cache)) = (&layout, &self.cache) {
+            if let Err(e) = cache.put(&namespace, &key, layout) {
+                println!("[SOURCIFY] Failed to cache storage layout of {}: {}", key, e);
+            }
+        }
+        Ok(layout)
+    }
+
+    /// Register the Sourcify storage layout of verified contracts that have none yet
+    /// Proxies get their implementation's layout; returns the number of layouts registered
+    pub async fn register_storage_layouts(&self, addresses: &BTreeSet<Address>, registry: &mut ContractRegistry) -> usize {
+        let mut registered = 0;
+        for address in addresses {
+            if registry.storage_layout(address).is_some() {
+                continue;
+            }
+            let target = registry.proxy(address).and_then(|p| p.implementation).unwrap_or(*address);
+            if !registry.get(&target).map(|c| c.abi.is_some()).unwrap_or(false) {
+                continue;
+            }
+            match self.storage_layout(target).await {
+                Ok(Some(layout)) => match StorageLayout::from_value(&layout) {
+                    Some(layout) => {
+                        registry.register_storage_layout(target, Arc::new(layout));
+                        registered += 1;
+                    }
+                    None => println!("[SOURCIFY] Unreadable storage layout for {:?}", target),
+                },
+                Ok(None) => {}
+                Err(e) => println!("[SOURCIFY] Failed to fetch storage layout of {:?}: {}", target, e),
+            }
+        }
+        registered
+    }
+
+    /// Look up every address not yet known to the registry and register verified contracts
+    /// Returns the number of contracts registered
+    pub async fn register_verified(&self, addresses: &[Address], registry: &mut ContractRegistry) -> usize {
+        let mut registered = 0;
+        for address in addresses {
+            if registry.get(address).map(|c| c.abi.is_some()).unwrap_or(false) {
+                continue;
+            }
+            match self.contract(*address).await {
+                Ok(Some(contract)) if contract.verified => {
+                    println!("[SOURCIFY] {:?} resolved as {}", address, contract.contract_name.as_deref().unwrap_or("?"));
+                    registry.register(contract.to_contract_info());
+                    registered += 1;
+                }
+                Ok(_) => {}
+                Err(e) => println!("[SOURCIFY] Failed to resolve {:?}: {}", address, e),
+            }
+        }
+        registered
+    }
+}
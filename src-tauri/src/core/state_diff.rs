@@ -0,0 +1,139 @@
+use ethers::providers::{Http, Provider};
+use ethers::types::{Address, Bytes, Transaction, H256, U256};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use super::call_tree::trace_call_request;
+use super::contract_registry::ContractRegistry;
+use super::storage_layout::Preimages;
+use super::units::UnitFormatter;
+
+/// Account fields reported by geth's `prestateTracer`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AccountState {
+    #[serde(default)]
+    pub balance: Option<U256>,
+    #[serde(default)]
+    pub nonce: Option<u64>,
+    #[serde(default)]
+    pub code: Option<Bytes>,
+    #[serde(default)]
+    pub storage: Option<BTreeMap<H256, H256>>,
+}
+
+/// State before and after a transaction (`prestateTracer` in diff mode)
+/// `post` only holds modified fields; storage slots cleared to zero are left out of it
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StateDiff {
+    #[serde(default)]
+    pub pre: BTreeMap<Address, AccountState>,
+    #[serde(default)]
+    pub post: BTreeMap<Address, AccountState>,
+}
+
+/// A storage slot written by the transaction
+#[derive(Debug, Clone)]
+pub struct StorageChange {
+    pub address: Address,
+    pub slot: H256,
+    pub before: H256,
+    pub after: H256,
+}
+
+impl StateDiff {
+    /// Re-execute the transaction on the fork with the prestate tracer
+    pub async fn fetch(provider: &Provider<Http>, tx: &Transaction) -> anyhow::Result<Self> {
+        let call = trace_call_request(tx);
+        let options = json!({
+            "tracer": "prestateTracer",
+            "tracerConfig": { "diffMode": true },
+        });
+
+        let raw: Value = provider
+            .request("debug_traceCall", (call, "latest", options))
+            .await
+            .map_err(|e| anyhow::anyhow!("debug_traceCall failed: {}", e))?;
+
+        serde_json::from_value(raw).map_err(|e| anyhow::anyhow!("Unexpected prestateTracer output: {}", e))
+    }
+
+    pub fn addresses(&self) -> BTreeSet<Address> {
+        self.pre.keys().chain(self.post.keys()).copied().collect()
+    }
+
+    /// Slots whose value differs before and after the transaction
+    pub fn storage_changes(&self, address: &Address) -> Vec<StorageChange> {
+        let empty = BTreeMap::new();
+        let pre = self.pre.get(address).and_then(|a| a.storage.as_ref()).unwrap_or(&empty);
+        let post = self.post.get(address).and_then(|a| a.storage.as_ref()).unwrap_or(&empty);
+
+        let slots: BTreeSet<&H256> = pre.keys().chain(post.keys()).collect();
+        slots.into_iter().filter_map(|slot| {
+            let before = pre.get(slot).copied().unwrap_or_default();
+            let after = post.get(slot).copied().unwrap_or_default();
+            (before != after).then_some(StorageChange { address: *address, slot: *slot, before, after })
+        }).collect()
+    }
+
+    /// Changes per account, with storage slots decoded through the registered storage layouts
+    pub fn to_json(&self, registry: &ContractRegistry, preimages: &Preimages, units: &UnitFormatter) -> Value {
+        let accounts: Vec<Value> = self.addresses().into_iter().filter_map(|address| {
+            let pre = self.pre.get(&address).cloned().unwrap_or_default();
+            let post = self.post.get(&address).cloned().unwrap_or_default();
+            let mut account = serde_json::Map::new();
+
+            if let Some(after) = post.balance.filter(|after| Some(*after) != pre.balance) {
+                let before = pre.balance.unwrap_or_default();
+                account.insert("balance".to_string(), json!({
+                    "before": before.to_string(),
+                    "after": after.to_string(),
+                    "formattedBefore": units.native(before),
+                    "formattedAfter": units.native(after),
+                }));
+            }
+            if let Some(after) = post.nonce.filter(|after| Some(*after) != pre.nonce) {
+                account.insert("nonce".to_string(), json!({ "before": pre.nonce.unwrap_or_default(), "after": after }));
+            }
+            if post.code.is_some() && post.code != pre.code {
+                account.insert("codeChanged".to_string(), json!(true));
+            }
+
+            let layout = registry.storage_layout(&address);
+            let storage: Vec<Value> = self.storage_changes(&address).iter().map(|change| {
+                let slot = U256::from_big_endian(change.slot.as_bytes());
+                let before = change.before.to_fixed_bytes();
+                let after = change.after.to_fixed_bytes();
+                let decoded: Vec<Value> = layout
+                    .map(|layout| layout.decode_slot(slot, preimages))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|variable| (variable.format_value(&before), variable.format_value(&after), variable))
+                    .filter(|(before, after, _)| before != after)
+                    .map(|(before, after, variable)| json!({
+                        "variable": variable.label,
+                        "type": variable.type_label,
+                        "before": before,
+                        "after": after,
+                    }))
+                    .collect();
+                json!({
+                    "slot": format!("{:?}", change.slot),
+                    "before": format!("{:?}", change.before),
+                    "after": format!("{:?}", change.after),
+                    "decoded": decoded,
+                })
+            }).collect();
+            if !storage.is_empty() {
+                account.insert("storage".to_string(), Value::Array(storage));
+            }
+
+            if account.is_empty() {
+                return None;
+            }
+            account.insert("address".to_string(), json!(format!("{:?}", address)));
+            account.insert("contract".to_string(), json!(registry.name(&address)));
+            Some(Value::Object(account))
+        }).collect();
+        Value::Array(accounts)
+    }
+}
@@ -0,0 +1,247 @@
+use ethers::providers::{Http, Provider};
+use ethers::types::{Address, DefaultFrame, StructLog, Transaction};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use super::call_tree::{trace_call_request, CallNode};
+use super::contract_registry::ContractRegistry;
+use super::source_map::SourceLocation;
+
+/// Maximum number of source-mapped steps included in the trace output
+const MAX_SOURCE_STEPS: usize = 20_000;
+
+/// Call frame entered while stepping through the struct log
+#[derive(Debug, Clone)]
+pub struct StepFrame {
+    /// Path of the frame in the call tree (see `CallNode::walk`)
+    pub path: Vec<usize>,
+    /// Address whose code is executing, `None` for init code of an unknown CREATE
+    pub code_address: Option<Address>,
+    /// Address whose storage is read and written
+    pub storage_address: Option<Address>,
+    /// Whether the frame runs init code (CREATE/CREATE2 or a deployment transaction)
+    pub is_create: bool,
+}
+
+/// Opcode-level execution trace with every step attributed to its call frame
+#[derive(Debug, Clone)]
+pub struct StepTrace {
+    pub failed: bool,
+    pub logs: Vec<StructLog>,
+    pub frames: Vec<StepFrame>,
+    /// Index into `frames` for each entry of `logs`
+    pub step_frames: Vec<usize>,
+}
+
+/// Options for the struct logger
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StepTraceOptions {
+    pub enable_memory: bool,
+    pub enable_storage: bool,
+}
+
+pub fn is_call_op(op: &str) -> bool {
+    matches!(op, "CALL" | "CALLCODE" | "DELEGATECALL" | "STATICCALL" | "CREATE" | "CREATE2")
+}
+
+impl StepTrace {
+    /// Re-execute the transaction on the fork with the default struct logger
+    pub async fn fetch(
+        provider: &Provider<Http>,
+        tx: &Transaction,
+        call_tree: Option<&CallNode>,
+        options: StepTraceOptions,
+    ) -> anyhow::Result<Self> {
+        let call = trace_call_request(tx);
+        let tracer_options = json!({
+            "enableMemory": options.enable_memory,
+            "disableStorage": !options.enable_storage,
+            "disableStack": false,
+            "enableReturnData": false,
+        });
+
+        let frame: DefaultFrame = provider
+            .request("debug_traceCall", (call, "latest", tracer_options))
+            .await
+            .map_err(|e| anyhow::anyhow!("debug_traceCall failed: {}", e))?;
+
+        println!("[STEPS] Collected {} steps", frame.struct_logs.len());
+        Ok(Self::from_frame(frame, tx.to, call_tree))
+    }
+
+    /// Attribute each step to a call frame
+    /// Frames are matched to the call tree by counting call opcodes, which keeps
+    /// calls to precompiles and accounts without code aligned with the tree
+    pub fn from_frame(frame: DefaultFrame, root: Option<Address>, call_tree: Option<&CallNode>) -> Self {
+        let logs = frame.struct_logs;
+        let mut frames = vec![StepFrame {
+            path: Vec::new(),
+            code_address: root,
+            storage_address: root,
+            is_create: root.is_none(),
+        }];
+        let mut step_frames = Vec::with_capacity(logs.len());
+
+        // Active frames (index into `frames`, number of call opcodes executed so far)
+        let mut stack: Vec<(usize, usize)> = vec![(0, 0)];
+        let mut pending_call: Option<(usize, &StructLog)> = None;
+
+        for log in logs.iter() {
+            let depth = log.depth.max(1) as usize;
+
+            if depth > stack.len() {
+                if let Some((caller_depth, call_log)) = pending_call.take() {
+                    if caller_depth + 1 == depth {
+                        let (parent, child_idx) = stack[caller_depth - 1];
+                        let parent_frame = &frames[parent];
+                        let mut path = parent_frame.path.clone();
+                        path.push(child_idx - 1);
+
+                        let node = call_tree.and_then(|tree| tree.node_at(&path));
+                        let target = node.and_then(|n| n.to).or_else(|| call_target(call_log));
+                        let is_create = call_log.op.starts_with("CREATE");
+                        let storage_address = match call_log.op.as_str() {
+                            "DELEGATECALL" | "CALLCODE" => parent_frame.storage_address,
+                            _ => target,
+                        };
+
+                        frames.push(StepFrame { path, code_address: target, storage_address, is_create });
+                        stack.push((frames.len() - 1, 0));
+                    }
+                }
+            }
+            while depth < stack.len() {
+                stack.pop();
+            }
+
+            let current = stack.last().map(|(frame, _)| *frame).unwrap_or(0);
+            step_frames.push(current);
+
+            pending_call = None;
+            if is_call_op(&log.op) {
+                if let Some(top) = stack.last_mut() {
+                    top.1 += 1;
+                }
+                pending_call = Some((depth, log));
+            }
+        }
+
+        Self { failed: frame.failed, logs, frames, step_frames }
+    }
+
+    pub fn len(&self) -> usize {
+        self.logs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.logs.is_empty()
+    }
+
+    /// Iterate over steps together with the frame executing them
+    pub fn steps(&self) -> impl Iterator<Item = (usize, &StructLog, &StepFrame)> {
+        self.logs.iter().enumerate().map(|(idx, log)| (idx, log, &self.frames[self.step_frames[idx]]))
+    }
+
+    /// Step of the call opcode that opened each sub-call, keyed by the callee's path
+    pub fn call_steps(&self) -> HashMap<Vec<usize>, usize> {
+        let mut calls_made = vec![0; self.frames.len()];
+        let mut steps = HashMap::new();
+        for (idx, log) in self.logs.iter().enumerate() {
+            if is_call_op(&log.op) {
+                let frame = self.step_frames[idx];
+                let mut path = self.frames[frame].path.clone();
+                path.push(calls_made[frame]);
+                calls_made[frame] += 1;
+                steps.insert(path, idx);
+            }
+        }
+        steps
+    }
+
+    /// Index of the last step executed by each frame
+    pub fn last_step_per_frame(&self) -> Vec<Option<usize>> {
+        let mut last = vec![None; self.frames.len()];
+        for (idx, frame) in self.step_frames.iter().enumerate() {
+            last[*frame] = Some(idx);
+        }
+        last
+    }
+}
+
+/// Target address of a CALL-family opcode taken from its stack arguments
+fn call_target(log: &StructLog) -> Option<Address> {
+    if log.op.starts_with("CREATE") {
+        return None;
+    }
+    // The stack is reported bottom to top: the callee is the second item from the top
+    let stack = log.stack.as_ref()?;
+    let raw = stack.get(stack.len().checked_sub(2)?)?;
+    let mut bytes = [0u8; 32];
+    raw.to_big_endian(&mut bytes);
+    Some(Address::from_slice(&bytes[12..]))
+}
+
+/// Serialize a step for the UI
+pub fn step_to_json(idx: usize, log: &StructLog, frame: &StepFrame) -> Value {
+    json!({
+        "step": idx,
+        "pc": log.pc,
+        "op": log.op,
+        "depth": log.depth,
+        "gas": log.gas,
+        "gasCost": log.gas_cost,
+        "frame": frame.path,
+        "address": frame.code_address.map(|a| format!("{:?}", a)),
+    })
+}
+
+/// Source location of a step, when its frame runs linked runtime code
+pub fn step_location(frame: &StepFrame, log: &StructLog, registry: &ContractRegistry) -> Option<SourceLocation> {
+    if frame.is_create {
+        return None;
+    }
+    registry.source_map(&frame.code_address?)?.location(log.pc as usize)
+}
+
+/// Steps executed in linked contracts, each with its source location
+/// Output is capped at `MAX_SOURCE_STEPS` entries to keep the trace responsive
+pub fn format_source_steps(trace: &StepTrace, registry: &ContractRegistry) -> Value {
+    let mut steps = Vec::new();
+    let mut total = 0;
+    for (idx, log, frame) in trace.steps() {
+        let Some(location) = step_location(frame, log, registry) else { continue };
+        total += 1;
+        if steps.len() < MAX_SOURCE_STEPS {
+            let mut step = step_to_json(idx, log, frame);
+            step["source"] = json!(location);
+            steps.push(step);
+        }
+    }
+
+    json!({
+        "steps": steps,
+        "total": total,
+        "truncated": total > MAX_SOURCE_STEPS,
+    })
+}
+
+/// Source location where each reverted frame stopped executing
+/// Walks back from the frame's last step to skip compiler generated revert code
+pub fn revert_locations(trace: &StepTrace, call_tree: &CallNode, registry: &ContractRegistry) -> Vec<(Vec<usize>, SourceLocation)> {
+    let last_steps = trace.last_step_per_frame();
+    let mut locations = Vec::new();
+
+    for (frame_idx, frame) in trace.frames.iter().enumerate() {
+        let reverted = call_tree.node_at(&frame.path).map(|n| !n.is_success()).unwrap_or(false);
+        let Some(last) = last_steps[frame_idx] else { continue };
+        if !reverted {
+            continue;
+        }
+        let location = (0..=last).rev()
+            .filter(|idx| trace.step_frames[*idx] == frame_idx)
+            .find_map(|idx| step_location(frame, &trace.logs[idx], registry));
+        if let Some(location) = location {
+            locations.push((frame.path.clone(), location));
+        }
+    }
+    locations
+}
@@ -0,0 +1,566 @@
+use ethers::types::{Address, I256, U256};
+use ethers::utils::keccak256;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use super::step_trace::StepTrace;
+
+/// Largest distance between a hashed slot and a slot it is the base of (struct members, array elements)
+const MAX_HASHED_OFFSET: u64 = 1 << 24;
+/// Nesting limit when resolving slots through keccak preimages (`a[k1][k2]...`)
+const MAX_DEPTH: usize = 16;
+/// Largest keccak input kept as a preimage; mapping keys are 64 bytes unless they are strings
+const MAX_PREIMAGE_LENGTH: usize = 4096;
+
+/// Inputs of every KECCAK256 executed by a transaction, by hash
+#[derive(Debug, Clone, Default)]
+pub struct Preimages {
+    preimages: BTreeMap<U256, Vec<u8>>,
+}
+
+impl Preimages {
+    /// Collect preimages from a step trace captured with memory enabled
+    pub fn from_steps(trace: &StepTrace) -> Self {
+        let mut preimages = BTreeMap::new();
+        for log in &trace.logs {
+            if log.op != "KECCAK256" && log.op != "SHA3" {
+                continue;
+            }
+            let (Some(stack), Some(memory)) = (&log.stack, &log.memory) else { continue };
+            // The stack is reported bottom to top: offset on top, then size
+            let (Some(offset), Some(size)) = (stack.last(), stack.len().checked_sub(2).and_then(|i| stack.get(i))) else { continue };
+            if offset.bits() > 32 || size.bits() > 32 || size.as_usize() > MAX_PREIMAGE_LENGTH {
+                continue;
+            }
+            let (offset, size) = (offset.as_usize(), size.as_usize());
+
+            let mut data = vec![0u8; size];
+            for (i, byte) in data.iter_mut().enumerate() {
+                let position = offset + i;
+                if let Some(word) = memory.get(position / 32) {
+                    let idx = (position % 32) * 2;
+                    if let Some(hex_byte) = word.get(idx..idx + 2) {
+                        *byte = u8::from_str_radix(hex_byte, 16).unwrap_or(0);
+                    }
+                }
+            }
+            preimages.insert(U256::from_big_endian(&keccak256(&data)), data);
+        }
+        Self { preimages }
+    }
+
+    pub fn len(&self) -> usize {
+        self.preimages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.preimages.is_empty()
+    }
+
+    /// Closest hash at or below a slot, with the slot's distance from it
+    fn base_of(&self, slot: U256) -> Option<(U256, u64, &[u8])> {
+        let (hash, preimage) = self.preimages.range(..=slot).next_back()?;
+        let delta = slot - *hash;
+        (delta < U256::from(MAX_HASHED_OFFSET)).then(|| (*hash, delta.as_u64(), preimage.as_slice()))
+    }
+}
+
+/// solc `storageLayout` output
+#[derive(Debug, Clone, Deserialize)]
+pub struct StorageLayout {
+    storage: Vec<StorageVariable>,
+    #[serde(default)]
+    types: Option<HashMap<String, StorageType>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct StorageVariable {
+    label: String,
+    offset: usize,
+    slot: String,
+    #[serde(rename = "type")]
+    type_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StorageType {
+    encoding: String,
+    label: String,
+    number_of_bytes: String,
+    key: Option<String>,
+    value: Option<String>,
+    base: Option<String>,
+    #[serde(default)]
+    members: Option<Vec<StorageVariable>>,
+}
+
+/// Variable (or part of one) stored in a slot
+#[derive(Debug, Clone)]
+pub struct SlotVariable {
+    /// Access path, e.g. `balances[0xabc…]` or `config.owner`
+    pub label: String,
+    /// Solidity type, e.g. `uint256`
+    pub type_label: String,
+    type_id: String,
+    kind: ValueKind,
+    /// Byte offset from the right end of the slot
+    offset: usize,
+    size: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    Value,
+    /// Short `string`/`bytes` stored in place, or the length of a long one
+    Bytes { is_string: bool },
+    /// Length of a dynamic array
+    Length,
+    /// Part of the data of a long `string`/`bytes`
+    Chunk,
+    /// Mapping base slot, which holds no data; kept to resolve the slots of its values
+    Mapping,
+}
+
+/// Position of a variable in storage while resolving a slot
+struct Located {
+    label: String,
+    type_id: String,
+    slot: U256,
+    offset: usize,
+}
+
+impl StorageLayout {
+    pub fn from_value(value: &Value) -> Option<Self> {
+        serde_json::from_value(value.clone()).ok()
+    }
+
+    fn type_of(&self, type_id: &str) -> Option<&StorageType> {
+        self.types.as_ref()?.get(type_id)
+    }
+
+    fn byte_size(&self, type_id: &str) -> usize {
+        self.type_of(type_id).and_then(|t| t.number_of_bytes.parse().ok()).unwrap_or(32)
+    }
+
+    /// Number of slots occupied in place by a type
+    fn slot_count(&self, type_id: &str) -> U256 {
+        U256::from(self.byte_size(type_id).div_ceil(32).max(1))
+    }
+
+    fn contains(&self, located: &Located, slot: U256) -> bool {
+        located.slot <= slot && slot < located.slot.saturating_add(self.slot_count(&located.type_id))
+    }
+
+    /// Variables stored in a slot, resolving mapping and dynamic array slots through keccak preimages
+    pub fn decode_slot(&self, slot: U256, preimages: &Preimages) -> Vec<SlotVariable> {
+        let mut variables = self.variables_at(slot, preimages, 0);
+        variables.retain(|v| v.kind != ValueKind::Mapping);
+        variables
+    }
+
+    fn variables_at(&self, slot: U256, preimages: &Preimages, depth: usize) -> Vec<SlotVariable> {
+        let mut variables = Vec::new();
+        for root in self.roots(slot, preimages, depth) {
+            self.descend(root, slot, &mut variables);
+        }
+        variables
+    }
+
+    /// Outermost variables whose storage covers a slot
+    fn roots(&self, slot: U256, preimages: &Preimages, depth: usize) -> Vec<Located> {
+        let top_level: Vec<Located> = self.storage.iter()
+            .filter_map(|v| Some(Located {
+                label: v.label.clone(),
+                type_id: v.type_id.clone(),
+                slot: U256::from_dec_str(&v.slot).ok()?,
+                offset: v.offset,
+            }))
+            .filter(|v| self.contains(v, slot))
+            .collect();
+        if !top_level.is_empty() || depth >= MAX_DEPTH {
+            return top_level;
+        }
+
+        let Some((hash, delta, preimage)) = preimages.base_of(slot) else { return Vec::new() };
+        let mut roots = Vec::new();
+
+        if preimage.len() == 32 {
+            // Data of a dynamic array or long string/bytes stored at keccak(p)
+            let parent = U256::from_big_endian(preimage);
+            for owner in self.variables_at(parent, preimages, depth + 1) {
+                let Some(owner_type) = self.find_type(&owner) else { continue };
+                let owner_label = owner.label.strip_suffix(".length").unwrap_or(&owner.label);
+                match (owner_type.encoding.as_str(), &owner_type.base) {
+                    ("dynamic_array", Some(base)) => {
+                        let element_size = self.byte_size(base);
+                        if element_size >= 32 {
+                            let slots = self.slot_count(base).as_u64();
+                            let index = delta / slots;
+                            roots.push(Located {
+                                label: format!("{}[{}]", owner_label, index),
+                                type_id: base.clone(),
+                                slot: hash + U256::from(index * slots),
+                                offset: 0,
+                            });
+                        } else {
+                            let per_slot = (32 / element_size) as u64;
+                            for i in 0..per_slot {
+                                roots.push(Located {
+                                    label: format!("{}[{}]", owner_label, delta * per_slot + i),
+                                    type_id: base.clone(),
+                                    slot,
+                                    offset: i as usize * element_size,
+                                });
+                            }
+                        }
+                    }
+                    ("bytes", _) => roots.push(Located {
+                        label: format!("{} (data)", owner_label),
+                        type_id: String::new(),
+                        slot,
+                        offset: 0,
+                    }),
+                    _ => {}
+                }
+            }
+        } else if preimage.len() > 32 {
+            // Mapping value at keccak(key . p)
+            let (key, parent) = preimage.split_at(preimage.len() - 32);
+            let parent = U256::from_big_endian(parent);
+            for owner in self.variables_at(parent, preimages, depth + 1) {
+                let Some(owner_type) = self.find_type(&owner) else { continue };
+                if owner_type.encoding != "mapping" {
+                    continue;
+                }
+                let (Some(key_type), Some(value_type)) = (&owner_type.key, &owner_type.value) else { continue };
+                let key_label = self.type_of(key_type).map(|t| t.label.as_str()).unwrap_or("");
+                let located = Located {
+                    label: format!("{}[{}]", owner.label, format_key(key, key_label)),
+                    type_id: value_type.clone(),
+                    slot: hash,
+                    offset: 0,
+                };
+                if self.contains(&located, slot) {
+                    roots.push(located);
+                }
+            }
+        }
+        roots
+    }
+
+    /// Narrow a located variable down to the leaves stored in `slot`
+    fn descend(&self, located: Located, slot: U256, out: &mut Vec<SlotVariable>) {
+        let Some(ty) = self.type_of(&located.type_id) else {
+            // Chunks of long string/bytes data have no type of their own
+            if located.type_id.is_empty() && located.slot == slot {
+                out.push(SlotVariable {
+                    label: located.label,
+                    type_label: "bytes".to_string(),
+                    type_id: String::new(),
+                    kind: ValueKind::Chunk,
+                    offset: 0,
+                    size: 32,
+                });
+            }
+            return;
+        };
+
+        if let Some(members) = ty.members.as_ref().filter(|m| !m.is_empty() && ty.encoding == "inplace") {
+            for member in members {
+                let Ok(member_slot) = U256::from_dec_str(&member.slot) else { continue };
+                let child = Located {
+                    label: format!("{}.{}", located.label, member.label),
+                    type_id: member.type_id.clone(),
+                    slot: located.slot + member_slot,
+                    offset: member.offset,
+                };
+                if self.contains(&child, slot) {
+                    self.descend(child, slot, out);
+                }
+            }
+            return;
+        }
+
+        if let (Some(base), "inplace") = (&ty.base, ty.encoding.as_str()) {
+            // Static array
+            let element_size = self.byte_size(base);
+            let length = static_array_length(&ty.label).unwrap_or(0);
+            let relative = (slot - located.slot).as_u64();
+            if element_size >= 32 {
+                let slots = self.slot_count(base).as_u64();
+                let index = relative / slots;
+                if index < length {
+                    self.descend(Located {
+                        label: format!("{}[{}]", located.label, index),
+                        type_id: base.clone(),
+                        slot: located.slot + U256::from(index * slots),
+                        offset: 0,
+                    }, slot, out);
+                }
+            } else {
+                let per_slot = (32 / element_size) as u64;
+                for i in 0..per_slot {
+                    let index = relative * per_slot + i;
+                    if index < length {
+                        self.descend(Located {
+                            label: format!("{}[{}]", located.label, index),
+                            type_id: base.clone(),
+                            slot,
+                            offset: i as usize * element_size,
+                        }, slot, out);
+                    }
+                }
+            }
+            return;
+        }
+
+        if located.slot != slot {
+            return;
+        }
+        let (label, kind) = match ty.encoding.as_str() {
+            "dynamic_array" => (format!("{}.length", located.label), ValueKind::Length),
+            "bytes" => (located.label, ValueKind::Bytes { is_string: ty.label == "string" }),
+            "mapping" => (located.label, ValueKind::Mapping),
+            _ => (located.label, ValueKind::Value),
+        };
+        out.push(SlotVariable {
+            label,
+            type_label: ty.label.clone(),
+            type_id: located.type_id.clone(),
+            kind,
+            offset: located.offset,
+            size: self.byte_size(&located.type_id).min(32),
+        });
+    }
+
+    /// Type of a leaf that can own hashed storage (mapping, dynamic array, long string/bytes)
+    fn find_type(&self, variable: &SlotVariable) -> Option<&StorageType> {
+        if variable.offset != 0 {
+            return None;
+        }
+        self.type_of(&variable.type_id)
+            .filter(|t| matches!(t.encoding.as_str(), "mapping" | "dynamic_array" | "bytes"))
+    }
+}
+
+impl SlotVariable {
+    /// Format the variable's value as stored in a 32-byte slot word
+    pub fn format_value(&self, word: &[u8; 32]) -> String {
+        let end = 32 - self.offset.min(32);
+        let start = end.saturating_sub(self.size);
+        let bytes = &word[start..end];
+        match self.kind {
+            ValueKind::Chunk | ValueKind::Mapping => format!("0x{}", hex::encode(word)),
+            ValueKind::Length => U256::from_big_endian(bytes).to_string(),
+            ValueKind::Bytes { is_string } => {
+                if word[31] & 1 == 0 {
+                    let length = (word[31] / 2) as usize;
+                    let data = &word[..length.min(31)];
+                    if is_string {
+                        format!("{:?}", String::from_utf8_lossy(data))
+                    } else {
+                        format!("0x{}", hex::encode(data))
+                    }
+                } else {
+                    let length = (U256::from_big_endian(word) - 1) / 2;
+                    format!("<{} bytes>", length)
+                }
+            }
+            ValueKind::Value => format_value(bytes, &self.type_label),
+        }
+    }
+}
+
+/// Format a value type from its (right-aligned) bytes
+fn format_value(bytes: &[u8], type_label: &str) -> String {
+    let value = U256::from_big_endian(bytes);
+    if type_label == "bool" {
+        return (!value.is_zero()).to_string();
+    }
+    if type_label.starts_with("address") || type_label.starts_with("contract ") {
+        let mut word = [0u8; 32];
+        value.to_big_endian(&mut word);
+        return format!("{:?}", Address::from_slice(&word[12..]));
+    }
+    if type_label.starts_with("uint") || type_label.starts_with("enum ") {
+        return value.to_string();
+    }
+    if type_label.starts_with("int") {
+        return signed_value(value, bytes.len()).to_string();
+    }
+    format!("0x{}", hex::encode(bytes))
+}
+
+/// Sign-extend a two's complement value of `size` bytes
+fn signed_value(value: U256, size: usize) -> I256 {
+    if size == 0 || size >= 32 {
+        return I256::from_raw(value);
+    }
+    let bits = size * 8;
+    if value.bit(bits - 1) {
+        let mask = (U256::one() << bits) - 1;
+        I256::from_raw(value | !mask)
+    } else {
+        I256::from_raw(value)
+    }
+}
+
+/// Format a mapping key from its keccak preimage bytes
+/// Value types are ABI-encoded to 32 bytes; strings and bytes are hashed as they are
+fn format_key(key: &[u8], type_label: &str) -> String {
+    if type_label == "string" {
+        return format!("{:?}", String::from_utf8_lossy(key));
+    }
+    if key.len() != 32 || type_label == "bytes" {
+        return format!("0x{}", hex::encode(key));
+    }
+    if let Some(size) = type_label.strip_prefix("bytes").and_then(|n| n.parse::<usize>().ok()) {
+        // Fixed-size bytes are left-aligned
+        return format!("0x{}", hex::encode(&key[..size.min(32)]));
+    }
+    format_value(key, type_label)
+}
+
+/// Length of a static array type label such as `uint256[3]`
+fn static_array_length(label: &str) -> Option<u64> {
+    let inner = label.strip_suffix(']')?;
+    inner[inner.rfind('[')? + 1..].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// `address owner; bool paused; int16 delta; mapping(address => uint256) balances; uint128[] items; string name;`
+    fn layout() -> StorageLayout {
+        let variable = |label: &str, slot: &str, offset: usize, ty: &str| json!({
+            "label": label, "slot": slot, "offset": offset, "type": ty
+        });
+        StorageLayout::from_value(&json!({
+            "storage": [
+                variable("owner", "0", 0, "t_address"),
+                variable("paused", "0", 20, "t_bool"),
+                variable("delta", "0", 21, "t_int16"),
+                variable("balances", "1", 0, "t_mapping(t_address,t_uint256)"),
+                variable("items", "2", 0, "t_array(t_uint128)dyn_storage"),
+                variable("name", "3", 0, "t_string_storage"),
+            ],
+            "types": {
+                "t_address": { "encoding": "inplace", "label": "address", "numberOfBytes": "20" },
+                "t_bool": { "encoding": "inplace", "label": "bool", "numberOfBytes": "1" },
+                "t_int16": { "encoding": "inplace", "label": "int16", "numberOfBytes": "2" },
+                "t_uint128": { "encoding": "inplace", "label": "uint128", "numberOfBytes": "16" },
+                "t_uint256": { "encoding": "inplace", "label": "uint256", "numberOfBytes": "32" },
+                "t_mapping(t_address,t_uint256)": {
+                    "encoding": "mapping", "label": "mapping(address => uint256)", "numberOfBytes": "32",
+                    "key": "t_address", "value": "t_uint256"
+                },
+                "t_array(t_uint128)dyn_storage": {
+                    "encoding": "dynamic_array", "label": "uint128[]", "numberOfBytes": "32", "base": "t_uint128"
+                },
+                "t_string_storage": { "encoding": "bytes", "label": "string", "numberOfBytes": "32" }
+            }
+        })).unwrap()
+    }
+
+    fn preimages(inputs: &[Vec<u8>]) -> Preimages {
+        Preimages {
+            preimages: inputs.iter().map(|i| (U256::from_big_endian(&keccak256(i)), i.clone())).collect(),
+        }
+    }
+
+    fn word(value: U256) -> [u8; 32] {
+        let mut word = [0u8; 32];
+        value.to_big_endian(&mut word);
+        word
+    }
+
+    fn decoded(variables: &[SlotVariable], word: &[u8; 32]) -> Vec<(String, String)> {
+        variables.iter().map(|v| (v.label.clone(), v.format_value(word))).collect()
+    }
+
+    #[test]
+    fn decodes_packed_slot() {
+        let mut slot = [0u8; 32];
+        slot[12..].copy_from_slice(&[0x11; 20]);
+        slot[11] = 1;
+        slot[9..11].copy_from_slice(&[0xff, 0xfe]);
+        let variables = layout().decode_slot(U256::zero(), &Preimages::default());
+        assert_eq!(decoded(&variables, &slot), vec![
+            ("owner".to_string(), "0x1111111111111111111111111111111111111111".to_string()),
+            ("paused".to_string(), "true".to_string()),
+            ("delta".to_string(), "-2".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn decodes_mapping_value_through_preimage() {
+        let holder = Address::repeat_byte(0x22);
+        let mut key = [0u8; 64];
+        key[12..32].copy_from_slice(holder.as_bytes());
+        key[63] = 1;
+        let slot = U256::from_big_endian(&keccak256(key));
+
+        let variables = layout().decode_slot(slot, &preimages(&[key.to_vec()]));
+        assert_eq!(decoded(&variables, &word(U256::exp10(18))), vec![
+            ("balances[0x2222222222222222222222222222222222222222]".to_string(), "1000000000000000000".to_string()),
+        ]);
+        // The mapping's own slot holds no data
+        assert!(layout().decode_slot(U256::one(), &Preimages::default()).is_empty());
+    }
+
+    #[test]
+    fn decodes_packed_dynamic_array_elements() {
+        let base_input = word(U256::from(2)).to_vec();
+        let data = U256::from_big_endian(&keccak256(&base_input));
+        let preimages = preimages(&[base_input]);
+
+        let length = layout().decode_slot(U256::from(2), &preimages);
+        assert_eq!(decoded(&length, &word(U256::from(4))), vec![("items.length".to_string(), "4".to_string())]);
+
+        // Two uint128 per slot: the second data slot holds elements 2 and 3
+        let value = (U256::from(7) << 128) | U256::from(5);
+        let elements = layout().decode_slot(data + 1, &preimages);
+        assert_eq!(decoded(&elements, &word(value)), vec![
+            ("items[2]".to_string(), "5".to_string()),
+            ("items[3]".to_string(), "7".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn decodes_short_and_long_strings() {
+        let variables = layout().decode_slot(U256::from(3), &Preimages::default());
+        assert_eq!(variables.len(), 1);
+
+        let mut short = [0u8; 32];
+        short[..5].copy_from_slice(b"hello");
+        short[31] = 10;
+        assert_eq!(variables[0].format_value(&short), "\"hello\"");
+        // Long strings store 2 * length + 1 in the slot and their data at keccak(slot)
+        assert_eq!(variables[0].format_value(&word(U256::from(2 * 100 + 1))), "<100 bytes>");
+
+        let data = U256::from_big_endian(&keccak256(word(U256::from(3))));
+        let chunks = layout().decode_slot(data + 2, &preimages(&[word(U256::from(3)).to_vec()]));
+        assert_eq!(chunks.iter().map(|v| v.label.as_str()).collect::<Vec<_>>(), vec!["name (data)"]);
+    }
+
+    #[test]
+    fn ignores_unrelated_slots() {
+        assert!(layout().decode_slot(U256::from(4), &Preimages::default()).is_empty());
+        let unknown = U256::from_big_endian(&keccak256([0xab; 64]));
+        assert!(layout().decode_slot(unknown, &Preimages::default()).is_empty());
+    }
+
+    #[test]
+    fn formats_fixed_size_values() {
+        assert_eq!(signed_value(U256::from(0x80), 1).to_string(), "-128");
+        assert_eq!(signed_value(U256::from(0x7f), 1).to_string(), "127");
+        assert_eq!(format_key(&word(U256::from(0xabcd) << 240), "bytes2"), "0xabcd");
+        assert_eq!(format_key(b"key", "string"), "\"key\"");
+        assert_eq!(static_array_length("uint256[3]"), Some(3));
+        assert_eq!(static_array_length("uint8[2][4]"), Some(4));
+        assert_eq!(static_array_length("uint256[]"), None);
+    }
+}
@@ -0,0 +1,405 @@
+use ethers::providers::{Http, Middleware, Provider, RpcError};
+use ethers::types::{transaction::eip2718::TypedTransaction, Address, Log, TransactionRequest, H256, U256};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use crate::types::TokenInfo;
+use super::cache::DiskCache;
+use super::units::UnitFormatter;
+
+/// `Transfer(address,address,uint256)` (ERC-20 and ERC-721)
+const TRANSFER_TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+/// `Approval(address,address,uint256)` (ERC-20 and ERC-721)
+pub const APPROVAL_TOPIC: &str = "0x8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925";
+/// `ApprovalForAll(address,address,bool)` (ERC-721 and ERC-1155)
+pub const APPROVAL_FOR_ALL_TOPIC: &str = "0x17307eab39ab6107e8899845ad3d59bd9653f200f220920489ca2b5937696c31";
+/// `TransferSingle(address,address,address,uint256,uint256)`
+const TRANSFER_SINGLE_TOPIC: &str = "0xc3d58168c5ae7397731d063d5bbf3d657854427343f4c083240f7aacaa2d0f62";
+/// `TransferBatch(address,address,address,uint256[],uint256[])`
+const TRANSFER_BATCH_TOPIC: &str = "0x4a39dc06d4c0dbc64b70af90fd698a233a518aa5d07e595d983b8c0526c8f7fb";
+
+const NAME_SELECTOR: [u8; 4] = [0x06, 0xfd, 0xde, 0x03];
+const SYMBOL_SELECTOR: [u8; 4] = [0x95, 0xd8, 0x9b, 0x41];
+const DECIMALS_SELECTOR: [u8; 4] = [0x31, 0x3c, 0xe5, 0x67];
+
+/// Allowances from `type(uint96).max` up count as unlimited: uint96-based tokens such as COMP
+/// and UNI store their own maximum instead of `type(uint256).max`
+const UNLIMITED_ALLOWANCE_BITS: usize = 96;
+
+/// Token standard a transfer event belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TokenStandard {
+    #[serde(rename = "ERC-20")]
+    Erc20,
+    #[serde(rename = "ERC-721")]
+    Erc721,
+    #[serde(rename = "ERC-1155")]
+    Erc1155,
+}
+
+/// Token movement decoded from a transfer event
+#[derive(Debug, Clone)]
+pub struct TokenTransfer {
+    /// Index of the log in the receipt
+    pub log_index: usize,
+    pub standard: TokenStandard,
+    pub token: Address,
+    pub from: Address,
+    pub to: Address,
+    /// ERC-1155 operator
+    pub operator: Option<Address>,
+    /// ERC-721/1155 token ID
+    pub token_id: Option<U256>,
+    /// Amount in the token's smallest unit (1 for ERC-721)
+    pub amount: U256,
+}
+
+impl TokenTransfer {
+    pub fn to_json(&self, tokens: &TokenRegistry, units: &UnitFormatter) -> Value {
+        let info = tokens.get(&self.token);
+        json!({
+            "logIndex": self.log_index,
+            "standard": self.standard,
+            "token": format!("{:?}", self.token),
+            "tokenName": info.and_then(|i| i.name.clone()),
+            "symbol": info.and_then(|i| i.symbol.clone()),
+            "from": format!("{:?}", self.from),
+            "to": format!("{:?}", self.to),
+            "operator": self.operator.map(|o| format!("{:?}", o)),
+            "tokenId": self.token_id.map(|id| id.to_string()),
+            "amount": self.amount.to_string(),
+            "formattedAmount": match self.standard {
+                TokenStandard::Erc20 => tokens.format_amount(&self.token, self.amount, units),
+                _ => self.amount.to_string(),
+            },
+        })
+    }
+}
+
+/// Decode ERC-20, ERC-721 and ERC-1155 transfers from receipt logs
+/// ERC-20 and ERC-721 share the `Transfer` signature and are told apart by the indexed token ID
+pub fn parse_token_transfers(logs: &[Log]) -> Vec<TokenTransfer> {
+    let (Ok(transfer), Ok(single), Ok(batch)) = (
+        TRANSFER_TOPIC.parse::<H256>(),
+        TRANSFER_SINGLE_TOPIC.parse::<H256>(),
+        TRANSFER_BATCH_TOPIC.parse::<H256>(),
+    ) else {
+        return Vec::new();
+    };
+
+    let mut transfers = Vec::new();
+    for (log_index, log) in logs.iter().enumerate() {
+        let Some(topic0) = log.topics.first() else { continue };
+        let data = log.data.as_ref();
+
+        if *topic0 == transfer && log.topics.len() == 3 && data.len() == 32 {
+            transfers.push(TokenTransfer {
+                log_index,
+                standard: TokenStandard::Erc20,
+                token: log.address,
+                from: topic_address(&log.topics[1]),
+                to: topic_address(&log.topics[2]),
+                operator: None,
+                token_id: None,
+                amount: U256::from_big_endian(data),
+            });
+        } else if *topic0 == transfer && log.topics.len() == 4 {
+            transfers.push(TokenTransfer {
+                log_index,
+                standard: TokenStandard::Erc721,
+                token: log.address,
+                from: topic_address(&log.topics[1]),
+                to: topic_address(&log.topics[2]),
+                operator: None,
+                token_id: Some(U256::from_big_endian(log.topics[3].as_bytes())),
+                amount: U256::one(),
+            });
+        } else if *topic0 == single && log.topics.len() == 4 && data.len() == 64 {
+            transfers.push(TokenTransfer {
+                log_index,
+                standard: TokenStandard::Erc1155,
+                token: log.address,
+                from: topic_address(&log.topics[2]),
+                to: topic_address(&log.topics[3]),
+                operator: Some(topic_address(&log.topics[1])),
+                token_id: Some(U256::from_big_endian(&data[..32])),
+                amount: U256::from_big_endian(&data[32..64]),
+            });
+        } else if *topic0 == batch && log.topics.len() == 4 {
+            let Some((ids, values)) = decode_batch(data) else { continue };
+            for (id, amount) in ids.into_iter().zip(values) {
+                transfers.push(TokenTransfer {
+                    log_index,
+                    standard: TokenStandard::Erc1155,
+                    token: log.address,
+                    from: topic_address(&log.topics[2]),
+                    to: topic_address(&log.topics[3]),
+                    operator: Some(topic_address(&log.topics[1])),
+                    token_id: Some(id),
+                    amount,
+                });
+            }
+        }
+    }
+    transfers
+}
+
+/// Contracts emitting token transfer or approval events
+pub fn token_addresses(logs: &[Log]) -> BTreeSet<Address> {
+    let topics: Vec<H256> = [TRANSFER_TOPIC, APPROVAL_TOPIC, TRANSFER_SINGLE_TOPIC, TRANSFER_BATCH_TOPIC]
+        .iter()
+        .filter_map(|t| t.parse().ok())
+        .collect();
+    logs.iter()
+        .filter(|log| log.topics.first().map(|t| topics.contains(t)).unwrap_or(false))
+        .map(|log| log.address)
+        .collect()
+}
+
+/// Decode the `(uint256[] ids, uint256[] values)` data of a `TransferBatch` event
+fn decode_batch(data: &[u8]) -> Option<(Vec<U256>, Vec<U256>)> {
+    let tokens = ethers::abi::decode(
+        &[
+            ethers::abi::ParamType::Array(Box::new(ethers::abi::ParamType::Uint(256))),
+            ethers::abi::ParamType::Array(Box::new(ethers::abi::ParamType::Uint(256))),
+        ],
+        data,
+    ).ok()?;
+    let mut arrays = tokens.into_iter().map(|t| {
+        t.into_array().unwrap_or_default().into_iter().filter_map(|v| v.into_uint()).collect::<Vec<_>>()
+    });
+    Some((arrays.next()?, arrays.next()?))
+}
+
+fn topic_address(topic: &H256) -> Address {
+    Address::from_slice(&topic.as_bytes()[12..])
+}
+
+/// Metadata of the tokens touched by a transaction
+#[derive(Debug, Clone, Default)]
+pub struct TokenRegistry {
+    tokens: HashMap<Address, TokenInfo>,
+}
+
+impl TokenRegistry {
+    /// Read `name()`, `symbol()` and `decimals()` of each token from the fork
+    /// Found metadata is cached per chain, as it does not change in practice; tokens without any
+    /// (deployed later in the traced transaction, or whose calls revert) are not cached
+    pub async fn fetch(
+        provider: &Provider<Http>,
+        addresses: &BTreeSet<Address>,
+        chain_id: u64,
+        cache: Option<&DiskCache>,
+    ) -> Self {
+        let namespace = format!("tokens/{}", chain_id);
+        let mut tokens = HashMap::new();
+
+        for address in addresses {
+            let key = format!("{:?}", address);
+            if let Some(cached) = cache.and_then(|c| c.get::<TokenInfo>(&namespace, &key)) {
+                tokens.insert(*address, cached);
+                continue;
+            }
+            match fetch_token_info(provider, *address).await {
+                Ok(info) => {
+                    let found = info.name.is_some() || info.symbol.is_some() || info.decimals.is_some();
+                    if let Some(cache) = cache.filter(|_| found) {
+                        if let Err(e) = cache.put(&namespace, &key, &info) {
+                            println!("[TOKENS] Failed to cache {}: {}", key, e);
+                        }
+                    }
+                    tokens.insert(*address, info);
+                }
+                Err(e) => println!("[TOKENS] Failed to read metadata of {}: {}", key, e),
+            }
+        }
+        Self { tokens }
+    }
+
+    pub fn get(&self, address: &Address) -> Option<&TokenInfo> {
+        self.tokens.get(address)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Amount scaled by the token's decimals and suffixed with its symbol
+    /// Falls back to the raw amount when the token has no `decimals()`
+    pub fn format_amount(&self, token: &Address, amount: U256, units: &UnitFormatter) -> String {
+        let info = self.tokens.get(token);
+        let Some(decimals) = info.and_then(|i| i.decimals) else {
+            return amount.to_string();
+        };
+        let value = units.amount(amount, decimals);
+        match info.and_then(|i| i.symbol.as_deref()) {
+            Some(symbol) => format!("{} {}", value, symbol),
+            None => value,
+        }
+    }
+
+    /// Add a `formattedAmount` to ERC-20 `Transfer` and `Approval` entries of the events list
+    pub fn annotate_events(&self, events: &mut Value, logs: &[Log], units: &UnitFormatter) {
+        let topics: Vec<H256> = [TRANSFER_TOPIC, APPROVAL_TOPIC].iter().filter_map(|t| t.parse().ok()).collect();
+        let approval = APPROVAL_TOPIC.parse::<H256>().ok();
+        let Some(events) = events.as_array_mut() else { return };
+
+        for (event, log) in events.iter_mut().zip(logs) {
+            let is_amount_event = log.topics.len() == 3
+                && log.data.len() == 32
+                && log.topics.first().map(|t| topics.contains(t)).unwrap_or(false);
+            if !is_amount_event || self.get(&log.address).and_then(|i| i.decimals).is_none() {
+                continue;
+            }
+            let amount = U256::from_big_endian(&log.data);
+            let formatted = if log.topics.first() == approval.as_ref() && is_unlimited_allowance(amount) {
+                "unlimited".to_string()
+            } else {
+                self.format_amount(&log.address, amount, units)
+            };
+            if let Some(obj) = event.as_object_mut() {
+                obj.insert("formattedAmount".to_string(), json!(formatted));
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut tokens: Vec<&TokenInfo> = self.tokens.values().collect();
+        tokens.sort_by(|a, b| a.address.cmp(&b.address));
+        json!(tokens)
+    }
+}
+
+/// Whether an ERC-20 allowance is large enough to be treated as unlimited
+pub fn is_unlimited_allowance(amount: U256) -> bool {
+    amount >= (U256::one() << UNLIMITED_ALLOWANCE_BITS) - 1
+}
+
+async fn fetch_token_info(provider: &Provider<Http>, address: Address) -> anyhow::Result<TokenInfo> {
+    let name = call_view(provider, address, NAME_SELECTOR).await?.and_then(|out| decode_string(&out));
+    let symbol = call_view(provider, address, SYMBOL_SELECTOR).await?.and_then(|out| decode_string(&out));
+    let decimals = call_view(provider, address, DECIMALS_SELECTOR).await?.and_then(|out| {
+        let value = (out.len() == 32).then(|| U256::from_big_endian(&out))?;
+        (value <= U256::from(u8::MAX)).then(|| value.as_u32() as u8)
+    });
+    Ok(TokenInfo { address: format!("{:?}", address), name, symbol, decimals })
+}
+
+/// Call a parameterless view function; reverts and missing functions return `None`
+async fn call_view(provider: &Provider<Http>, to: Address, selector: [u8; 4]) -> anyhow::Result<Option<Vec<u8>>> {
+    let tx: TypedTransaction = TransactionRequest::new().to(to).data(selector.to_vec()).into();
+    match provider.call(&tx, None).await {
+        Ok(output) if !output.is_empty() => Ok(Some(output.to_vec())),
+        Ok(_) => Ok(None),
+        Err(e) if e.as_error_response().is_some() => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Decode a `string` return value, or a `bytes32` one as used by early tokens (e.g. MKR)
+fn decode_string(output: &[u8]) -> Option<String> {
+    let text = if output.len() == 32 {
+        let end = output.iter().position(|b| *b == 0).unwrap_or(32);
+        String::from_utf8(output[..end].to_vec()).ok()?
+    } else {
+        let tokens = ethers::abi::decode(&[ethers::abi::ParamType::String], output).ok()?;
+        tokens.into_iter().next()?.into_string()?
+    };
+    let text = text.trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::NativeCurrency;
+
+    fn registry(decimals: Option<u8>, symbol: Option<&str>) -> (Address, TokenRegistry) {
+        let token = Address::repeat_byte(0x70);
+        let info = TokenInfo {
+            address: format!("{:?}", token),
+            name: None,
+            symbol: symbol.map(str::to_string),
+            decimals,
+        };
+        (token, TokenRegistry { tokens: HashMap::from([(token, info)]) })
+    }
+
+    fn amount_log(token: Address, topic: &str, amount: U256, extra_topic: Option<H256>) -> Log {
+        let mut data = [0u8; 32];
+        amount.to_big_endian(&mut data);
+        let mut topics = vec![topic.parse().unwrap(), H256::repeat_byte(0x01), H256::repeat_byte(0x02)];
+        topics.extend(extra_topic);
+        Log { address: token, topics, data: data.to_vec().into(), ..Default::default() }
+    }
+
+    #[test]
+    fn decodes_string_and_bytes32_symbols() {
+        let mut mkr = [0u8; 32];
+        mkr[..3].copy_from_slice(b"MKR");
+        assert_eq!(decode_string(&mkr).as_deref(), Some("MKR"));
+        assert_eq!(decode_string(&[0u8; 32]), None);
+
+        let encoded = ethers::abi::encode(&[ethers::abi::Token::String(" Dai Stablecoin ".to_string())]);
+        assert_eq!(decode_string(&encoded).as_deref(), Some("Dai Stablecoin"));
+        assert_eq!(decode_string(&[0x01, 0x02]), None);
+    }
+
+    #[test]
+    fn formats_amounts_with_known_decimals_only() {
+        let units = UnitFormatter::new(&NativeCurrency::default(), None);
+        let amount = U256::from(1_500_000);
+
+        let (token, tokens) = registry(None, Some("USDC"));
+        assert_eq!(tokens.format_amount(&token, amount, &units), "1500000");
+        let (token, tokens) = registry(Some(6), Some("USDC"));
+        assert_eq!(tokens.format_amount(&token, amount, &units), "1.5 USDC");
+        let (token, tokens) = registry(Some(6), None);
+        assert_eq!(tokens.format_amount(&token, amount, &units), "1.5");
+        assert_eq!(tokens.format_amount(&Address::zero(), amount, &units), "1500000");
+    }
+
+    #[test]
+    fn treats_uint96_max_allowances_as_unlimited() {
+        let uint96_max = (U256::one() << 96) - 1;
+        assert!(is_unlimited_allowance(U256::MAX));
+        assert!(is_unlimited_allowance(uint96_max));
+        assert!(!is_unlimited_allowance(uint96_max - 1));
+    }
+
+    #[test]
+    fn shows_unlimited_approvals_in_events() {
+        let units = UnitFormatter::new(&NativeCurrency::default(), None);
+        let (token, tokens) = registry(Some(18), Some("UNI"));
+        let uint96_max = (U256::one() << 96) - 1;
+        let logs = vec![
+            amount_log(token, APPROVAL_TOPIC, uint96_max, None),
+            amount_log(token, APPROVAL_TOPIC, U256::exp10(18), None),
+            amount_log(token, TRANSFER_TOPIC, uint96_max, None),
+        ];
+        let mut events = serde_json::json!([{}, {}, {}]);
+        tokens.annotate_events(&mut events, &logs, &units);
+
+        assert_eq!(events[0]["formattedAmount"], "unlimited");
+        assert_eq!(events[1]["formattedAmount"], "1 UNI");
+        assert_eq!(events[2]["formattedAmount"], "79228162514.264337593543950335 UNI");
+    }
+
+    #[test]
+    fn tells_erc20_and_erc721_transfers_apart() {
+        let token = Address::repeat_byte(0x70);
+        let logs = vec![
+            amount_log(token, TRANSFER_TOPIC, U256::from(5), None),
+            Log { data: Default::default(), ..amount_log(token, TRANSFER_TOPIC, U256::zero(), Some(H256::from_low_u64_be(42))) },
+        ];
+        let transfers = parse_token_transfers(&logs);
+
+        assert_eq!(transfers.len(), 2);
+        assert_eq!((transfers[0].standard, transfers[0].amount, transfers[0].token_id), (TokenStandard::Erc20, U256::from(5), None));
+        assert_eq!(transfers[0].from, Address::repeat_byte(0x01));
+        assert_eq!(transfers[0].to, Address::repeat_byte(0x02));
+        assert_eq!(transfers[1].standard, TokenStandard::Erc721);
+        assert_eq!((transfers[1].amount, transfers[1].token_id), (U256::one(), Some(U256::from(42))));
+        assert_eq!(token_addresses(&logs).into_iter().collect::<Vec<_>>(), vec![token]);
+    }
+}
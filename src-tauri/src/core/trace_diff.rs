@@ -0,0 +1,432 @@
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use super::trace_formatter::items;
+
+/// Call frame fields compared between aligned frames
+const FRAME_FIELDS: [&str; 9] = ["function", "to", "value", "input", "output", "gasUsed", "success", "error", "revertReason"];
+/// Largest LCS table built when aligning two lists of children (16 MB of `u32`)
+const MAX_LCS_CELLS: usize = 4_000_000;
+/// Transaction-level fields compared first, as (section, field)
+const OVERVIEW_FIELDS: [(&str, &str); 6] = [
+    ("overview", "status"),
+    ("transactionInfo", "to"),
+    ("transactionInfo", "function"),
+    ("transactionInfo", "value"),
+    ("gasDetails", "gasUsed"),
+    ("gasDetails", "totalCost"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffStatus {
+    Same,
+    Changed,
+    Added,
+    Removed,
+}
+
+/// Number of entries per status
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct DiffCounts {
+    pub same: usize,
+    pub changed: usize,
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl DiffCounts {
+    fn add(&mut self, status: DiffStatus) {
+        match status {
+            DiffStatus::Same => self.same += 1,
+            DiffStatus::Changed => self.changed += 1,
+            DiffStatus::Added => self.added += 1,
+            DiffStatus::Removed => self.removed += 1,
+        }
+    }
+}
+
+/// Compare two traces produced by `trace_transaction` (`left` is usually the passing one)
+/// Call trees are aligned frame by frame, events in order of emission and state changes by
+/// account and storage slot
+pub fn diff_traces(left: &Value, right: &Value) -> Value {
+    let overview: Vec<Value> = OVERVIEW_FIELDS.iter()
+        .filter(|(section, field)| left[section][field] != right[section][field])
+        .map(|(section, field)| json!({
+            "field": format!("{}.{}", section, field),
+            "left": left[section][field],
+            "right": right[section][field],
+        }))
+        .collect();
+
+    let mut frame_counts = DiffCounts::default();
+    let call_tree = match (left.get("callTree"), right.get("callTree")) {
+        (None, None) => Value::Null,
+        (l, r) => diff_frame(l, r, &mut frame_counts).0,
+    };
+
+    let mut event_counts = DiffCounts::default();
+    let events = diff_events(items(left, "events"), items(right, "events"), &mut event_counts);
+
+    let mut state_counts = DiffCounts::default();
+    let state = diff_state(items(left, "stateDiff"), items(right, "stateDiff"), &mut state_counts);
+
+    json!({
+        "left": summary(left),
+        "right": summary(right),
+        "overview": overview,
+        "callTree": call_tree,
+        "events": events,
+        "stateDiff": state,
+        "summary": {
+            "frames": frame_counts,
+            "events": event_counts,
+            "accounts": state_counts,
+        },
+    })
+}
+
+fn summary(trace: &Value) -> Value {
+    json!({
+        "transactionHash": trace["overview"]["transactionHash"],
+        "status": trace["overview"]["status"],
+        "block": trace["overview"]["block"],
+    })
+}
+
+/// Pair up two lists: a longest common subsequence on `key`, then the items left between two
+/// matches paired in order when `similar`, so a call to another function shows as changed
+/// Common prefixes and suffixes are matched first; when what remains would need an LCS table
+/// of more than `MAX_LCS_CELLS`, it is paired by position instead
+fn align<K: PartialEq>(
+    left: &[Value],
+    right: &[Value],
+    key: impl Fn(&Value) -> K,
+    similar: impl Fn(&Value, &Value) -> bool,
+) -> Vec<(Option<usize>, Option<usize>)> {
+    let left_keys: Vec<K> = left.iter().map(&key).collect();
+    let right_keys: Vec<K> = right.iter().map(&key).collect();
+    let (n, m) = (left.len(), right.len());
+
+    let prefix = left_keys.iter().zip(&right_keys).take_while(|(a, b)| a == b).count();
+    let suffix = left_keys[prefix..].iter().rev().zip(right_keys[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (left_middle, right_middle) = (&left_keys[prefix..n - suffix], &right_keys[prefix..m - suffix]);
+
+    let mut matches: Vec<(usize, usize)> = (0..prefix).map(|k| (k, k)).collect();
+    if (left_middle.len() + 1).saturating_mul(right_middle.len() + 1) <= MAX_LCS_CELLS {
+        matches.extend(lcs(left_middle, right_middle).into_iter().map(|(i, j)| (prefix + i, prefix + j)));
+    } else {
+        matches.extend((0..left_middle.len().min(right_middle.len())).map(|k| (prefix + k, prefix + k)));
+    }
+    matches.extend((0..suffix).map(|k| (n - suffix + k, m - suffix + k)));
+    matches.push((n, m));
+
+    let mut pairs = Vec::with_capacity(n.max(m));
+    let (mut i, mut j) = (0, 0);
+    for (next_i, next_j) in matches {
+        while i < next_i || j < next_j {
+            match (i < next_i, j < next_j) {
+                (true, true) if similar(&left[i], &right[j]) => {
+                    pairs.push((Some(i), Some(j)));
+                    i += 1;
+                    j += 1;
+                }
+                (true, _) => {
+                    pairs.push((Some(i), None));
+                    i += 1;
+                }
+                _ => {
+                    pairs.push((None, Some(j)));
+                    j += 1;
+                }
+            }
+        }
+        if next_i < n {
+            pairs.push((Some(next_i), Some(next_j)));
+        }
+        i = next_i + 1;
+        j = next_j + 1;
+    }
+    pairs
+}
+
+/// Index pairs of a longest common subsequence of two key lists
+fn lcs<K: PartialEq>(left: &[K], right: &[K]) -> Vec<(usize, usize)> {
+    let (n, m) = (left.len(), right.len());
+    // lengths[i][j]: LCS length of left[i..] and right[j..]
+    let mut lengths = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = if left[i] == right[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut matches = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if left[i] == right[j] {
+            matches.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    matches
+}
+
+fn field_changes(left: &Value, right: &Value, fields: &[&str]) -> Vec<Value> {
+    fields.iter()
+        .filter(|field| left[**field] != right[**field])
+        .map(|field| json!({ "field": field, "left": left[*field], "right": right[*field] }))
+        .collect()
+}
+
+/// Diff of a frame and its subtree; the flag tells whether anything below differs
+fn diff_frame(left: Option<&Value>, right: Option<&Value>, counts: &mut DiffCounts) -> (Value, bool) {
+    let frame = right.or(left).cloned().unwrap_or_default();
+    let (status, changes) = match (left, right) {
+        (Some(l), Some(r)) => {
+            let changes = field_changes(l, r, &FRAME_FIELDS);
+            (if changes.is_empty() { DiffStatus::Same } else { DiffStatus::Changed }, changes)
+        }
+        (Some(_), None) => (DiffStatus::Removed, Vec::new()),
+        _ => (DiffStatus::Added, Vec::new()),
+    };
+    counts.add(status);
+
+    let left_calls = left.map(|l| items(l, "calls")).unwrap_or_default();
+    let right_calls = right.map(|r| items(r, "calls")).unwrap_or_default();
+    let pairs = align(
+        left_calls,
+        right_calls,
+        |call| (call["type"].clone(), call["function"].clone()),
+        |l, r| l["type"] == r["type"] && l["to"] == r["to"],
+    );
+    let mut differs = status != DiffStatus::Same;
+    let calls: Vec<Value> = pairs.into_iter().map(|(l, r)| {
+        let (call, child_differs) = diff_frame(l.map(|i| &left_calls[i]), r.map(|i| &right_calls[i]), counts);
+        differs |= child_differs;
+        call
+    }).collect();
+
+    let node = json!({
+        "status": status,
+        "leftPath": left.map(|l| l["path"].clone()),
+        "rightPath": right.map(|r| r["path"].clone()),
+        "type": frame["type"],
+        "to": frame["to"],
+        "contract": frame["contract"],
+        "function": frame["function"],
+        "changes": changes,
+        "hasChanges": differs,
+        "calls": calls,
+    });
+    (node, differs)
+}
+
+fn diff_events(left: &[Value], right: &[Value], counts: &mut DiffCounts) -> Vec<Value> {
+    let pairs = align(
+        left,
+        right,
+        |event| (event["address"].clone(), event["topics"][0].clone()),
+        |l, r| l["address"] == r["address"],
+    );
+    pairs.into_iter().map(|(l, r)| {
+        let (l, r) = (l.map(|i| &left[i]), r.map(|i| &right[i]));
+        let (status, changes) = match (l, r) {
+            (Some(l), Some(r)) => {
+                let changes = field_changes(l, r, &["name", "topics", "data"]);
+                (if changes.is_empty() { DiffStatus::Same } else { DiffStatus::Changed }, changes)
+            }
+            (Some(_), None) => (DiffStatus::Removed, Vec::new()),
+            _ => (DiffStatus::Added, Vec::new()),
+        };
+        counts.add(status);
+        let event = r.or(l).cloned().unwrap_or_default();
+        json!({
+            "status": status,
+            "leftIndex": l.map(|e| e["index"].clone()),
+            "rightIndex": r.map(|e| e["index"].clone()),
+            "address": event["address"],
+            "contract": event["contract"],
+            "name": event["name"],
+            "changes": changes,
+        })
+    }).collect()
+}
+
+/// Final account values of a `stateDiff` entry, keyed by field
+fn account_values(account: &Value) -> BTreeMap<String, (Value, Value)> {
+    let mut values = BTreeMap::new();
+    values.insert("balance".to_string(), (account["balance"]["after"].clone(), Value::Null));
+    values.insert("nonce".to_string(), (account["nonce"]["after"].clone(), Value::Null));
+    values.insert("codeChanged".to_string(), (account["codeChanged"].clone(), Value::Null));
+    for slot in account["storage"].as_array().into_iter().flatten() {
+        let variables: Vec<&str> = slot["decoded"].as_array().into_iter().flatten()
+            .filter_map(|v| v["variable"].as_str())
+            .collect();
+        let variable = if variables.is_empty() { Value::Null } else { json!(variables.join(", ")) };
+        values.insert(
+            format!("storage {}", slot["slot"].as_str().unwrap_or_default()),
+            (slot["after"].clone(), variable),
+        );
+    }
+    values
+}
+
+fn diff_state(left: &[Value], right: &[Value], counts: &mut DiffCounts) -> Vec<Value> {
+    let by_address = |accounts: &[Value]| -> BTreeMap<String, Value> {
+        accounts.iter()
+            .filter_map(|a| Some((a["address"].as_str()?.to_string(), a.clone())))
+            .collect()
+    };
+    let (left, right) = (by_address(left), by_address(right));
+    let addresses: BTreeSet<&String> = left.keys().chain(right.keys()).collect();
+
+    addresses.into_iter().filter_map(|address| {
+        let (l, r) = (left.get(address), right.get(address));
+        let left_values = l.map(account_values).unwrap_or_default();
+        let right_values = r.map(account_values).unwrap_or_default();
+        let fields: BTreeSet<&String> = left_values.keys().chain(right_values.keys()).collect();
+
+        let changes: Vec<Value> = fields.into_iter().filter_map(|field| {
+            let (left_value, left_variable) = left_values.get(field).cloned().unwrap_or_default();
+            let (right_value, right_variable) = right_values.get(field).cloned().unwrap_or_default();
+            (left_value != right_value).then(|| {
+                let mut change = Map::new();
+                change.insert("field".to_string(), json!(field));
+                if let Some(variable) = [right_variable, left_variable].into_iter().find(|v| !v.is_null()) {
+                    change.insert("variable".to_string(), variable);
+                }
+                change.insert("left".to_string(), left_value);
+                change.insert("right".to_string(), right_value);
+                Value::Object(change)
+            })
+        }).collect();
+
+        let status = match (l, r) {
+            (Some(_), None) => DiffStatus::Removed,
+            (None, Some(_)) => DiffStatus::Added,
+            _ if changes.is_empty() => DiffStatus::Same,
+            _ => DiffStatus::Changed,
+        };
+        counts.add(status);
+        (status != DiffStatus::Same).then(|| json!({
+            "status": status,
+            "address": address,
+            "contract": r.or(l).map(|a| a["contract"].clone()),
+            "changes": changes,
+        }))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(items: &[&str]) -> Vec<Value> {
+        items.iter().map(|i| json!(i)).collect()
+    }
+
+    /// Align string items by value; items sharing a first letter are similar
+    fn aligned(left: &[&str], right: &[&str]) -> Vec<(Option<usize>, Option<usize>)> {
+        align(
+            &values(left),
+            &values(right),
+            |v| v.as_str().unwrap_or_default().to_string(),
+            |a, b| a.as_str().and_then(|a| a.chars().next()) == b.as_str().and_then(|b| b.chars().next()),
+        )
+    }
+
+    #[test]
+    fn pairs_identical_lists() {
+        assert_eq!(aligned(&["a", "b", "c"], &["a", "b", "c"]), vec![
+            (Some(0), Some(0)),
+            (Some(1), Some(1)),
+            (Some(2), Some(2)),
+        ]);
+        assert!(aligned(&[], &[]).is_empty());
+        assert_eq!(aligned(&[], &["a"]), vec![(None, Some(0))]);
+        assert_eq!(aligned(&["a"], &[]), vec![(Some(0), None)]);
+    }
+
+    #[test]
+    fn marks_insertions_and_removals() {
+        assert_eq!(aligned(&["a", "b", "c"], &["a", "x", "b", "c"]), vec![
+            (Some(0), Some(0)),
+            (None, Some(1)),
+            (Some(1), Some(2)),
+            (Some(2), Some(3)),
+        ]);
+        assert_eq!(aligned(&["a", "x", "y", "c"], &["a", "c"]), vec![
+            (Some(0), Some(0)),
+            (Some(1), None),
+            (Some(2), None),
+            (Some(3), Some(1)),
+        ]);
+    }
+
+    #[test]
+    fn pairs_similar_items_between_matches() {
+        assert_eq!(aligned(&["a", "f1", "c"], &["a", "f2", "c"]), vec![
+            (Some(0), Some(0)),
+            (Some(1), Some(1)),
+            (Some(2), Some(2)),
+        ]);
+        assert_eq!(aligned(&["a", "f", "c"], &["a", "g", "c"]), vec![
+            (Some(0), Some(0)),
+            (Some(1), None),
+            (None, Some(1)),
+            (Some(2), Some(2)),
+        ]);
+    }
+
+    #[test]
+    fn keeps_order_when_items_move() {
+        assert_eq!(aligned(&["a", "b"], &["b", "a"]), vec![
+            (Some(0), None),
+            (Some(1), Some(0)),
+            (None, Some(1)),
+        ]);
+    }
+
+    #[test]
+    fn matches_common_prefix_and_suffix_around_lcs() {
+        assert_eq!(lcs(&["x", "b", "y"], &["b"]), vec![(1, 0)]);
+        assert_eq!(aligned(&["p", "x", "b", "y", "s"], &["p", "b", "s"]), vec![
+            (Some(0), Some(0)),
+            (Some(1), None),
+            (Some(2), Some(1)),
+            (Some(3), None),
+            (Some(4), Some(2)),
+        ]);
+    }
+
+    #[test]
+    fn pairs_large_middles_by_position() {
+        let size = 2001;
+        assert!((size + 1) * (size + 1) > MAX_LCS_CELLS);
+        let left: Vec<String> = std::iter::once("p".to_string())
+            .chain((0..size).map(|i| format!("l{}", i)))
+            .chain(std::iter::once("s".to_string()))
+            .collect();
+        let right: Vec<String> = std::iter::once("p".to_string())
+            .chain((0..size).map(|i| format!("r{}", i)))
+            .chain(std::iter::once("s".to_string()))
+            .collect();
+        let left: Vec<&str> = left.iter().map(|s| s.as_str()).collect();
+        let right: Vec<&str> = right.iter().map(|s| s.as_str()).collect();
+
+        let pairs = aligned(&left, &right);
+        assert_eq!(pairs.len(), size + 2);
+        assert!(pairs.iter().enumerate().all(|(k, pair)| *pair == (Some(k), Some(k))));
+    }
+}
@@ -1,14 +1,23 @@
 use serde_json::{json, Value};
-use ethers::types::{Transaction, TransactionReceipt};
+use ethers::types::{Address, Transaction, TransactionReceipt, H256, U256};
+use super::contract_registry::ContractRegistry;
+use super::units::UnitFormatter;
+use super::block_context::BlockContext;
 
 /// Format trace data in a Tenderly-style readable format
 pub fn format_tenderly_style(
     tx: &Transaction,
     receipt: &TransactionReceipt,
     cast_output: Option<&str>,
+    registry: &ContractRegistry,
+    block: Option<&BlockContext>,
+    units: &UnitFormatter,
 ) -> Value {
-    // Parse function signature from input data
-    let function_sig = if tx.input.len() >= 4 {
+    // Parse function signature from input data, using the ABI when the target is known
+    let decoded_input = tx.to.and_then(|to| registry.decode_call(&to, &tx.input));
+    let function_sig = if let Some(call) = &decoded_input {
+        call.signature.clone()
+    } else if tx.input.len() >= 4 {
         format!("0x{}", hex::encode(&tx.input[..4]))
     } else {
         "0x".to_string()
@@ -20,25 +29,38 @@ pub fn format_tenderly_style(
             "status": if receipt.status == Some(1.into()) { "✓ Success" } else { "✗ Failed" },
             "transactionHash": format!("{:?}", tx.hash),
             "block": receipt.block_number,
-            "timestamp": receipt.block_number, // You can fetch actual timestamp if needed
+            "timestamp": block.map(|b| b.timestamp.low_u64()),
+            "dateTime": block.and_then(|b| b.date_time()).map(|t| t.to_rfc3339()),
+            "transactionIndex": receipt.transaction_index.as_u64(),
         },
         "transactionInfo": {
             "from": format!("{:?}", tx.from),
             "to": tx.to.map(|addr| format!("{:?}", addr)),
-            "value": format_ether(&tx.value),
+            "contract": tx.to.and_then(|to| registry.name(&to)),
+            "value": units.native(tx.value),
+            "valueWei": tx.value.to_string(),
             "function": function_sig,
+            "decodedInput": decoded_input,
+            "input": format!("0x{}", hex::encode(&tx.input)),
             "nonce": format!("{}", tx.nonce),
         },
         "gasDetails": {
             "gasLimit": format!("{}", tx.gas),
             "gasUsed": format!("{}", receipt.gas_used.unwrap_or_default()),
-            "gasPrice": tx.gas_price.map(|g| format_gwei(&g)),
-            "effectiveGasPrice": receipt.effective_gas_price.map(|g| format_gwei(&g)),
-            "totalCost": calculate_gas_cost(receipt),
+            "gasPrice": tx.gas_price.map(|g| units.gwei(g)),
+            "gasPriceWei": tx.gas_price.map(|g| g.to_string()),
+            "effectiveGasPrice": receipt.effective_gas_price.map(|g| units.gwei(g)),
+            "effectiveGasPriceWei": receipt.effective_gas_price.map(|g| g.to_string()),
+            "totalCost": gas_cost(receipt).map(|cost| units.native(cost)).unwrap_or_else(|| "Unknown".to_string()),
+            "totalCostWei": gas_cost(receipt).map(|cost| cost.to_string()),
         },
-        "events": format_events(&receipt.logs),
+        "events": format_events(&receipt.logs, registry),
     });
 
+    if let (Some(block), Some(obj)) = (block, trace.as_object_mut()) {
+        obj.insert("blockContext".to_string(), block.to_json(tx, receipt, units));
+    }
+
     // Add state changes section
     if let Some(output) = cast_output {
         if let Some(obj) = trace.as_object_mut() {
@@ -49,49 +71,35 @@ pub fn format_tenderly_style(
     trace
 }
 
-/// Format Wei value to ETH with proper decimals
-fn format_ether(wei: &ethers::types::U256) -> String {
-    let eth_value = wei.as_u128() as f64 / 1e18;
-    if eth_value == 0.0 {
-        "0 ETH".to_string()
-    } else if eth_value < 0.000001 {
-        format!("{} wei", wei)
-    } else {
-        format!("{:.6} ETH", eth_value)
-    }
-}
-
-/// Format Wei to Gwei
-fn format_gwei(wei: &ethers::types::U256) -> String {
-    let gwei_value = wei.as_u128() as f64 / 1e9;
-    format!("{:.2} Gwei", gwei_value)
-}
-
-/// Calculate total gas cost in ETH
-fn calculate_gas_cost(receipt: &TransactionReceipt) -> String {
-    if let (Some(gas_used), Some(gas_price)) = (receipt.gas_used, receipt.effective_gas_price) {
-        let cost = gas_used.saturating_mul(gas_price);
-        format_ether(&cost)
-    } else {
-        "Unknown".to_string()
-    }
+/// Total fee paid for gas, in wei
+fn gas_cost(receipt: &TransactionReceipt) -> Option<U256> {
+    Some(receipt.gas_used?.saturating_mul(receipt.effective_gas_price?))
 }
 
 /// Format logs/events in a readable way
-fn format_events(logs: &[ethers::types::Log]) -> Vec<Value> {
+fn format_events(logs: &[ethers::types::Log], registry: &ContractRegistry) -> Vec<Value> {
     logs.iter().enumerate().map(|(idx, log)| {
-        json!({
-            "index": idx,
-            "address": format!("{:?}", log.address),
-            "name": decode_event_name(&log.topics),
-            "topics": log.topics.iter().map(|t| format!("{:?}", t)).collect::<Vec<_>>(),
-            "data": format!("0x{}", hex::encode(&log.data)),
-        })
+        let mut event = format_event(&log.address, &log.topics, &log.data, registry);
+        event["index"] = json!(idx);
+        event
     }).collect()
 }
 
+/// A single log with its name and decoded parameters when the emitter's ABI is known
+pub fn format_event(address: &Address, topics: &[H256], data: &[u8], registry: &ContractRegistry) -> Value {
+    let decoded = registry.decode_log(address, topics, data);
+    json!({
+        "address": format!("{:?}", address),
+        "contract": registry.name(address),
+        "name": decoded.as_ref().map(|d| d.name.clone()).unwrap_or_else(|| decode_event_name(topics)),
+        "topics": topics.iter().map(|t| format!("{:?}", t)).collect::<Vec<_>>(),
+        "data": format!("0x{}", hex::encode(data)),
+        "decoded": decoded,
+    })
+}
+
 /// Try to decode event name from topic[0]
-fn decode_event_name(topics: &[ethers::types::H256]) -> String {
+fn decode_event_name(topics: &[H256]) -> String {
     if topics.is_empty() {
         return "Unknown Event".to_string();
     }
@@ -180,15 +188,86 @@ pub fn format_trace_for_display(trace: &Value) -> String {
         output.push_str(&format!("   TX Hash: {}\n", overview["transactionHash"].as_str().unwrap_or("Unknown")));
         output.push_str(&format!("   Block: {}\n\n", overview["block"]));
     }
+
+    if let Some(block) = trace.get("blockContext") {
+        output.push_str("🧱 BLOCK\n");
+        if let Some(date_time) = block["dateTime"].as_str() {
+            output.push_str(&format!("   Timestamp: {} ({})\n", date_time, block["age"].as_str().unwrap_or("?")));
+        }
+        if let Some(miner) = block["miner"].as_str() {
+            output.push_str(&format!("   Fee Recipient: {}{}\n", miner, label_suffix(&block["minerLabel"])));
+        }
+        output.push_str(&format!("   Position: {} of {} transactions\n",
+            block["transactionIndex"].as_u64().map(|i| (i + 1).to_string()).unwrap_or_else(|| "?".to_string()),
+            block["transactionCount"]
+        ));
+        output.push_str(&format!("   Gas Used: {} / {}{}\n",
+            block["gasUsed"].as_str().unwrap_or("?"),
+            block["gasLimit"].as_str().unwrap_or("?"),
+            block["utilization"].as_f64().map(|u| format!(" ({:.2}%)", u)).unwrap_or_default()
+        ));
+        if let Some(base_fee) = block["baseFee"].as_str() {
+            output.push_str(&format!("   Base Fee: {}\n", base_fee));
+        }
+        if let Some(priority_fee) = block["priorityFee"].as_str() {
+            output.push_str(&format!("   Priority Fee: {}{}\n",
+                priority_fee,
+                block["maxPriorityFee"].as_str().map(|max| format!(" (max {})", max)).unwrap_or_default()
+            ));
+        }
+        output.push('\n');
+    }
     
+    if let Some(cause) = trace.get("revertCause") {
+        let caught = cause["caught"].as_array().map(|c| c.len()).unwrap_or(0);
+        if cause["reverted"].as_bool().unwrap_or(false) || caught > 0 {
+            output.push_str("❌ REVERT\n");
+            for frame in cause["path"].as_array().into_iter().flatten() {
+                let depth = frame["path"].as_array().map(|p| p.len()).unwrap_or(0);
+                output.push_str(&format!("   {}{} {}.{}{}\n",
+                    "  ".repeat(depth),
+                    if frame["status"] == "origin" { "✗" } else { "↑" },
+                    frame["contract"].as_str().or_else(|| frame["to"].as_str()).unwrap_or("?"),
+                    frame["function"].as_str().unwrap_or("?"),
+                    frame["reason"].as_str().or_else(|| frame["error"].as_str()).map(|r| format!(": {}", r)).unwrap_or_default()
+                ));
+            }
+            if caught > 0 {
+                output.push_str(&format!("   {} caught revert(s) did not affect the outcome\n", caught));
+            }
+            output.push('\n');
+        }
+    }
+
+    if let Some(warnings) = trace.get("warnings").and_then(|w| w.as_array()) {
+        if !warnings.is_empty() {
+            output.push_str(&format!("⚠️  WARNINGS ({})\n", warnings.len()));
+            for warning in warnings {
+                let frame = match warning["logIndex"].as_u64() {
+                    Some(index) if warning["path"].is_null() => format!(" [log {}]", index),
+                    _ => frame_suffix(&warning["path"]),
+                };
+                output.push_str(&format!("   [{}] {}{}{}\n",
+                    warning["severity"].as_str().unwrap_or("?").to_uppercase(),
+                    warning["message"].as_str().unwrap_or(""),
+                    frame,
+                    if warning["reverted"].as_bool().unwrap_or(false) { " (reverted)" } else { "" }
+                ));
+            }
+            output.push('\n');
+        }
+    }
+
     if let Some(tx_info) = trace.get("transactionInfo") {
         output.push_str("💼 TRANSACTION INFO\n");
-        output.push_str(&format!("   From: {}\n", tx_info["from"].as_str().unwrap_or("Unknown")));
+        output.push_str(&format!("   From: {}{}\n", tx_info["from"].as_str().unwrap_or("Unknown"), label_suffix(&tx_info["fromLabel"])));
         if let Some(to) = tx_info["to"].as_str() {
-            output.push_str(&format!("   To: {}\n", to));
+            output.push_str(&format!("   To: {}{}\n", to, label_suffix(&tx_info["toLabel"])));
         }
         output.push_str(&format!("   Value: {}\n", tx_info["value"].as_str().unwrap_or("0")));
-        output.push_str(&format!("   Function: {}\n\n", tx_info["function"].as_str().unwrap_or("Unknown")));
+        output.push_str(&format!("   Function: {}\n", tx_info["function"].as_str().unwrap_or("Unknown")));
+        format_virtual_calls(&tx_info["decodedInput"], 2, &mut output);
+        output.push('\n');
     }
     
     if let Some(gas) = trace.get("gasDetails") {
@@ -205,9 +284,11 @@ pub fn format_trace_for_display(trace: &Value) -> String {
         if !events.is_empty() {
             output.push_str(&format!("📢 EVENTS ({})\n", events.len()));
             for event in events.iter().take(5) {
-                output.push_str(&format!("   • {} at {}\n", 
+                output.push_str(&format!("   • {} at {}{}{}\n", 
                     event["name"].as_str().unwrap_or("Unknown"),
-                    event["address"].as_str().unwrap_or("Unknown")
+                    event["address"].as_str().unwrap_or("Unknown"),
+                    label_suffix(&event["addressLabel"]),
+                    frame_suffix(&event["frame"])
                 ));
             }
             if events.len() > 5 {
@@ -216,7 +297,214 @@ pub fn format_trace_for_display(trace: &Value) -> String {
             output.push_str("\n");
         }
     }
+
+    if let Some(events) = trace.get("revertedEvents").and_then(|e| e.as_array()) {
+        if !events.is_empty() {
+            output.push_str(&format!("↩️  REVERTED EVENTS ({})\n", events.len()));
+            for event in events {
+                output.push_str(&format!("   • {} at {}{}{}\n",
+                    event["name"].as_str().unwrap_or("Unknown"),
+                    event["address"].as_str().unwrap_or("Unknown"),
+                    label_suffix(&event["addressLabel"]),
+                    frame_suffix(&event["frame"])
+                ));
+            }
+            output.push('\n');
+        }
+    }
     
+    if let Some(transfers) = trace.get("tokenTransfers").and_then(|t| t.as_array()) {
+        if !transfers.is_empty() {
+            output.push_str(&format!("🪙 TOKEN TRANSFERS ({})\n", transfers.len()));
+            for transfer in transfers.iter().take(10) {
+                let amount = match transfer["tokenId"].as_str() {
+                    Some(id) if transfer["standard"] == "ERC-721" => format!("#{}", id),
+                    Some(id) => format!("{} of #{}", transfer["formattedAmount"].as_str().unwrap_or("?"), id),
+                    None => transfer["formattedAmount"].as_str().unwrap_or("?").to_string(),
+                };
+                let token = transfer["symbol"].as_str()
+                    .or_else(|| transfer["token"].as_str())
+                    .unwrap_or("Unknown");
+                output.push_str(&format!("   • {} {} → {}: {}{}\n",
+                    token,
+                    transfer["from"].as_str().unwrap_or("Unknown"),
+                    transfer["to"].as_str().unwrap_or("Unknown"),
+                    amount,
+                    if transfer["standard"] == "ERC-20" { String::new() } else { format!(" ({})", transfer["standard"].as_str().unwrap_or("")) }
+                ));
+            }
+            if transfers.len() > 10 {
+                output.push_str(&format!("   ... and {} more\n", transfers.len() - 10));
+            }
+            output.push('\n');
+        }
+    }
+
+    if let Some(transfers) = trace.get("internalTransfers").and_then(|t| t.as_array()) {
+        if !transfers.is_empty() {
+            output.push_str(&format!("💸 INTERNAL TRANSFERS ({})\n", transfers.len()));
+            for transfer in transfers.iter().take(10) {
+                output.push_str(&format!("   • {} {}{} → {}{}: {}{}{}\n",
+                    transfer["type"].as_str().unwrap_or("CALL"),
+                    transfer["from"].as_str().unwrap_or("Unknown"),
+                    label_suffix(&transfer["fromLabel"]),
+                    transfer["to"].as_str().unwrap_or("Unknown"),
+                    label_suffix(&transfer["toLabel"]),
+                    transfer["value"].as_str().unwrap_or("?"),
+                    frame_suffix(&transfer["path"]),
+                    if transfer["reverted"].as_bool().unwrap_or(false) { " (reverted)" } else { "" }
+                ));
+            }
+            if transfers.len() > 10 {
+                output.push_str(&format!("   ... and {} more\n", transfers.len() - 10));
+            }
+            output.push('\n');
+        }
+    }
+
+    if let Some(deployments) = trace.get("deployments").and_then(|d| d.as_array()) {
+        if !deployments.is_empty() {
+            output.push_str(&format!("🏗️  DEPLOYMENTS ({})\n", deployments.len()));
+            for deployment in deployments {
+                let status = if !deployment["success"].as_bool().unwrap_or(false) {
+                    format!(" ✗ failed: {}", deployment["error"].as_str().unwrap_or("unknown error"))
+                } else if deployment["reverted"].as_bool().unwrap_or(false) {
+                    " (reverted)".to_string()
+                } else {
+                    String::new()
+                };
+                output.push_str(&format!("   • {} {}{} by {}{}, {} bytes{}{}\n",
+                    deployment["type"].as_str().unwrap_or("CREATE"),
+                    deployment["address"].as_str().unwrap_or("?"),
+                    label_suffix(&deployment["addressLabel"]),
+                    deployment["deployer"].as_str().unwrap_or("?"),
+                    frame_suffix(&deployment["path"]),
+                    deployment["runtimeCodeSize"],
+                    if deployment["addressVerified"] == false { " ⚠️ address mismatch" } else { "" },
+                    status
+                ));
+                if let Some(salt) = deployment["salt"].as_str() {
+                    output.push_str(&format!("     salt {}\n", salt));
+                }
+            }
+            output.push('\n');
+        }
+    }
+
+    if let Some(balances) = trace.get("balanceChanges").and_then(|b| b.as_array()) {
+        if !balances.is_empty() {
+            output.push_str("💰 BALANCE CHANGES\n");
+            for entry in balances {
+                output.push_str(&format!("   {}{}\n",
+                    entry["address"].as_str().unwrap_or("Unknown"),
+                    label_suffix(&entry["addressLabel"])
+                ));
+                for change in entry["changes"].as_array().into_iter().flatten() {
+                    let asset = change["symbol"].as_str()
+                        .or_else(|| change["token"].as_str())
+                        .unwrap_or("");
+                    let amount = change["formattedAmount"].as_str().unwrap_or("?");
+                    match change["tokenId"].as_str() {
+                        Some(id) => output.push_str(&format!("      {} {} #{}\n", amount, asset, id)),
+                        None => output.push_str(&format!("      {}\n", amount)),
+                    }
+                }
+            }
+            output.push('\n');
+        }
+    }
+
+    if let Some(accounts) = trace.get("stateDiff").and_then(|s| s.as_array()) {
+        let changed: Vec<&Value> = accounts.iter().filter(|a| a.get("storage").is_some()).collect();
+        if !changed.is_empty() {
+            output.push_str("📦 STORAGE CHANGES\n");
+            for account in changed {
+                output.push_str(&format!("   {}{}\n",
+                    account["address"].as_str().unwrap_or("Unknown"),
+                    label_suffix(&account["addressLabel"])
+                ));
+                for slot in account["storage"].as_array().into_iter().flatten() {
+                    match slot["decoded"].as_array().filter(|d| !d.is_empty()) {
+                        Some(variables) => {
+                            for variable in variables {
+                                output.push_str(&format!("      {}: {} → {}\n",
+                                    variable["variable"].as_str().unwrap_or("?"),
+                                    variable["before"].as_str().unwrap_or("?"),
+                                    variable["after"].as_str().unwrap_or("?")
+                                ));
+                            }
+                        }
+                        None => output.push_str(&format!("      {}: {} → {}\n",
+                            slot["slot"].as_str().unwrap_or("?"),
+                            slot["before"].as_str().unwrap_or("?"),
+                            slot["after"].as_str().unwrap_or("?")
+                        )),
+                    }
+                }
+            }
+            output.push('\n');
+        }
+    }
+
+    if let Some(profile) = trace.get("gasProfile") {
+        output.push_str(&format!("🔥 GAS PROFILE ({} gas)\n", profile["totalGas"]));
+        output.push_str("   Hotspots (own gas):\n");
+        for frame in profile["hotspots"].as_array().into_iter().flatten().take(5) {
+            output.push_str(&format!("      {:>10}  {}{}\n",
+                frame["exclusive"],
+                frame["contract"].as_str().or_else(|| frame["address"].as_str()).unwrap_or("unknown"),
+                frame["function"].as_str().map(|f| format!(".{}", f)).unwrap_or_default()
+            ));
+        }
+        output.push_str("   By contract (incl. calls):\n");
+        for contract in profile["byContract"].as_array().into_iter().flatten().take(5) {
+            output.push_str(&format!("      {:>10}  {} ({} calls)\n",
+                contract["inclusive"],
+                contract["key"].as_str().unwrap_or("unknown"),
+                contract["calls"]
+            ));
+        }
+        output.push('\n');
+    }
+
+    if let Some(opcode_gas) = trace.get("opcodeGas") {
+        output.push_str("📊 OPCODE GAS\n");
+        for opcode in opcode_gas["opcodes"].as_array().into_iter().flatten().take(8) {
+            output.push_str(&format!("   {:<14} {:>10} gas  ({}×)\n",
+                opcode["op"].as_str().unwrap_or("?"),
+                opcode["gas"],
+                opcode["count"]
+            ));
+        }
+        output.push_str(&format!("   Memory expansion: {} gas\n", opcode_gas["memoryExpansion"]["gas"]));
+        let storage = &opcode_gas["storage"];
+        for (name, key) in [("SLOAD", "sload"), ("SSTORE", "sstore"), ("Account access", "accountAccess")] {
+            output.push_str(&format!("   {}: {} cold ({} gas), {} warm ({} gas)\n",
+                name,
+                storage[key]["cold"]["count"],
+                storage[key]["cold"]["gas"],
+                storage[key]["warm"]["count"],
+                storage[key]["warm"]["gas"]
+            ));
+        }
+        if let Some(total) = opcode_gas["refunds"]["total"].as_u64().filter(|t| *t > 0) {
+            output.push_str(&format!("   Refund: {} gas ({} applied)\n", total, opcode_gas["refunds"]["applied"]));
+        }
+        output.push('\n');
+    }
+
+    if let Some(access_list) = trace.get("accessList") {
+        output.push_str("🔑 ACCESS LIST SUGGESTION\n");
+        output.push_str(&format!("   {} addresses, {} storage keys\n", access_list["addresses"], access_list["storageKeys"]));
+        let savings = access_list["savings"].as_i64().unwrap_or(0);
+        output.push_str(&format!("   Gas: {} without, {} with ({})\n",
+            access_list["gasWithout"],
+            access_list["gasWith"],
+            if savings > 0 { format!("saves {}", savings) } else { format!("costs {} more", -savings) }
+        ));
+        output.push('\n');
+    }
+
     if let Some(call_trace) = trace.get("callTrace") {
         if let Some(calls) = call_trace.get("calls").and_then(|c| c.as_array()) {
             output.push_str("🔍 CALL TRACE\n");
@@ -231,3 +519,32 @@ pub fn format_trace_for_display(trace: &Value) -> String {
     output.push_str("\n");
     output
 }
+
+/// " (label)" when an address label is present
+fn label_suffix(label: &Value) -> String {
+    label.as_str().map(|l| format!(" ({})", l)).unwrap_or_default()
+}
+
+/// " [frame 1.0]" when a call tree path is present
+fn frame_suffix(path: &Value) -> String {
+    path.as_array()
+        .map(|path| format!(" [frame {}]", path.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(".")))
+        .unwrap_or_default()
+}
+
+/// Inner calls unpacked from batching functions, indented by nesting level
+fn format_virtual_calls(decoded: &Value, level: usize, output: &mut String) {
+    for call in decoded["virtualCalls"].as_array().into_iter().flatten() {
+        let name = call["decoded"]["signature"].as_str()
+            .or_else(|| call["label"].as_str())
+            .unwrap_or_else(|| call["data"].as_str().map(|d| &d[..d.len().min(10)]).unwrap_or("?"));
+        let target = call["target"].as_str().map(|t| format!(" on {}", t)).unwrap_or_default();
+        output.push_str(&format!("{}↳ {}{}\n", "   ".repeat(level), name, target));
+        format_virtual_calls(&call["decoded"], level + 1, output);
+    }
+}
+
+/// Array stored under `key` in a trace section, empty when missing
+pub fn items<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value.get(key).and_then(|v| v.as_array()).map(|v| v.as_slice()).unwrap_or_default()
+}
@@ -0,0 +1,140 @@
+//! Exact decimal formatting of on-chain amounts
+//! Everything stays in `U256`, so amounts above `u128` neither panic nor lose precision
+
+use ethers::types::U256;
+use crate::types::NativeCurrency;
+
+const GWEI_DECIMALS: u8 = 9;
+
+/// Exact decimal representation of `amount / 10^decimals` without trailing zeros
+pub fn format_units(amount: U256, decimals: u8) -> String {
+    let digits = amount.to_string();
+    let decimals = decimals as usize;
+    if decimals == 0 {
+        return digits;
+    }
+    let padded = format!("{:0>width$}", digits, width = decimals + 1);
+    let (whole, fraction) = padded.split_at(padded.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+/// Like `format_units`, rounded half up to at most `precision` fraction digits
+/// Non-zero amounts that round to zero are shown as `<0.0…1` rather than `0`
+pub fn format_units_rounded(amount: U256, decimals: u8, precision: Option<usize>) -> String {
+    let precision = match precision {
+        Some(precision) if precision < decimals as usize => precision as u8,
+        _ => return format_units(amount, decimals),
+    };
+    // 10^(decimals - precision) may not fit in a U256, in which case everything rounds to zero
+    let rounded = match U256::from(10).checked_pow(U256::from(decimals - precision)) {
+        Some(divisor) => {
+            let (quotient, remainder) = amount.div_mod(divisor);
+            if remainder >= divisor - remainder { quotient + 1 } else { quotient }
+        }
+        None => U256::zero(),
+    };
+    if rounded.is_zero() && !amount.is_zero() {
+        return format!("<{}", format_units(U256::one(), precision));
+    }
+    format_units(rounded, precision)
+}
+
+/// Formats amounts in the native currency of a network with a shared display precision
+#[derive(Debug, Clone, Default)]
+pub struct UnitFormatter {
+    currency: NativeCurrency,
+    precision: Option<usize>,
+}
+
+impl UnitFormatter {
+    pub fn new(currency: &NativeCurrency, precision: Option<usize>) -> Self {
+        Self { currency: currency.clone(), precision }
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.currency.symbol
+    }
+
+    /// Amount with the given decimals, rounded to the display precision
+    pub fn amount(&self, amount: U256, decimals: u8) -> String {
+        format_units_rounded(amount, decimals, self.precision)
+    }
+
+    /// Native amount suffixed with the currency symbol, e.g. "1.5 POL"
+    pub fn native(&self, wei: U256) -> String {
+        format!("{} {}", self.amount(wei, self.currency.decimals), self.currency.symbol)
+    }
+
+    /// Gas price in Gwei
+    pub fn gwei(&self, wei: U256) -> String {
+        format!("{} Gwei", self.amount(wei, GWEI_DECIMALS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wei(amount: &str) -> U256 {
+        U256::from_dec_str(amount).unwrap()
+    }
+
+    #[test]
+    fn formats_exact_units() {
+        assert_eq!(format_units(wei("1500000000000000000"), 18), "1.5");
+        assert_eq!(format_units(wei("1000000000000000000"), 18), "1");
+        assert_eq!(format_units(U256::one(), 18), "0.000000000000000001");
+        assert_eq!(format_units(U256::zero(), 18), "0");
+        assert_eq!(format_units(wei("123456"), 6), "0.123456");
+        assert_eq!(format_units(wei("42"), 0), "42");
+    }
+
+    #[test]
+    fn formats_amounts_above_u128() {
+        assert_eq!(
+            format_units(U256::MAX, 18),
+            "115792089237316195423570985008687907853269984665640564039457.584007913129639935"
+        );
+        assert_eq!(format_units(U256::MAX, 77), format!("1.{}", &U256::MAX.to_string()[1..]));
+        assert_eq!(format_units(U256::one(), u8::MAX), format!("0.{}1", "0".repeat(254)));
+    }
+
+    #[test]
+    fn rounds_half_up() {
+        assert_eq!(format_units_rounded(wei("1234500000000000000"), 18, Some(4)), "1.2345");
+        assert_eq!(format_units_rounded(wei("1234550000000000000"), 18, Some(4)), "1.2346");
+        assert_eq!(format_units_rounded(wei("1234549999999999999"), 18, Some(4)), "1.2345");
+        assert_eq!(format_units_rounded(wei("1999990000000000000"), 18, Some(4)), "2");
+        assert_eq!(format_units_rounded(wei("1500000000000000000"), 18, Some(0)), "2");
+    }
+
+    #[test]
+    fn keeps_exact_value_within_precision() {
+        assert_eq!(format_units_rounded(wei("1234567"), 6, None), "1.234567");
+        assert_eq!(format_units_rounded(wei("1234567"), 6, Some(6)), "1.234567");
+        assert_eq!(format_units_rounded(wei("1234567"), 6, Some(10)), "1.234567");
+    }
+
+    #[test]
+    fn marks_dust_that_rounds_to_zero() {
+        assert_eq!(format_units_rounded(U256::one(), 18, Some(4)), "<0.0001");
+        assert_eq!(format_units_rounded(wei("49999999999999"), 18, Some(4)), "<0.0001");
+        assert_eq!(format_units_rounded(wei("50000000000000"), 18, Some(4)), "0.0001");
+        assert_eq!(format_units_rounded(U256::zero(), 18, Some(4)), "0");
+        // 10^250 does not fit in a U256
+        assert_eq!(format_units_rounded(U256::MAX, 255, Some(5)), "<0.00001");
+    }
+
+    #[test]
+    fn formats_native_and_gwei_amounts() {
+        let units = UnitFormatter::new(&NativeCurrency::new("Polygon Ecosystem Token", "POL", 18), Some(3));
+        assert_eq!(units.native(wei("1500000000000000000")), "1.5 POL");
+        assert_eq!(units.gwei(wei("30123456789")), "30.123 Gwei");
+        assert_eq!(units.symbol(), "POL");
+    }
+}
@@ -0,0 +1,359 @@
+use ethers::types::{Address, Bytes, Log, H256, U256};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use super::call_tree::CallNode;
+use super::contract_registry::ContractRegistry;
+use super::precompiles::is_precompile;
+use super::tokens::{is_unlimited_allowance, APPROVAL_FOR_ALL_TOPIC, APPROVAL_TOPIC};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    High,
+    Medium,
+    Low,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WarningKind {
+    Reentrancy,
+    ReadOnlyReentrancy,
+    UnknownDelegatecall,
+    Selfdestruct,
+    ValueToNewContract,
+    UnlimitedApprovalToEoa,
+}
+
+/// A security-relevant pattern found in a transaction
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub severity: Severity,
+    pub message: String,
+    /// Offending frame in the call tree
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<Vec<usize>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_index: Option<usize>,
+    /// The frame or one of its callers reverted, so its effects were undone
+    pub reverted: bool,
+}
+
+/// Addresses granted an unlimited ERC-20 allowance or an operator approval in the logs
+/// Their code is needed to tell EOAs apart from contracts
+pub fn approval_spenders(logs: &[Log]) -> BTreeSet<Address> {
+    logs.iter().filter_map(unlimited_approval).map(|(spender, _)| spender).collect()
+}
+
+/// Spender of an unlimited `Approval` or an `ApprovalForAll(.., true)`, with the event name
+fn unlimited_approval(log: &Log) -> Option<(Address, &'static str)> {
+    if log.topics.len() != 3 || log.data.len() != 32 {
+        return None;
+    }
+    let value = U256::from_big_endian(&log.data);
+    let spender = Address::from_slice(&log.topics[2].as_bytes()[12..]);
+    let topic = log.topics[0];
+    if topic == APPROVAL_TOPIC.parse::<H256>().ok()? && is_unlimited_allowance(value) {
+        Some((spender, "unlimited allowance"))
+    } else if topic == APPROVAL_FOR_ALL_TOPIC.parse::<H256>().ok()? && value == U256::one() {
+        Some((spender, "approval for all tokens"))
+    } else {
+        None
+    }
+}
+
+/// Flag reentrancy, unknown delegate calls, self-destructs, value sent to contracts created
+/// in the same transaction and unlimited approvals granted to EOAs
+/// `codes` holds the runtime code of every contract that may be involved, and `unknown_codes`
+/// the addresses whose code could not be fetched
+pub fn analyze(
+    call_tree: Option<&CallNode>,
+    logs: &[Log],
+    registry: &ContractRegistry,
+    codes: &HashMap<Address, Bytes>,
+    unknown_codes: &BTreeSet<Address>,
+) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let name = |address: &Address| match registry.name(address) {
+        Some(name) => format!("{} ({:?})", name, address),
+        None => format!("{:?}", address),
+    };
+
+    if let Some(tree) = call_tree {
+        let mut analyzer = TreeAnalyzer {
+            registry,
+            name: &name,
+            created: BTreeSet::new(),
+            warnings: &mut warnings,
+        };
+        if matches!(tree.call_type.as_str(), "CREATE" | "CREATE2") {
+            analyzer.created.extend(tree.to);
+        }
+        let context = tree.to.unwrap_or_default();
+        analyzer.visit(tree, &mut Vec::new(), &mut vec![context], false);
+    }
+
+    // Contracts created by this transaction have no code yet at the fork block
+    let mut created = BTreeSet::new();
+    if let Some(tree) = call_tree {
+        tree.walk(&mut |_, node| {
+            if matches!(node.call_type.as_str(), "CREATE" | "CREATE2") {
+                created.extend(node.to);
+            }
+        });
+    }
+    for (index, log) in logs.iter().enumerate() {
+        let Some((spender, approval)) = unlimited_approval(log) else { continue };
+        if codes.contains_key(&spender) || unknown_codes.contains(&spender) || created.contains(&spender) {
+            continue;
+        }
+        let owner = Address::from_slice(&log.topics[1].as_bytes()[12..]);
+        warnings.push(Warning {
+            kind: WarningKind::UnlimitedApprovalToEoa,
+            severity: Severity::High,
+            message: format!(
+                "{} granted an {} on {} to {}, which is not a contract",
+                name(&owner), approval, name(&log.address), name(&spender)
+            ),
+            path: None,
+            address: Some(format!("{:?}", spender)),
+            log_index: Some(index),
+            reverted: false,
+        });
+    }
+
+    warnings.sort_by_key(|w| w.severity);
+    warnings
+}
+
+struct TreeAnalyzer<'a, F: Fn(&Address) -> String> {
+    registry: &'a ContractRegistry,
+    name: &'a F,
+    /// Contracts created so far in the transaction
+    created: BTreeSet<Address>,
+    warnings: &'a mut Vec<Warning>,
+}
+
+impl<F: Fn(&Address) -> String> TreeAnalyzer<'_, F> {
+    /// `contexts` holds the address whose storage each active frame runs against,
+    /// the current frame's last
+    fn visit(&mut self, node: &CallNode, path: &mut Vec<usize>, contexts: &mut Vec<Address>, reverted: bool) {
+        let reverted = reverted || !node.is_success();
+        for (idx, child) in node.calls.iter().enumerate() {
+            path.push(idx);
+            let context = match child.call_type.as_str() {
+                "DELEGATECALL" | "CALLCODE" => caller_context(contexts),
+                _ => child.to.unwrap_or_default(),
+            };
+            self.check(child, path, contexts, reverted);
+            contexts.push(context);
+            self.visit(child, path, contexts, reverted);
+            contexts.pop();
+            path.pop();
+        }
+    }
+
+    fn check(&mut self, node: &CallNode, path: &[usize], contexts: &[Address], parent_reverted: bool) {
+        let reverted = parent_reverted || !node.is_success();
+        let caller = caller_context(contexts);
+        let mut warn = |kind, severity, message: String, address: Option<Address>| {
+            self.warnings.push(Warning {
+                kind,
+                severity,
+                message,
+                path: Some(path.to_vec()),
+                address: address.map(|a| format!("{:?}", a)),
+                log_index: None,
+                reverted,
+            });
+        };
+        let name = self.name;
+
+        match node.call_type.as_str() {
+            "CALL" | "STATICCALL" => {
+                let Some(to) = node.to else { return };
+                // A contract calling itself directly (e.g. `this.multicall`) is not a re-entry
+                let entered_from = contexts.iter().rposition(|c| *c == to);
+                if entered_from.is_some_and(|i| i + 1 < contexts.len()) {
+                    let (kind, severity, verb) = if node.call_type == "STATICCALL" {
+                        (WarningKind::ReadOnlyReentrancy, Severity::Low, "read from")
+                    } else {
+                        (WarningKind::Reentrancy, Severity::Medium, "re-entered")
+                    };
+                    warn(kind, severity, format!(
+                        "{} was {} by {} while it was already on the call stack",
+                        name(&to), verb, name(&caller)
+                    ), Some(to));
+                }
+            }
+            "DELEGATECALL" | "CALLCODE" => {
+                let Some(to) = node.to else { return };
+                let is_implementation = self.registry.proxy(&caller)
+                    .map(|proxy| proxy.implementations().contains(&to))
+                    .unwrap_or(false);
+                if !is_implementation && !self.registry.contains(&to) && !is_precompile(&to) {
+                    warn(WarningKind::UnknownDelegatecall, Severity::Medium, format!(
+                        "{} ran the code of {} in its own context; it is neither a known proxy implementation nor a verified contract",
+                        name(&caller), name(&to)
+                    ), Some(to));
+                }
+            }
+            "SELFDESTRUCT" => {
+                warn(WarningKind::Selfdestruct, Severity::Medium, format!(
+                    "{} self-destructed, sending its balance to {}",
+                    name(&node.from), node.to.map(|to| name(&to)).unwrap_or_default()
+                ), Some(node.from));
+            }
+            _ => {}
+        }
+
+        let value = node.value.unwrap_or_default();
+        if let Some(to) = node.to.filter(|to| !value.is_zero() && self.created.contains(to)) {
+            if node.transfers_value() {
+                warn(WarningKind::ValueToNewContract, Severity::Low, format!(
+                    "{} wei sent to {}, a contract created earlier in this transaction",
+                    value, name(&to)
+                ), Some(to));
+            }
+        }
+        if matches!(node.call_type.as_str(), "CREATE" | "CREATE2") && node.is_success() {
+            self.created.extend(node.to);
+        }
+    }
+}
+
+/// Storage context of the frame at the top of the stack
+fn caller_context(contexts: &[Address]) -> Address {
+    contexts.last().copied().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::proxy::{ProxyInfo, ProxyKind};
+
+    const A: Address = Address::repeat_byte(0x0a);
+    const B: Address = Address::repeat_byte(0x0b);
+    const C: Address = Address::repeat_byte(0x0c);
+
+    fn call(call_type: &str, from: Address, to: Address, value: u64, calls: Vec<CallNode>) -> CallNode {
+        CallNode {
+            call_type: call_type.to_string(),
+            from,
+            to: Some(to),
+            value: Some(U256::from(value)),
+            calls,
+            ..Default::default()
+        }
+    }
+
+    fn approval(spender: Address, amount: U256) -> Log {
+        let mut data = [0u8; 32];
+        amount.to_big_endian(&mut data);
+        Log {
+            address: C,
+            topics: vec![APPROVAL_TOPIC.parse().unwrap(), H256::from(A), H256::from(spender)],
+            data: data.to_vec().into(),
+            ..Default::default()
+        }
+    }
+
+    fn tree_warnings(tree: &CallNode, registry: &ContractRegistry) -> Vec<(WarningKind, Vec<usize>, bool)> {
+        analyze(Some(tree), &[], registry, &HashMap::new(), &BTreeSet::new())
+            .into_iter()
+            .map(|w| (w.kind, w.path.unwrap_or_default(), w.reverted))
+            .collect()
+    }
+
+    fn approval_warnings(logs: &[Log], tree: Option<&CallNode>, codes: &HashMap<Address, Bytes>, unknown: &BTreeSet<Address>) -> Vec<Option<usize>> {
+        analyze(tree, logs, &ContractRegistry::new(), codes, unknown)
+            .into_iter()
+            .filter(|w| w.kind == WarningKind::UnlimitedApprovalToEoa)
+            .map(|w| w.log_index)
+            .collect()
+    }
+
+    #[test]
+    fn flags_reentry_into_a_contract_on_the_stack() {
+        // A -> B -> A
+        let tree = call("CALL", C, A, 0, vec![call("CALL", A, B, 0, vec![call("CALL", B, A, 0, Vec::new())])]);
+        assert_eq!(tree_warnings(&tree, &ContractRegistry::new()), vec![(WarningKind::Reentrancy, vec![0, 0], false)]);
+
+        let tree = call("CALL", C, A, 0, vec![call("CALL", A, B, 0, vec![call("STATICCALL", B, A, 0, Vec::new())])]);
+        let warnings = analyze(Some(&tree), &[], &ContractRegistry::new(), &HashMap::new(), &BTreeSet::new());
+        assert_eq!((warnings[0].kind, warnings[0].severity), (WarningKind::ReadOnlyReentrancy, Severity::Low));
+    }
+
+    #[test]
+    fn ignores_direct_self_calls() {
+        // A -> A (e.g. `this.multicall`), then A -> B -> B
+        let tree = call("CALL", C, A, 0, vec![
+            call("CALL", A, A, 0, Vec::new()),
+            call("CALL", A, B, 0, vec![call("CALL", B, B, 0, Vec::new())]),
+        ]);
+        assert!(tree_warnings(&tree, &ContractRegistry::new()).is_empty());
+    }
+
+    #[test]
+    fn marks_reentry_under_a_failed_frame_as_reverted() {
+        let mut inner = call("CALL", A, B, 0, vec![call("CALL", B, A, 0, Vec::new())]);
+        inner.error = Some("execution reverted".to_string());
+        let tree = call("CALL", C, A, 0, vec![inner]);
+        assert_eq!(tree_warnings(&tree, &ContractRegistry::new()), vec![(WarningKind::Reentrancy, vec![0, 0], true)]);
+    }
+
+    #[test]
+    fn flags_delegatecall_to_unknown_code_only() {
+        let mut registry = ContractRegistry::new();
+        registry.register_proxy(A, ProxyInfo {
+            kind: ProxyKind::Eip1967,
+            implementation: Some(B),
+            beacon: None,
+            admin: None,
+            facets: Default::default(),
+        });
+
+        let tree = call("CALL", C, A, 0, vec![call("DELEGATECALL", A, B, 0, Vec::new())]);
+        assert!(tree_warnings(&tree, &registry).is_empty());
+
+        let tree = call("CALL", C, A, 0, vec![call("DELEGATECALL", A, C, 0, Vec::new())]);
+        assert_eq!(tree_warnings(&tree, &registry), vec![(WarningKind::UnknownDelegatecall, vec![0], false)]);
+    }
+
+    #[test]
+    fn flags_value_sent_to_a_contract_created_earlier() {
+        let tree = call("CALL", C, A, 0, vec![
+            call("CREATE", A, B, 0, Vec::new()),
+            call("CALL", A, B, 0, Vec::new()),
+            call("CALL", A, B, 5, Vec::new()),
+            call("CALL", A, C, 5, Vec::new()),
+        ]);
+        assert_eq!(tree_warnings(&tree, &ContractRegistry::new()), vec![(WarningKind::ValueToNewContract, vec![2], false)]);
+
+        // Value sent before the contract exists is an endowment of a plain address
+        let tree = call("CALL", C, A, 0, vec![call("CALL", A, B, 5, Vec::new()), call("CREATE2", A, B, 0, Vec::new())]);
+        assert!(tree_warnings(&tree, &ContractRegistry::new()).is_empty());
+    }
+
+    #[test]
+    fn flags_unlimited_approvals_from_uint96_max() {
+        let uint96_max = (U256::one() << 96) - 1;
+        let logs = vec![approval(B, uint96_max - 1), approval(B, uint96_max), approval(B, U256::MAX)];
+        assert_eq!(approval_warnings(&logs, None, &HashMap::new(), &BTreeSet::new()), vec![Some(1), Some(2)]);
+        assert_eq!(approval_spenders(&logs).into_iter().collect::<Vec<_>>(), vec![B]);
+    }
+
+    #[test]
+    fn skips_approvals_to_contracts_and_unknown_spenders() {
+        let logs = vec![approval(A, U256::MAX), approval(B, U256::MAX), approval(C, U256::MAX)];
+        let codes = HashMap::from([(A, Bytes::from(vec![0x00]))]);
+        let unknown = BTreeSet::from([B]);
+        let tree = call("CALL", A, B, 0, vec![call("CREATE", B, C, 0, Vec::new())]);
+        assert!(approval_warnings(&logs, Some(&tree), &codes, &unknown).is_empty());
+        assert_eq!(approval_warnings(&logs, None, &codes, &unknown), vec![Some(2)]);
+    }
+}
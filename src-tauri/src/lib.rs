@@ -1,8 +1,19 @@
 // Modules
 pub mod types;
 pub mod core;
-use crate::core::simulator_debug::trace_transaction;
+pub mod state;
+use crate::core::address_book::{AddressBook, AddressBookFormat};
+use crate::core::artifacts::ProjectArtifacts;
+use crate::core::export::{write_export, ExportFormat};
+use crate::core::foundry_repro::write_foundry_test;
+use crate::core::simulator_debug::{trace_transaction, TraceContext};
+use crate::core::trace_diff::diff_traces;
+use crate::state::AppState;
+use crate::types::{AddressLabel, ReproOptions, TraceOptions, TraceSource};
 use ethers::types::TxHash;
+use std::path::Path;
+use std::sync::Arc;
+use tauri::Manager;
 
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
@@ -25,11 +36,64 @@ fn path() -> String {
     format!("Binaries path: {:?}, Located: {:?}", path, located)
 }
 
+#[tauri::command]
+fn link_project(path: String, state: tauri::State<'_, AppState>) -> Result<String, String> {
+    println!("Link project called with path: {}", path);
+
+    let project = ProjectArtifacts::scan(Path::new(&path))
+        .map_err(|e| format!("Failed to load project artifacts: {}", e))?;
+    let summary = project.summary();
+
+    *state.project.lock().map_err(|e| e.to_string())? = Some(Arc::new(project));
+    serde_json::to_string_pretty(&summary).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn unlink_project(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    *state.project.lock().map_err(|e| e.to_string())? = None;
+    Ok(())
+}
+
+#[tauri::command]
+fn get_address_book(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    let book = state.address_book.lock().map_err(|e| e.to_string())?;
+    serde_json::to_string_pretty(book.labels()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_address_label(label: AddressLabel, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let mut book = state.address_book.lock().map_err(|e| e.to_string())?;
+    book.set(label).map_err(|e| format!("Failed to save label: {}", e))
+}
+
+#[tauri::command]
+fn remove_address_label(address: String, chain_id: Option<u64>, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let mut book = state.address_book.lock().map_err(|e| e.to_string())?;
+    book.remove(&address, chain_id).map_err(|e| format!("Failed to remove label: {}", e))
+}
+
+#[tauri::command]
+fn import_address_book(content: String, format: String, state: tauri::State<'_, AppState>) -> Result<usize, String> {
+    let format = AddressBookFormat::parse(&format).map_err(|e| e.to_string())?;
+    let mut book = state.address_book.lock().map_err(|e| e.to_string())?;
+    book.import(&content, format).map_err(|e| format!("Failed to import address book: {}", e))
+}
+
+#[tauri::command]
+fn export_address_book(format: String, state: tauri::State<'_, AppState>) -> Result<String, String> {
+    let format = AddressBookFormat::parse(&format).map_err(|e| e.to_string())?;
+    let book = state.address_book.lock().map_err(|e| e.to_string())?;
+    book.export(format).map_err(|e| format!("Failed to export address book: {}", e))
+}
+
 #[tauri::command]
 async fn debug_transaction(
     tx_hash: String,
     rpc_url: String,
     block: u64,
+    options: Option<TraceOptions>,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
 ) -> Result<String, String> {
     println!("Debug transaction called with tx_hash: {}, rpc_url: {}, block: {}", tx_hash, rpc_url, block);
     
@@ -37,7 +101,9 @@ async fn debug_transaction(
     let tx: TxHash = tx_hash.parse()
         .map_err(|e| format!("Invalid transaction hash: {}", e))?;
     
-    let result = trace_transaction(tx, &rpc_url, block).await;
+    let context = trace_context(options, &app, &state)?;
+
+    let result = trace_transaction(tx, &rpc_url, block, &context).await;
     match result {
         Ok(trace) => {
             println!("Successfully traced transaction");
@@ -50,11 +116,114 @@ async fn debug_transaction(
     }
 }
 
+fn trace_context(
+    options: Option<TraceOptions>,
+    app: &tauri::AppHandle,
+    state: &tauri::State<'_, AppState>,
+) -> Result<TraceContext, String> {
+    Ok(TraceContext {
+        project: state.project.lock().map_err(|e| e.to_string())?.clone(),
+        options: options.unwrap_or_default(),
+        cache_dir: app.path().app_cache_dir().ok(),
+        address_book: state.address_book.lock().map_err(|e| e.to_string())?.clone(),
+    })
+}
+
+/// Trace JSON for one side of a comparison
+async fn resolve_trace_source(
+    source: TraceSource,
+    app: &tauri::AppHandle,
+    state: &tauri::State<'_, AppState>,
+) -> Result<serde_json::Value, String> {
+    match source {
+        TraceSource::Saved { trace } => serde_json::from_str(&trace)
+            .map_err(|e| format!("Invalid saved trace: {}", e)),
+        TraceSource::Transaction { tx_hash, rpc_url, block, options } => {
+            let tx: TxHash = tx_hash.parse()
+                .map_err(|e| format!("Invalid transaction hash: {}", e))?;
+            let context = trace_context(options, app, state)?;
+            trace_transaction(tx, &rpc_url, block, &context).await
+                .map_err(|e| format!("Error tracing transaction {}: {:?}", tx_hash, e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn diff_transactions(
+    left: TraceSource,
+    right: TraceSource,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<String, String> {
+    let left = resolve_trace_source(left, &app, &state).await?;
+    let right = resolve_trace_source(right, &app, &state).await?;
+    let diff = diff_traces(&left, &right);
+    serde_json::to_string_pretty(&diff).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn export_trace(
+    source: TraceSource,
+    format: String,
+    directory: String,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    let format = ExportFormat::parse(&format).map_err(|e| e.to_string())?;
+    let trace = resolve_trace_source(source, &app, &state).await?;
+    let written = write_export(&trace, format, Path::new(&directory))
+        .map_err(|e| format!("Failed to export trace: {}", e))?;
+    Ok(written.iter().map(|path| path.display().to_string()).collect())
+}
+
+#[tauri::command]
+async fn export_foundry_test(
+    source: TraceSource,
+    directory: String,
+    options: Option<ReproOptions>,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<String, String> {
+    let trace = resolve_trace_source(source, &app, &state).await?;
+    let path = write_foundry_test(&trace, &options.unwrap_or_default(), Path::new(&directory))
+        .map_err(|e| format!("Failed to generate Foundry test: {}", e))?;
+    Ok(path.display().to_string())
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![greet, add, path, debug_transaction])
+        .manage(AppState::default())
+        .setup(|app| {
+            if let Ok(dir) = app.path().app_data_dir() {
+                let path = dir.join("address_book.json");
+                let book = AddressBook::load(path.clone()).unwrap_or_else(|e| {
+                    println!("[LABELS] {}; labels will not be saved", e);
+                    AddressBook::unavailable(path, e.to_string())
+                });
+                if let Ok(mut state) = app.state::<AppState>().address_book.lock() {
+                    *state = book;
+                }
+            }
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            add,
+            path,
+            debug_transaction,
+            link_project,
+            unlink_project,
+            get_address_book,
+            set_address_label,
+            remove_address_label,
+            import_address_book,
+            export_address_book,
+            diff_transactions,
+            export_trace,
+            export_foundry_test
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
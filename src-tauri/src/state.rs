@@ -0,0 +1,12 @@
+use std::sync::{Arc, Mutex};
+use crate::core::address_book::AddressBook;
+use crate::core::artifacts::ProjectArtifacts;
+
+/// State shared between Tauri commands
+#[derive(Default)]
+pub struct AppState {
+    /// Foundry/Hardhat project linked by the user, if any
+    pub project: Mutex<Option<Arc<ProjectArtifacts>>>,
+    /// User-defined address labels, loaded from the app data directory at startup
+    pub address_book: Mutex<AddressBook>,
+}
@@ -79,6 +79,31 @@ pub struct NetworkConfig {
     pub rpc_url: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub block_explorer: Option<String>,
+    /// Etherscan-compatible API endpoint (Etherscan, Blockscout, Routescan...)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explorer_api_url: Option<String>,
+    #[serde(default)]
+    pub native_currency: NativeCurrency,
+}
+
+/// Native currency of a network (as in EIP-3085 `nativeCurrency`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NativeCurrency {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+impl NativeCurrency {
+    pub fn new(name: &str, symbol: &str, decimals: u8) -> Self {
+        Self { name: name.to_string(), symbol: symbol.to_string(), decimals }
+    }
+}
+
+impl Default for NativeCurrency {
+    fn default() -> Self {
+        Self::new("Ether", "ETH", 18)
+    }
 }
 
 /// Common networks configuration
@@ -92,6 +117,8 @@ pub fn get_networks() -> HashMap<String, NetworkConfig> {
             chain_id: 1,
             rpc_url: "https://eth.llamarpc.com".to_string(),
             block_explorer: Some("https://etherscan.io".to_string()),
+            explorer_api_url: Some("https://api.etherscan.io/v2/api?chainid=1".to_string()),
+            native_currency: NativeCurrency::default(),
         },
     );
     
@@ -102,6 +129,8 @@ pub fn get_networks() -> HashMap<String, NetworkConfig> {
             chain_id: 11155111,
             rpc_url: "https://ethereum-sepolia-rpc.publicnode.com".to_string(),
             block_explorer: Some("https://sepolia.etherscan.io".to_string()),
+            explorer_api_url: Some("https://api.etherscan.io/v2/api?chainid=11155111".to_string()),
+            native_currency: NativeCurrency::new("Sepolia Ether", "ETH", 18),
         },
     );
     
@@ -112,6 +141,8 @@ pub fn get_networks() -> HashMap<String, NetworkConfig> {
             chain_id: 137,
             rpc_url: "https://rpc.ankr.com/polygon".to_string(),
             block_explorer: Some("https://polygonscan.com".to_string()),
+            explorer_api_url: Some("https://api.etherscan.io/v2/api?chainid=137".to_string()),
+            native_currency: NativeCurrency::new("POL", "POL", 18),
         },
     );
     
@@ -122,6 +153,8 @@ pub fn get_networks() -> HashMap<String, NetworkConfig> {
             chain_id: 42161,
             rpc_url: "https://rpc.ankr.com/arbitrum".to_string(),
             block_explorer: Some("https://arbiscan.io".to_string()),
+            explorer_api_url: Some("https://api.etherscan.io/v2/api?chainid=42161".to_string()),
+            native_currency: NativeCurrency::default(),
         },
     );
     
@@ -132,12 +165,58 @@ pub fn get_networks() -> HashMap<String, NetworkConfig> {
             chain_id: 41455,
             rpc_url: "https://rpc.ankr.com/monad_testnet".to_string(),
             block_explorer: Some("https://testnet.monad.xyz".to_string()),
+            explorer_api_url: None,
+            native_currency: NativeCurrency::new("Monad", "MON", 18),
         },
     );
     
     networks
 }
 
+/// Find a known network by chain ID
+pub fn find_network(chain_id: u64) -> Option<NetworkConfig> {
+    get_networks().into_values().find(|network| network.chain_id == chain_id)
+}
+
+/// Optional settings sent by the UI along with a trace request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceOptions {
+    /// Etherscan-compatible API overriding the network default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explorer_api_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explorer_api_key: Option<String>,
+    /// Only use cached verified sources, never query the explorer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offline: Option<bool>,
+    /// Sourcify repository URL or local mirror directory (empty to disable)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sourcify_repository: Option<String>,
+    /// Maximum number of decimals shown for amounts (exact when unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_precision: Option<usize>,
+    /// Break gas down per opcode and suggest an access list, which re-executes the
+    /// transaction with a full step trace
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opcode_gas: Option<bool>,
+}
+
+/// Settings for the generated Foundry reproduction test
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReproOptions {
+    /// Environment variable holding the fork RPC URL (`ETH_RPC_URL` when unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_env: Option<String>,
+    /// Block to fork at (the block before the transaction's when unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fork_block: Option<u64>,
+    /// Also assert the events emitted by the transaction
+    #[serde(default)]
+    pub assert_events: bool,
+}
+
 /// ABI parameter definition
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AbiParam {
@@ -146,6 +225,10 @@ pub struct AbiParam {
     pub type_: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub indexed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub components: Option<Vec<AbiParam>>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "internalType")]
+    pub internal_type: Option<String>,
 }
 
 /// ABI entry for function/event definitions
@@ -170,12 +253,22 @@ pub enum AbiEntry {
         #[serde(skip_serializing_if = "Option::is_none")]
         name: Option<String>,
         inputs: Vec<AbiParam>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        anonymous: Option<bool>,
     },
     Error {
         #[serde(skip_serializing_if = "Option::is_none")]
         name: Option<String>,
         inputs: Vec<AbiParam>,
     },
+    Fallback {
+        #[serde(skip_serializing_if = "Option::is_none", rename = "stateMutability")]
+        state_mutability: Option<StateMutability>,
+    },
+    Receive {
+        #[serde(skip_serializing_if = "Option::is_none", rename = "stateMutability")]
+        state_mutability: Option<StateMutability>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -202,6 +295,122 @@ pub struct ContractInfo {
     pub source: Option<String>,
 }
 
+/// Verified contract fetched from an Etherscan-compatible explorer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifiedContract {
+    pub address: String,
+    pub verified: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contract_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compiler_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optimization_used: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub evm_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abi: Option<Vec<AbiEntry>>,
+    /// Source files keyed by path
+    #[serde(default)]
+    pub sources: std::collections::BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub implementation: Option<String>,
+    pub fetched_at: String, // ISO 8601 datetime string
+}
+
+impl VerifiedContract {
+    /// Whether a cached lookup is still valid: "not verified" answers expire after `ttl`,
+    /// since the contract may be verified in the meantime
+    pub fn is_fresh(&self, ttl: std::time::Duration) -> bool {
+        let fetched = chrono::DateTime::parse_from_rfc3339(&self.fetched_at);
+        self.verified || match (fetched, chrono::Duration::from_std(ttl)) {
+            (Ok(fetched), Ok(ttl)) => chrono::Utc::now().signed_duration_since(fetched) < ttl,
+            _ => false,
+        }
+    }
+
+    /// Source file declaring the verified contract, falling back to the first file
+    pub fn main_source(&self) -> Option<&String> {
+        let declaration = self.contract_name.as_ref().map(|name| format!("contract {}", name));
+        declaration
+            .and_then(|decl| self.sources.values().find(|code| code.contains(&decl)))
+            .or_else(|| self.sources.values().next())
+    }
+
+    pub fn to_contract_info(&self) -> ContractInfo {
+        ContractInfo {
+            address: self.address.clone(),
+            abi: self.abi.clone(),
+            name: self.contract_name.clone(),
+            verified: Some(self.verified),
+            source: self.main_source().cloned(),
+        }
+    }
+}
+
+/// One side of a trace comparison: a trace saved from `debug_transaction`, or a transaction to trace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TraceSource {
+    Saved {
+        trace: String,
+    },
+    Transaction {
+        #[serde(rename = "txHash")]
+        tx_hash: String,
+        #[serde(rename = "rpcUrl")]
+        rpc_url: String,
+        block: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        options: Option<TraceOptions>,
+    },
+}
+
+/// Where an address label comes from, in order of precedence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelSource {
+    User,
+    Bundled,
+    Ens,
+    Contract,
+}
+
+/// Human readable label attached to an address
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddressLabel {
+    pub address: String,
+    pub label: String,
+    /// Chain the label applies to, `None` for every chain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// CSS color used to highlight the address in the UI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default = "default_label_source")]
+    pub source: LabelSource,
+}
+
+fn default_label_source() -> LabelSource {
+    LabelSource::User
+}
+
+/// Token metadata read from the token contract at the traced block
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenInfo {
+    pub address: String,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+}
+
 /// Transaction trace information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -299,4 +508,47 @@ pub struct SimulatorDebugInfo {
     pub gas_estimate: U256,
     pub call_result: Result<Vec<u8>, String>, // Ok if successful, Err if revert
     pub trace: Value, 
-}
\ No newline at end of file
+}
+
+/// Decoded function parameter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecodedParam {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub value: serde_json::Value,
+}
+
+/// Decoded function call (input data matched against a known ABI)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecodedCall {
+    pub name: String,
+    pub signature: String,
+    pub selector: String,
+    pub args: Vec<DecodedParam>,
+    /// Calls packed inside the arguments (multicall, Safe multiSend, Universal Router...)
+    #[serde(rename = "virtualCalls", default, skip_serializing_if = "Vec::is_empty")]
+    pub virtual_calls: Vec<VirtualCall>,
+}
+
+/// Call unpacked from the arguments of a batching function
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualCall {
+    pub index: usize,
+    /// Contract the call is made on, when the batch format names one
+    pub target: Option<String>,
+    pub value: Option<String>,
+    /// CALL or DELEGATECALL (Safe transactions)
+    pub operation: Option<String>,
+    pub allow_failure: Option<bool>,
+    /// Command or step name for formats without function selectors
+    pub label: Option<String>,
+    pub data: String,
+    pub decoded: Option<DecodedCall>,
+}
+
+/// Serialize an optional address in the lowercase `0x…` form used throughout the trace
+pub fn serialize_address<S: serde::Serializer>(address: &Option<H160>, serializer: S) -> Result<S::Ok, S::Error> {
+    address.map(|a| format!("{:?}", a)).serialize(serializer)
+}